@@ -0,0 +1,486 @@
+//! HTTP Digest access authentication, see RFC 7616.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use http::header::{HeaderMap, HeaderValue, WWW_AUTHENTICATE};
+use md5::{Digest, Md5};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use url::Url;
+
+use crate::util::fast_random;
+
+/// Everything but the `attr-char`s of RFC 8187 is encoded in `username*`.
+const ATTR_CHAR_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'#')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'^')
+    .remove(b'_')
+    .remove(b'`')
+    .remove(b'|')
+    .remove(b'~');
+
+/// The username and password set with `RequestBuilder::digest_auth`.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Credentials {
+    username: String,
+    password: String,
+    // how often the request was sent again to answer a challenge
+    challenges_answered: u8,
+}
+
+/// The hash algorithms a server can ask for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum Algorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+/// A challenge from a `WWW-Authenticate: Digest` header, together with the
+/// number of times its nonce was used.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Challenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: Algorithm,
+    // `false` for servers that still follow RFC 2069 and send no `qop`
+    qop_auth: bool,
+    userhash: bool,
+    stale: bool,
+    // the URLs the challenge applies to, the whole origin if empty
+    domain: Vec<Url>,
+    nc: u32,
+}
+
+/// The challenges received by a client, by origin, so that later requests
+/// to the same protection space authenticate on the first try.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Cache {
+    origins: HashMap<String, Vec<Challenge>>,
+}
+
+impl Credentials {
+    pub(crate) fn new(username: String, password: String) -> Self {
+        Credentials {
+            username,
+            password,
+            challenges_answered: 0,
+        }
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .finish()
+    }
+}
+
+impl Cache {
+    /// Returns the `Authorization` header for a request to `url`, if a
+    /// challenge for its protection space was received before.
+    pub(crate) fn authorization(
+        &mut self,
+        credentials: &Credentials,
+        method: &str,
+        url: &Url,
+    ) -> Option<HeaderValue> {
+        let challenges = self.origins.get_mut(&origin(url))?;
+        let challenge = challenges.iter_mut().find(|c| c.applies_to(url))?;
+        challenge.nc += 1;
+        challenge.authorization(credentials, method, url)
+    }
+
+    /// Stores the digest challenges of a `401 Unauthorized` response to a
+    /// request to `url`.
+    ///
+    /// Returns the credentials to send the request again with, or `None` if
+    /// there was no usable challenge or the credentials were rejected.
+    pub(crate) fn challenge(
+        &mut self,
+        credentials: &Credentials,
+        url: &Url,
+        headers: &HeaderMap,
+    ) -> Option<Credentials> {
+        let challenge = headers
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| parse_challenges(value, url))
+            // prefer the strongest algorithm the server offers
+            .max_by_key(|c| matches!(c.algorithm, Algorithm::Sha256 | Algorithm::Sha256Sess))?;
+
+        // answer the first challenge, and a second one only if the nonce of
+        // the first one went stale in between
+        let allowed = match credentials.challenges_answered {
+            0 => true,
+            1 => challenge.stale,
+            _ => false,
+        };
+        let challenges = self.origins.entry(origin(url)).or_default();
+        challenges.retain(|c| c.realm != challenge.realm);
+        challenges.push(challenge);
+        if !allowed {
+            return None;
+        }
+        let mut credentials = credentials.clone();
+        credentials.challenges_answered += 1;
+        Some(credentials)
+    }
+}
+
+impl Challenge {
+    fn applies_to(&self, url: &Url) -> bool {
+        self.domain.is_empty()
+            || self
+                .domain
+                .iter()
+                .any(|d| origin(d) == origin(url) && url.path().starts_with(d.path()))
+    }
+
+    fn hash(&self, data: &str) -> String {
+        match self.algorithm {
+            Algorithm::Md5 | Algorithm::Md5Sess => format!("{:x}", Md5::digest(data.as_bytes())),
+            Algorithm::Sha256 | Algorithm::Sha256Sess => {
+                format!("{:x}", Sha256::digest(data.as_bytes()))
+            }
+        }
+    }
+
+    fn authorization(
+        &self,
+        credentials: &Credentials,
+        method: &str,
+        url: &Url,
+    ) -> Option<HeaderValue> {
+        let cnonce = format!("{:016x}", fast_random());
+        self.authorization_with_cnonce(credentials, method, url, &cnonce)
+    }
+
+    fn authorization_with_cnonce(
+        &self,
+        credentials: &Credentials,
+        method: &str,
+        url: &Url,
+        cnonce: &str,
+    ) -> Option<HeaderValue> {
+        let uri = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let nc = format!("{:08x}", self.nc);
+
+        let mut ha1 = self.hash(&format!(
+            "{}:{}:{}",
+            credentials.username, self.realm, credentials.password
+        ));
+        if let Algorithm::Md5Sess | Algorithm::Sha256Sess = self.algorithm {
+            ha1 = self.hash(&format!("{}:{}:{}", ha1, self.nonce, cnonce));
+        }
+        let ha2 = self.hash(&format!("{}:{}", method, uri));
+        let response = if self.qop_auth {
+            self.hash(&format!(
+                "{}:{}:{}:{}:auth:{}",
+                ha1, self.nonce, nc, cnonce, ha2
+            ))
+        } else {
+            self.hash(&format!("{}:{}:{}", ha1, self.nonce, ha2))
+        };
+
+        let username = if self.userhash {
+            format!(
+                "username=\"{}\"",
+                self.hash(&format!("{}:{}", credentials.username, self.realm))
+            )
+        } else if can_quote(&credentials.username) {
+            format!("username=\"{}\"", quote(&credentials.username))
+        } else {
+            // RFC 7616, section 3.4.4
+            format!(
+                "username*=UTF-8''{}",
+                utf8_percent_encode(&credentials.username, ATTR_CHAR_ENCODE)
+            )
+        };
+        let mut value = format!(
+            "Digest {}, realm=\"{}\", uri=\"{}\", algorithm={}, nonce=\"{}\"",
+            username,
+            quote(&self.realm),
+            quote(&uri),
+            self.algorithm.as_str(),
+            quote(&self.nonce),
+        );
+        if self.qop_auth {
+            value.push_str(&format!(", nc={}, cnonce=\"{}\", qop=auth", nc, cnonce));
+        }
+        value.push_str(&format!(", response=\"{}\"", response));
+        if let Some(ref opaque) = self.opaque {
+            value.push_str(&format!(", opaque=\"{}\"", quote(opaque)));
+        }
+        if self.userhash {
+            value.push_str(", userhash=true");
+        }
+        let mut value = HeaderValue::from_str(&value).ok()?;
+        value.set_sensitive(true);
+        Some(value)
+    }
+}
+
+impl Algorithm {
+    fn parse(s: &str) -> Option<Algorithm> {
+        match s.to_ascii_uppercase().as_str() {
+            "MD5" => Some(Algorithm::Md5),
+            "MD5-SESS" => Some(Algorithm::Md5Sess),
+            "SHA-256" => Some(Algorithm::Sha256),
+            "SHA-256-SESS" => Some(Algorithm::Sha256Sess),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::Md5 => "MD5",
+            Algorithm::Md5Sess => "MD5-sess",
+            Algorithm::Sha256 => "SHA-256",
+            Algorithm::Sha256Sess => "SHA-256-sess",
+        }
+    }
+}
+
+fn origin(url: &Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+/// Returns true if `s` can be sent as a quoted string.
+fn can_quote(s: &str) -> bool {
+    s.chars()
+        .all(|c| c == ' ' || c == '\t' || c.is_ascii_graphic())
+}
+
+fn quote(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Parses the supported digest challenges out of a `WWW-Authenticate`
+/// header value, which may hold several challenges of different schemes.
+fn parse_challenges(value: &str, url: &Url) -> Vec<Challenge> {
+    let mut challenges = Vec::new();
+    let mut current: Option<(String, HashMap<String, String>)> = None;
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        let token_end = rest
+            .find(|c: char| c == '=' || c == ',' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let token = &rest[..token_end];
+        rest = rest[token_end..].trim_start();
+        if let Some(after_eq) = rest.strip_prefix('=') {
+            // an auth-param of the current challenge
+            let (param, after) = parse_param_value(after_eq.trim_start());
+            if let Some((_, ref mut params)) = current {
+                params.insert(token.to_ascii_lowercase(), param);
+            }
+            rest = after;
+        } else if !token.is_empty() {
+            // a new challenge starts with its scheme
+            challenges.extend(current.take());
+            current = Some((token.to_string(), HashMap::new()));
+        }
+        rest = rest.trim_start().trim_start_matches(',').trim_start();
+    }
+    challenges.extend(current);
+
+    challenges
+        .into_iter()
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("Digest"))
+        .filter_map(|(_, params)| Challenge::from_params(params, url))
+        .collect()
+}
+
+/// Parses a token or quoted string, returning it and the rest of the input.
+fn parse_param_value(s: &str) -> (String, &str) {
+    if let Some(quoted) = s.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some((_, escaped)) = chars.next() {
+                        value.push(escaped);
+                    }
+                }
+                '"' => return (value, &quoted[i + 1..]),
+                c => value.push(c),
+            }
+        }
+        (value, "")
+    } else {
+        let end = s
+            .find(|c: char| c == ',' || c.is_whitespace())
+            .unwrap_or(s.len());
+        (s[..end].to_string(), &s[end..])
+    }
+}
+
+impl Challenge {
+    fn from_params(mut params: HashMap<String, String>, url: &Url) -> Option<Challenge> {
+        let algorithm = match params.get("algorithm") {
+            Some(algorithm) => Algorithm::parse(algorithm)?,
+            None => Algorithm::Md5,
+        };
+        let qop_auth = match params.get("qop") {
+            Some(qop) => {
+                if !qop
+                    .split(',')
+                    .any(|qop| qop.trim().eq_ignore_ascii_case("auth"))
+                {
+                    // only auth-int is offered, which isn't supported
+                    return None;
+                }
+                true
+            }
+            None => false,
+        };
+        let domain = params
+            .get("domain")
+            .map(|domain| {
+                domain
+                    .split_whitespace()
+                    .filter_map(|d| url.join(d).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(Challenge {
+            realm: params.remove("realm").unwrap_or_default(),
+            nonce: params.remove("nonce")?,
+            opaque: params.remove("opaque"),
+            algorithm,
+            qop_auth,
+            userhash: params
+                .get("userhash")
+                .map_or(false, |v| v.eq_ignore_ascii_case("true")),
+            stale: params
+                .get("stale")
+                .map_or(false, |v| v.eq_ignore_ascii_case("true")),
+            domain,
+            nc: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[lunatic::test]
+    fn test_parse_challenges() {
+        let url = Url::parse("http://example.com/dir/index.html").unwrap();
+        let challenges = parse_challenges(
+            "Basic realm=\"basic\", Digest realm=\"http-auth@example.org\", \
+             qop=\"auth, auth-int\", algorithm=SHA-256, \
+             nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+             opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\", domain=\"/dir/\"",
+            &url,
+        );
+        assert_eq!(challenges.len(), 1);
+        let challenge = &challenges[0];
+        assert_eq!(challenge.realm, "http-auth@example.org");
+        assert_eq!(challenge.algorithm, Algorithm::Sha256);
+        assert!(challenge.qop_auth);
+        assert!(challenge.applies_to(&url));
+        assert!(!challenge.applies_to(&Url::parse("http://example.com/other").unwrap()));
+    }
+
+    #[lunatic::test]
+    fn test_response_rfc_7616_example() {
+        // the SHA-256 example of RFC 7616, section 3.9.1
+        let url = Url::parse("http://www.example.org/dir/index.html").unwrap();
+        let mut challenge = parse_challenges(
+            "Digest realm=\"http-auth@example.org\", qop=\"auth, auth-int\", \
+             algorithm=SHA-256, nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+             opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"",
+            &url,
+        )
+        .remove(0);
+        challenge.nc = 1;
+        let credentials = Credentials::new("Mufasa".to_string(), "Circle of Life".to_string());
+
+        let ha1 = challenge.hash("Mufasa:http-auth@example.org:Circle of Life");
+        let ha2 = challenge.hash("GET:/dir/index.html");
+        let expected = challenge.hash(&format!(
+            "{}:7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v:00000001:f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ:auth:{}",
+            ha1, ha2
+        ));
+        assert_eq!(
+            expected,
+            "753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1"
+        );
+
+        let value = challenge
+            .authorization_with_cnonce(
+                &credentials,
+                "GET",
+                &url,
+                "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ",
+            )
+            .unwrap();
+        assert_eq!(
+            value.to_str().unwrap(),
+            "Digest username=\"Mufasa\", realm=\"http-auth@example.org\", \
+             uri=\"/dir/index.html\", algorithm=SHA-256, \
+             nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", nc=00000001, \
+             cnonce=\"f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ\", qop=auth, \
+             response=\"753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1\", \
+             opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\""
+        );
+    }
+
+    #[lunatic::test]
+    fn test_non_ascii_username() {
+        // the username of the example of RFC 7616, section 3.9.2
+        let url = Url::parse("http://api.example.org/doe.json").unwrap();
+        let mut challenge = parse_challenges(
+            "Digest realm=\"api@example.org\", qop=\"auth\", algorithm=SHA-256, \
+             nonce=\"5TsQWLVdgBdmrQ0XsxbDODV+57QdFR34I9HAbC/RVvkK\"",
+            &url,
+        )
+        .remove(0);
+        challenge.nc = 1;
+        let credentials = Credentials::new("J\u{e4}s\u{f8}n Doe".to_string(), "Secret".to_string());
+
+        let value = challenge
+            .authorization_with_cnonce(
+                &credentials,
+                "GET",
+                &url,
+                "NTg6RKcb9boFIAS3KrFK9BGeh+iDa/sm6jUMp2wds69v",
+            )
+            .unwrap();
+        assert!(value
+            .to_str()
+            .unwrap()
+            .starts_with("Digest username*=UTF-8''J%C3%A4s%C3%B8n%20Doe, realm="));
+
+        challenge.userhash = true;
+        let value = challenge
+            .authorization_with_cnonce(
+                &credentials,
+                "GET",
+                &url,
+                "NTg6RKcb9boFIAS3KrFK9BGeh+iDa/sm6jUMp2wds69v",
+            )
+            .unwrap();
+        assert!(value.to_str().unwrap().starts_with(
+            "Digest username=\"5a1a8a47df5c298551b9b42ba9b05835174a5bd7d511ff7fe9191d8e946fc4e7\""
+        ));
+    }
+}
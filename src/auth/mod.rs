@@ -0,0 +1,28 @@
+//! Authentication
+//!
+//! Besides sending the `Authorization` headers set with
+//! `RequestBuilder::basic_auth()` and `RequestBuilder::bearer_auth()`, a
+//! `Client` can answer authentication challenges of servers on its own:
+//!
+//! - `RequestBuilder::digest_auth()` answers `WWW-Authenticate: Digest`
+//!   challenges as described in RFC 7616. The challenge is remembered, so
+//!   that later requests to the same protection space are authenticated
+//!   on the first try.
+//! - An [`OAuth2`] token source set with `ClientBuilder::oauth2()` fetches
+//!   access tokens and sends them as bearer tokens, renewing them when they
+//!   expire or get rejected.
+//! - `RequestBuilder::aws_sigv4()` signs requests with AWS Signature
+//!   Version 4, using the credentials and scope of a [`SigV4`].
+//! - `RequestBuilder::message_signature()` signs requests with HTTP Message
+//!   Signatures as described in RFC 9421, and a [`SignatureVerifier`]
+//!   checks the signatures of responses with
+//!   `HttpResponse::verify_signature()`.
+
+pub(crate) mod digest;
+pub(crate) mod oauth2;
+pub(crate) mod signature;
+pub(crate) mod sigv4;
+
+pub use self::oauth2::OAuth2;
+pub use self::signature::{MessageSigner, SignatureKey, SignatureVerifier};
+pub use self::sigv4::SigV4;
@@ -0,0 +1,365 @@
+//! OAuth 2.0 access tokens for the requests of a `Client`, see RFC 6749.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Url;
+
+/// A source of OAuth 2.0 access tokens, set with `ClientBuilder::oauth2()`.
+///
+/// The client fetches an access token from the token endpoint before its
+/// first request to one of the resources of the token, and sends it as a
+/// bearer token with every such request that has no `Authorization` header
+/// of its own. Unless resources are added with `OAuth2::resource()`, the
+/// token is only sent to the origin of the token endpoint. The token is kept
+/// until shortly before it expires, and renewed with its refresh token if
+/// the server issued one. If a request is rejected with `401 Unauthorized`,
+/// the token is renewed once and the request sent again.
+///
+/// The client authenticates to the token endpoint with HTTP basic
+/// authentication. Building the client fails if the token endpoint or a
+/// resource uses `http`, unless `OAuth2::allow_http()` is set.
+///
+/// # Example
+///
+/// ```rust
+/// # fn run() -> Result<(), nightfly::Error> {
+/// use nightfly::auth::OAuth2;
+///
+/// let oauth2 = OAuth2::client_credentials("https://auth.example.com/token", "id", "secret")
+///     .scope("read")
+///     .scope("write")
+///     .resource("https://api.example.com/v1/");
+/// let client = nightfly::Client::builder().oauth2(oauth2).build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OAuth2 {
+    token_url: String,
+    client_id: String,
+    client_secret: String,
+    scopes: Vec<String>,
+    resources: Vec<String>,
+    allow_http: bool,
+    refresh_token: Option<String>,
+    refresh_margin: Duration,
+}
+
+/// The access token of a client, and how to renew it.
+#[derive(Clone, Debug)]
+pub(crate) struct TokenSource {
+    config: OAuth2,
+    token_url: Url,
+    // the URLs the token is sent to, along with any URL below them
+    resources: Vec<Url>,
+    token: Option<Token>,
+    refresh_token: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    access_token: String,
+    // `None` if the server didn't say when the token expires
+    renew_at: Option<Instant>,
+}
+
+/// A successful response of the token endpoint, see RFC 6749, section 5.1.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+impl OAuth2 {
+    /// Fetch access tokens with the client credentials grant.
+    pub fn client_credentials<U, I, S>(token_url: U, client_id: I, client_secret: S) -> OAuth2
+    where
+        U: Into<String>,
+        I: Into<String>,
+        S: Into<String>,
+    {
+        OAuth2 {
+            token_url: token_url.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            scopes: Vec::new(),
+            resources: Vec::new(),
+            allow_http: false,
+            refresh_token: None,
+            refresh_margin: Duration::from_secs(30),
+        }
+    }
+
+    /// Fetch access tokens with the refresh token grant, starting with
+    /// `refresh_token`.
+    ///
+    /// If the token endpoint issues a new refresh token, it replaces the
+    /// previous one.
+    pub fn refresh_token<U, I, S, R>(
+        token_url: U,
+        client_id: I,
+        client_secret: S,
+        refresh_token: R,
+    ) -> OAuth2
+    where
+        U: Into<String>,
+        I: Into<String>,
+        S: Into<String>,
+        R: Into<String>,
+    {
+        let mut oauth2 = OAuth2::client_credentials(token_url, client_id, client_secret);
+        oauth2.refresh_token = Some(refresh_token.into());
+        oauth2
+    }
+
+    /// Add a scope to request access tokens for.
+    pub fn scope<S: Into<String>>(mut self, scope: S) -> OAuth2 {
+        self.scopes.push(scope.into());
+        self
+    }
+
+    /// Add a resource to send access tokens to.
+    ///
+    /// Tokens are sent with requests to the origin of `url` whose path
+    /// starts with the path of `url`, so that `https://api.example.com`
+    /// covers the whole origin and `https://api.example.com/v1/` only the
+    /// paths below `/v1/`. Without resources, tokens are sent to the origin
+    /// of the token endpoint.
+    pub fn resource<U: Into<String>>(mut self, url: U) -> OAuth2 {
+        self.resources.push(url.into());
+        self
+    }
+
+    /// Allow the token endpoint and the resources to use `http`.
+    ///
+    /// Client secrets and access tokens sent over `http` can be read by
+    /// anyone on the network, so this should only be set for testing.
+    ///
+    /// Default is `false`.
+    pub fn allow_http(mut self, allow: bool) -> OAuth2 {
+        self.allow_http = allow;
+        self
+    }
+
+    /// Set how long before it expires an access token is renewed.
+    ///
+    /// Default is 30 seconds.
+    pub fn refresh_margin(mut self, margin: Duration) -> OAuth2 {
+        self.refresh_margin = margin;
+        self
+    }
+
+    pub(crate) fn token_url(&self) -> &str {
+        &self.token_url
+    }
+}
+
+impl fmt::Debug for OAuth2 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OAuth2")
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .field("scopes", &self.scopes)
+            .field("resources", &self.resources)
+            .finish()
+    }
+}
+
+impl TokenSource {
+    pub(crate) fn new(config: OAuth2) -> crate::Result<Self> {
+        let token_url = Url::parse(&config.token_url).map_err(crate::error::builder)?;
+        let resources = if config.resources.is_empty() {
+            let mut origin = token_url.clone();
+            origin.set_path("/");
+            origin.set_query(None);
+            origin.set_fragment(None);
+            vec![origin]
+        } else {
+            config
+                .resources
+                .iter()
+                .map(|url| Url::parse(url).map_err(crate::error::builder))
+                .collect::<crate::Result<_>>()?
+        };
+        for url in std::iter::once(&token_url).chain(&resources) {
+            if url.scheme() != "https" && !(url.scheme() == "http" && config.allow_http) {
+                return Err(crate::error::builder(format!(
+                    "OAuth 2.0 over {} needs OAuth2::allow_http(): {}",
+                    url.scheme(),
+                    url
+                )));
+            }
+        }
+        Ok(TokenSource {
+            refresh_token: config.refresh_token.clone(),
+            config,
+            token_url,
+            resources,
+            token: None,
+        })
+    }
+
+    /// Returns true if the access token is sent with requests to `url`.
+    pub(crate) fn covers(&self, url: &Url) -> bool {
+        self.resources.iter().any(|resource| {
+            resource.origin() == url.origin() && url.path().starts_with(resource.path())
+        })
+    }
+
+    /// Returns the cached access token, unless it's about to expire.
+    pub(crate) fn cached(&self) -> Option<String> {
+        self.token
+            .as_ref()
+            .filter(|token| token.renew_at.map_or(true, |at| Instant::now() < at))
+            .map(|token| token.access_token.clone())
+    }
+
+    /// Drops the access token, so that the next request renews it.
+    pub(crate) fn invalidate(&mut self) {
+        self.token = None;
+    }
+
+    /// Returns the token endpoint and the form to request a new access
+    /// token with.
+    pub(crate) fn token_request(&self) -> (Url, Vec<(&'static str, String)>) {
+        let mut form = match self.refresh_token {
+            Some(ref refresh_token) => vec![
+                ("grant_type", "refresh_token".to_string()),
+                ("refresh_token", refresh_token.clone()),
+            ],
+            None => vec![("grant_type", "client_credentials".to_string())],
+        };
+        if !self.config.scopes.is_empty() {
+            form.push(("scope", self.config.scopes.join(" ")));
+        }
+        (self.token_url.clone(), form)
+    }
+
+    /// Returns the credentials for HTTP basic authentication to the token
+    /// endpoint, which are form-urlencoded first, see RFC 6749, section
+    /// 2.3.1.
+    pub(crate) fn basic_credentials(&self) -> String {
+        let encode =
+            |s: &str| url::form_urlencoded::byte_serialize(s.as_bytes()).collect::<String>();
+        let credentials = format!(
+            "{}:{}",
+            encode(&self.config.client_id),
+            encode(&self.config.client_secret)
+        );
+        format!("Basic {}", base64::encode(credentials))
+    }
+
+    /// Stores the access token from a response of the token endpoint and
+    /// returns it.
+    pub(crate) fn store(&mut self, body: &[u8]) -> crate::Result<String> {
+        let res: TokenResponse = serde_json::from_slice(body).map_err(crate::error::decode)?;
+        if !res.token_type.eq_ignore_ascii_case("bearer") {
+            return Err(crate::error::decode(format!(
+                "unsupported token type {:?}",
+                res.token_type
+            )));
+        }
+        let margin = self.config.refresh_margin;
+        let renew_at = res.expires_in.map(|expires_in| {
+            let expires_in = Duration::from_secs(expires_in);
+            Instant::now() + expires_in.checked_sub(margin).unwrap_or_default()
+        });
+        if res.refresh_token.is_some() {
+            self.refresh_token = res.refresh_token;
+        }
+        self.token = Some(Token {
+            access_token: res.access_token.clone(),
+            renew_at,
+        });
+        Ok(res.access_token)
+    }
+
+    /// Forgets the refresh token after the token endpoint rejected it, so
+    /// that the client credentials grant is used next.
+    pub(crate) fn refresh_token_rejected(&mut self) -> bool {
+        self.refresh_token.take().is_some()
+    }
+}
+
+#[lunatic::test]
+fn test_token_source_caches_until_margin() {
+    let mut source = TokenSource::new(
+        OAuth2::client_credentials("https://localhost/token", "id", "secret")
+            .scope("a")
+            .scope("b")
+            .refresh_margin(Duration::from_secs(60)),
+    )
+    .unwrap();
+    let (_, form) = source.token_request();
+    assert_eq!(
+        form,
+        vec![
+            ("grant_type", "client_credentials".to_string()),
+            ("scope", "a b".to_string())
+        ]
+    );
+
+    let token = source
+        .store(br#"{"access_token":"t1","token_type":"Bearer","expires_in":3600,"refresh_token":"r1"}"#)
+        .unwrap();
+    assert_eq!(token, "t1");
+    assert_eq!(source.cached(), Some("t1".to_string()));
+    assert_eq!(
+        source.token_request().1[1],
+        ("refresh_token", "r1".to_string())
+    );
+
+    // a token that expires within the margin is renewed right away
+    source
+        .store(br#"{"access_token":"t2","token_type":"bearer","expires_in":30}"#)
+        .unwrap();
+    assert_eq!(source.cached(), None);
+    assert!(source
+        .store(br#"{"access_token":"t3","token_type":"mac"}"#)
+        .is_err());
+}
+
+#[lunatic::test]
+fn test_token_source_scope() {
+    let source = TokenSource::new(OAuth2::client_credentials(
+        "https://auth.example.com/oauth/token",
+        "id",
+        "secret",
+    ))
+    .unwrap();
+    assert!(source.covers(&Url::parse("https://auth.example.com/userinfo").unwrap()));
+    assert!(!source.covers(&Url::parse("http://auth.example.com/userinfo").unwrap()));
+    assert!(!source.covers(&Url::parse("https://api.example.com/").unwrap()));
+
+    let source = TokenSource::new(
+        OAuth2::client_credentials("https://auth.example.com/token", "id", "secret")
+            .resource("https://api.example.com/v1/"),
+    )
+    .unwrap();
+    assert!(source.covers(&Url::parse("https://api.example.com/v1/items?a=b").unwrap()));
+    assert!(!source.covers(&Url::parse("https://api.example.com/v2/items").unwrap()));
+    assert!(!source.covers(&Url::parse("https://auth.example.com/userinfo").unwrap()));
+
+    let http = OAuth2::client_credentials("http://auth.example.com/token", "id", "secret");
+    assert!(TokenSource::new(http.clone()).unwrap_err().is_builder());
+    assert!(TokenSource::new(http.allow_http(true)).is_ok());
+}
+
+#[lunatic::test]
+fn test_token_source_basic_credentials() {
+    let source = TokenSource::new(OAuth2::client_credentials(
+        "https://localhost/token",
+        "my client",
+        "s3cr:t&",
+    ))
+    .unwrap();
+    assert_eq!(
+        source.basic_credentials(),
+        format!("Basic {}", base64::encode("my+client:s3cr%3At%26"))
+    );
+}
@@ -0,0 +1,725 @@
+//! HTTP Message Signatures, see RFC 9421.
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ed25519_dalek::Signer;
+use hmac::{Hmac, Mac};
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use url::Url;
+
+use crate::error::{self, BoxError};
+
+const CONTENT_DIGEST: &str = "content-digest";
+
+/// A key to sign HTTP messages with or verify their signatures.
+///
+/// The key id is sent as the `keyid` parameter of signatures, and must
+/// match the one of a signature to verify it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SignatureKey {
+    key_id: String,
+    key: Key,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+enum Key {
+    HmacSha256(Vec<u8>),
+    Ed25519([u8; 32]),
+    Ed25519Public([u8; 32]),
+}
+
+impl SignatureKey {
+    /// A shared secret for `hmac-sha256` signatures.
+    pub fn hmac_sha256<I, S>(key_id: I, secret: S) -> SignatureKey
+    where
+        I: Into<String>,
+        S: Into<Vec<u8>>,
+    {
+        SignatureKey {
+            key_id: key_id.into(),
+            key: Key::HmacSha256(secret.into()),
+        }
+    }
+
+    /// An `ed25519` private key, which can both sign and verify.
+    pub fn ed25519<I: Into<String>>(key_id: I, private_key: [u8; 32]) -> SignatureKey {
+        SignatureKey {
+            key_id: key_id.into(),
+            key: Key::Ed25519(private_key),
+        }
+    }
+
+    /// An `ed25519` public key, which can only verify.
+    pub fn ed25519_public<I: Into<String>>(key_id: I, public_key: [u8; 32]) -> SignatureKey {
+        SignatureKey {
+            key_id: key_id.into(),
+            key: Key::Ed25519Public(public_key),
+        }
+    }
+
+    fn alg(&self) -> &'static str {
+        match self.key {
+            Key::HmacSha256(_) => "hmac-sha256",
+            Key::Ed25519(_) | Key::Ed25519Public(_) => "ed25519",
+        }
+    }
+
+    fn sign(&self, base: &[u8]) -> Result<Vec<u8>, BoxError> {
+        match self.key {
+            Key::HmacSha256(ref secret) => {
+                let mut mac = hmac(secret);
+                mac.update(base);
+                Ok(mac.finalize().into_bytes().to_vec())
+            }
+            Key::Ed25519(ref private_key) => {
+                let key = ed25519_dalek::SigningKey::from_bytes(private_key);
+                Ok(key.sign(base).to_bytes().to_vec())
+            }
+            Key::Ed25519Public(_) => Err("an ed25519 public key can't sign".into()),
+        }
+    }
+
+    fn verify(&self, base: &[u8], signature: &[u8]) -> bool {
+        let public_key = match self.key {
+            Key::HmacSha256(ref secret) => {
+                let mut mac = hmac(secret);
+                mac.update(base);
+                return mac.verify_slice(signature).is_ok();
+            }
+            Key::Ed25519(ref private_key) => {
+                ed25519_dalek::SigningKey::from_bytes(private_key).verifying_key()
+            }
+            Key::Ed25519Public(ref public_key) => {
+                match ed25519_dalek::VerifyingKey::from_bytes(public_key) {
+                    Ok(public_key) => public_key,
+                    Err(_) => return false,
+                }
+            }
+        };
+        match ed25519_dalek::Signature::from_slice(signature) {
+            Ok(signature) => public_key.verify_strict(base, &signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+fn hmac(secret: &[u8]) -> Hmac<Sha256> {
+    // HMAC takes keys of any length
+    Hmac::<Sha256>::new_from_slice(secret).expect("HMAC key")
+}
+
+impl fmt::Debug for SignatureKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SignatureKey")
+            .field("key_id", &self.key_id)
+            .field("alg", &self.alg())
+            .finish()
+    }
+}
+
+/// Signs requests with HTTP Message Signatures, see RFC 9421.
+///
+/// Set on a request with `RequestBuilder::message_signature()`. The
+/// signature covers the components added with `component()`, in that
+/// order, and is sent in the `Signature-Input` and `Signature` headers.
+///
+/// Supported components are `@method`, `@target-uri`, `@authority`,
+/// `@scheme`, `@path`, `@query` and header names. If `content-digest` is
+/// covered and the request has no `Content-Digest` header, one with the
+/// SHA-256 digest of the body is added, see RFC 9530.
+///
+/// # Example
+///
+/// ```rust
+/// # fn run() -> Result<(), nightfly::Error> {
+/// use nightfly::auth::{MessageSigner, SignatureKey};
+///
+/// let signer = MessageSigner::new(SignatureKey::hmac_sha256("partner", "secret"))
+///     .component("@method")
+///     .component("@target-uri")
+///     .component("content-digest");
+/// let client = nightfly::Client::new();
+/// let res = client
+///     .post("http://localhost:8080/webhook")
+///     .body("{}")
+///     .message_signature(signer)
+///     .send()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MessageSigner {
+    key: SignatureKey,
+    label: String,
+    components: Vec<String>,
+    expires_in: Option<Duration>,
+    tag: Option<String>,
+}
+
+impl MessageSigner {
+    /// Sign with `key`, covering no components yet.
+    pub fn new(key: SignatureKey) -> MessageSigner {
+        MessageSigner {
+            key,
+            label: "sig1".to_string(),
+            components: Vec::new(),
+            expires_in: None,
+            tag: None,
+        }
+    }
+
+    /// Cover a derived component such as `@method`, or a header.
+    pub fn component<C: Into<String>>(mut self, component: C) -> MessageSigner {
+        self.components.push(component.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Set the label of the signature in the `Signature-Input` and
+    /// `Signature` headers.
+    ///
+    /// Default is `sig1`.
+    pub fn label<L: Into<String>>(mut self, label: L) -> MessageSigner {
+        self.label = label.into();
+        self
+    }
+
+    /// Add an `expires` parameter, `expires_in` after the signature is
+    /// created.
+    pub fn expires_in(mut self, expires_in: Duration) -> MessageSigner {
+        self.expires_in = Some(expires_in);
+        self
+    }
+
+    /// Add a `tag` parameter, naming the application of the signature.
+    pub fn tag<T: Into<String>>(mut self, tag: T) -> MessageSigner {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    /// Adds the `Signature-Input` and `Signature` headers for a request
+    /// sent at `now`.
+    pub(crate) fn sign(
+        &self,
+        method: &str,
+        url: &Url,
+        headers: &mut HeaderMap,
+        body: &[u8],
+        now: SystemTime,
+    ) -> crate::Result<()> {
+        if self.components.iter().any(|c| c == CONTENT_DIGEST)
+            && !headers.contains_key(CONTENT_DIGEST)
+        {
+            let digest = format!("sha-256=:{}:", base64::encode(Sha256::digest(body)));
+            headers.insert(
+                HeaderName::from_static(CONTENT_DIGEST),
+                HeaderValue::from_str(&digest).map_err(error::builder)?,
+            );
+        }
+        let message = Message {
+            method: Some(method),
+            url,
+            status: None,
+            headers,
+        };
+        let (input, signature) = self.sign_message(&message, now).map_err(error::builder)?;
+        headers.insert(
+            HeaderName::from_static("signature-input"),
+            HeaderValue::from_str(&input).map_err(error::builder)?,
+        );
+        headers.insert(
+            HeaderName::from_static("signature"),
+            HeaderValue::from_str(&signature).map_err(error::builder)?,
+        );
+        Ok(())
+    }
+
+    /// Returns the values of the `Signature-Input` and `Signature` headers.
+    fn sign_message(
+        &self,
+        message: &Message,
+        now: SystemTime,
+    ) -> Result<(String, String), BoxError> {
+        let created = unix_time(now);
+        let mut params = inner_list(&self.components);
+        params.push_str(&format!(";created={}", created));
+        if let Some(expires_in) = self.expires_in {
+            params.push_str(&format!(";expires={}", created + expires_in.as_secs()));
+        }
+        params.push_str(&format!(";keyid={}", quote(&self.key.key_id)));
+        if let Some(ref tag) = self.tag {
+            params.push_str(&format!(";tag={}", quote(tag)));
+        }
+
+        let base = signature_base(message, &self.components, &params)?;
+        let signature = self.key.sign(base.as_bytes())?;
+        Ok((
+            format!("{}={}", self.label, params),
+            format!("{}=:{}:", self.label, base64::encode(signature)),
+        ))
+    }
+}
+
+/// Verifies HTTP Message Signatures of responses, see
+/// `HttpResponse::verify_signature()`.
+///
+/// A signature is accepted if it was made with the key of the verifier,
+/// covers all required components and hasn't expired. If it covers
+/// `content-digest`, the `Content-Digest` header must match the body as it
+/// was received, before it was decompressed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignatureVerifier {
+    key: SignatureKey,
+    label: Option<String>,
+    required: Vec<String>,
+    max_age: Option<Duration>,
+}
+
+impl SignatureVerifier {
+    /// Verify signatures made with `key`.
+    pub fn new(key: SignatureKey) -> SignatureVerifier {
+        SignatureVerifier {
+            key,
+            label: None,
+            required: Vec::new(),
+            max_age: None,
+        }
+    }
+
+    /// Verify the signature with this label.
+    ///
+    /// Default is the first signature with the key id of the key.
+    pub fn label<L: Into<String>>(mut self, label: L) -> SignatureVerifier {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Require the signature to cover a component, such as `@status` or
+    /// `content-digest`.
+    pub fn require_component<C: Into<String>>(mut self, component: C) -> SignatureVerifier {
+        self.required.push(component.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Reject signatures created longer than `max_age` ago.
+    ///
+    /// Default is to accept signatures of any age, until they expire.
+    pub fn max_age(mut self, max_age: Duration) -> SignatureVerifier {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub(crate) fn verify(
+        &self,
+        status: StatusCode,
+        url: &Url,
+        headers: &HeaderMap,
+        body: &[u8],
+        now: SystemTime,
+    ) -> Result<(), BoxError> {
+        let inputs =
+            header_string(headers, "signature-input").ok_or("missing Signature-Input header")?;
+        let signatures = header_string(headers, "signature").ok_or("missing Signature header")?;
+
+        let (label, raw_params, params) = dictionary_members(&inputs)
+            .into_iter()
+            .filter_map(|(label, member)| {
+                parse_signature_params(member).map(|params| (label, member, params))
+            })
+            .find(|(label, _, params)| match self.label {
+                Some(ref wanted) => *label == wanted.as_str(),
+                None => params.get("keyid").map_or(true, |id| id == self.key.key_id),
+            })
+            .ok_or("no matching signature")?;
+        let signature = dictionary_members(&signatures)
+            .into_iter()
+            .find(|(l, _)| *l == label)
+            .and_then(|(_, value)| value.strip_prefix(':')?.strip_suffix(':'))
+            .ok_or("missing signature value")?;
+        let signature = base64::decode(signature)?;
+
+        if let Some(key_id) = params.get("keyid") {
+            if key_id != self.key.key_id {
+                return Err(format!("unknown key id {:?}", key_id).into());
+            }
+        }
+        if let Some(alg) = params.get("alg") {
+            if alg != self.key.alg() {
+                return Err(format!("unexpected algorithm {:?}", alg).into());
+            }
+        }
+        for component in &self.required {
+            if !params.components.contains(component) {
+                return Err(format!("component {:?} isn't covered", component).into());
+            }
+        }
+        let now = unix_time(now);
+        if let Some(expires) = params.get("expires") {
+            if expires.parse::<u64>()? <= now {
+                return Err("signature expired".into());
+            }
+        }
+        if let Some(max_age) = self.max_age {
+            let created = params.get("created").ok_or("missing created parameter")?;
+            if created.parse::<u64>()? + max_age.as_secs() < now {
+                return Err("signature too old".into());
+            }
+        }
+        if params.components.iter().any(|c| c == CONTENT_DIGEST) {
+            verify_content_digest(headers, body)?;
+        }
+
+        let message = Message {
+            method: None,
+            url,
+            status: Some(status),
+            headers,
+        };
+        let base = signature_base(&message, &params.components, raw_params)?;
+        if !self.key.verify(base.as_bytes(), &signature) {
+            return Err("signature mismatch".into());
+        }
+        Ok(())
+    }
+}
+
+/// A request, if `method` is set, or a response otherwise.
+struct Message<'a> {
+    method: Option<&'a str>,
+    url: &'a Url,
+    status: Option<StatusCode>,
+    headers: &'a HeaderMap,
+}
+
+/// The covered components and parameters of a signature.
+struct SignatureParams {
+    components: Vec<String>,
+    params: Vec<(String, String)>,
+}
+
+impl SignatureParams {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn signature_base(
+    message: &Message,
+    components: &[String],
+    params: &str,
+) -> Result<String, BoxError> {
+    let mut base = String::new();
+    for component in components {
+        let value = component_value(message, component)?;
+        base.push_str(&format!("\"{}\": {}\n", component, value));
+    }
+    base.push_str(&format!("\"@signature-params\": {}", params));
+    Ok(base)
+}
+
+fn component_value(message: &Message, component: &str) -> Result<String, BoxError> {
+    let url = message.url;
+    let request = message.method.is_some();
+    let value = match component {
+        "@method" if request => message.method.unwrap_or_default().to_string(),
+        "@target-uri" if request => {
+            let mut url = url.clone();
+            url.set_fragment(None);
+            url.to_string()
+        }
+        "@authority" if request => match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        },
+        "@scheme" if request => url.scheme().to_string(),
+        "@path" if request => url.path().to_string(),
+        "@query" if request => format!("?{}", url.query().unwrap_or_default()),
+        "@status" if !request => {
+            let status = message.status.map(|status| status.as_u16());
+            status.unwrap_or_default().to_string()
+        }
+        derived if derived.starts_with('@') => {
+            return Err(format!("unsupported component {:?}", derived).into())
+        }
+        header => {
+            let values: Vec<String> = message
+                .headers
+                .get_all(header)
+                .iter()
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).trim().to_string())
+                .collect();
+            if values.is_empty() {
+                return Err(format!("missing header {:?}", header).into());
+            }
+            values.join(", ")
+        }
+    };
+    Ok(value)
+}
+
+fn verify_content_digest(headers: &HeaderMap, body: &[u8]) -> Result<(), BoxError> {
+    let digests = header_string(headers, CONTENT_DIGEST).ok_or("missing Content-Digest header")?;
+    for (alg, value) in dictionary_members(&digests) {
+        let expected = match alg {
+            "sha-256" => base64::encode(Sha256::digest(body)),
+            "sha-512" => base64::encode(Sha512::digest(body)),
+            _ => continue,
+        };
+        return match value.strip_prefix(':').and_then(|v| v.strip_suffix(':')) {
+            Some(digest) if digest == expected => Ok(()),
+            _ => Err("Content-Digest doesn't match the body".into()),
+        };
+    }
+    Err("no supported algorithm in Content-Digest".into())
+}
+
+/// Joins all values of a header, like a list-based structured field.
+fn header_string(headers: &HeaderMap, name: &str) -> Option<String> {
+    let values: Vec<&str> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.join(", "))
+    }
+}
+
+/// Splits a structured field dictionary into its keys and raw member
+/// values, see RFC 8941, section 3.2. Commas inside inner lists and
+/// strings don't split members.
+fn dictionary_members(value: &str) -> Vec<(&str, &str)> {
+    let mut members = Vec::new();
+    let (mut depth, mut quoted, mut escaped, mut start) = (0usize, false, false, 0);
+    for (i, c) in value.char_indices() {
+        if quoted {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                quoted = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => quoted = true,
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                members.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    members.push(&value[start..]);
+    members
+        .into_iter()
+        .filter_map(|member| {
+            let (key, value) = member.trim().split_once('=')?;
+            Some((key.trim(), value.trim()))
+        })
+        .collect()
+}
+
+/// Parses `("@method" "content-type");created=1618884473;keyid="key"`.
+fn parse_signature_params(member: &str) -> Option<SignatureParams> {
+    let rest = member.strip_prefix('(')?;
+    let end = rest.find(')')?;
+    let components = rest[..end]
+        .split_whitespace()
+        .map(|item| Some(unquote(item)?.to_string()))
+        .collect::<Option<Vec<_>>>()?;
+    let params = rest[end + 1..]
+        .split(';')
+        .filter(|param| !param.trim().is_empty())
+        .map(|param| {
+            let (name, value) = param.trim().split_once('=')?;
+            let value = unquote(value).unwrap_or(value);
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(SignatureParams { components, params })
+}
+
+fn inner_list(components: &[String]) -> String {
+    let items: Vec<String> = components.iter().map(|c| quote(c)).collect();
+    format!("({})", items.join(" "))
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn unquote(s: &str) -> Option<&str> {
+    s.strip_prefix('"')?.strip_suffix('"')
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn created() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1618884473)
+    }
+
+    fn example_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("example.com"));
+        headers.insert(
+            "date",
+            HeaderValue::from_static("Tue, 20 Apr 2021 02:07:55 GMT"),
+        );
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        headers.insert("content-length", HeaderValue::from_static("18"));
+        headers
+    }
+
+    #[lunatic::test]
+    fn test_sign_hmac_sha256_rfc_9421_example() {
+        // appendix B.2.5 of RFC 9421
+        let secret = base64::decode(
+            "uzvJfB4u3N0Jy4T7NZ75MDVcr8zSTInedJtkgcu46YW4XByzNJjxBdtjUkdJPBtbmHhIDi6pcl8jsasjlTMtDQ==",
+        )
+        .unwrap();
+        let signer = MessageSigner::new(SignatureKey::hmac_sha256("test-shared-secret", secret))
+            .label("sig-b25")
+            .component("date")
+            .component("@authority")
+            .component("Content-Type");
+        let url = Url::parse("http://example.com/foo?param=Value&Pet=dog").unwrap();
+        let mut headers = example_headers();
+        signer
+            .sign(
+                "POST",
+                &url,
+                &mut headers,
+                b"{\"hello\": \"world\"}",
+                created(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            headers["signature-input"],
+            "sig-b25=(\"date\" \"@authority\" \"content-type\")\
+             ;created=1618884473;keyid=\"test-shared-secret\""
+        );
+        assert_eq!(
+            headers["signature"],
+            "sig-b25=:pxcQw6G3AjtMBQjwo8XzkZf/bws5LelbaMk5rGIGtE8=:"
+        );
+    }
+
+    #[lunatic::test]
+    fn test_sign_ed25519_rfc_9421_example() {
+        // appendix B.2.6 of RFC 9421
+        // the private key is the last 32 bytes of the PKCS#8 document
+        let der =
+            base64::decode("MC4CAQAwBQYDK2VwBCIEIJ+DYvh6SEqVTm50DFtMDoQikTmiCqirVv9mWG9qfSnF")
+                .unwrap();
+        let mut private_key = [0; 32];
+        private_key.copy_from_slice(&der[16..]);
+        let signer = MessageSigner::new(SignatureKey::ed25519("test-key-ed25519", private_key))
+            .label("sig-b26")
+            .component("date")
+            .component("@method")
+            .component("@path")
+            .component("@authority")
+            .component("content-type")
+            .component("content-length");
+        let url = Url::parse("http://example.com/foo?param=Value&Pet=dog").unwrap();
+        let mut headers = example_headers();
+        signer
+            .sign(
+                "POST",
+                &url,
+                &mut headers,
+                b"{\"hello\": \"world\"}",
+                created(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            headers["signature"],
+            "sig-b26=:wqcAqbmYJ2ji2glfAMaRy4gruYYnx2nEFN2HN6jrnDnQCK1u02Gb04v9EDgwUPiu4A0w6vuQv5lIp5WPpBKRCw==:"
+        );
+    }
+
+    #[lunatic::test]
+    fn test_verify_response() {
+        let key = SignatureKey::hmac_sha256("server", "secret");
+        let url = Url::parse("http://example.com/hook").unwrap();
+        let body = b"{\"ok\":true}";
+        let mut headers = HeaderMap::new();
+        let digest = format!("sha-256=:{}:", base64::encode(Sha256::digest(body)));
+        headers.insert(CONTENT_DIGEST, HeaderValue::from_str(&digest).unwrap());
+        let signer = MessageSigner::new(key.clone())
+            .component("@status")
+            .component("content-digest")
+            .expires_in(Duration::from_secs(60));
+        let message = Message {
+            method: None,
+            url: &url,
+            status: Some(StatusCode::OK),
+            headers: &headers,
+        };
+        let (input, signature) = signer.sign_message(&message, created()).unwrap();
+        headers.insert("signature-input", HeaderValue::from_str(&input).unwrap());
+        headers.insert("signature", HeaderValue::from_str(&signature).unwrap());
+
+        let verifier = SignatureVerifier::new(key).require_component("content-digest");
+        let now = created() + Duration::from_secs(10);
+        verifier
+            .verify(StatusCode::OK, &url, &headers, body, now)
+            .unwrap();
+
+        // tampered status, body, or an expired signature are rejected
+        assert!(verifier
+            .verify(StatusCode::CREATED, &url, &headers, body, now)
+            .is_err());
+        assert!(verifier
+            .verify(StatusCode::OK, &url, &headers, b"{}", now)
+            .is_err());
+        let later = created() + Duration::from_secs(120);
+        assert!(verifier
+            .verify(StatusCode::OK, &url, &headers, body, later)
+            .is_err());
+        let other_key = SignatureVerifier::new(SignatureKey::hmac_sha256("server", "other"));
+        assert!(other_key
+            .verify(StatusCode::OK, &url, &headers, body, now)
+            .is_err());
+        let missing = SignatureVerifier::new(SignatureKey::hmac_sha256("server", "secret"))
+            .require_component("content-type");
+        assert!(missing
+            .verify(StatusCode::OK, &url, &headers, body, now)
+            .is_err());
+    }
+
+    #[lunatic::test]
+    fn test_dictionary_members() {
+        let members =
+            dictionary_members("sig1=(\"@method\" \"a,b\");created=1;tag=\"x,y\", sig2=:abc=:");
+        assert_eq!(
+            members,
+            vec![
+                ("sig1", "(\"@method\" \"a,b\");created=1;tag=\"x,y\""),
+                ("sig2", ":abc=:")
+            ]
+        );
+    }
+}
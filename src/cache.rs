@@ -0,0 +1,715 @@
+//! HTTP Caching
+//!
+//! A `Client` can keep responses in a cache following
+//! [RFC 9111](https://www.rfc-editor.org/rfc/rfc9111), which is enabled by
+//! passing a `CacheStore` to `ClientBuilder::cache`. The cache is private to
+//! the client and shared by every process that uses it.
+//!
+//! Fresh responses are answered from the cache, and stale responses are
+//! revalidated with `If-None-Match` or `If-Modified-Since`. Freshness is
+//! taken from `Cache-Control` and `Expires`, or estimated from
+//! `Last-Modified`, and `Vary` is honored for the headers that are sent,
+//! including default headers and cookies. Whether a response came from the
+//! cache is reported by [`HttpResponse::cache_status`].
+//!
+//! # Example
+//!
+//! ```rust
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let client = nightfly::Client::builder()
+//!     .cache(nightfly::cache::MemoryStore::new(1000))
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::hash_map::HashMap;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use http::header::{
+    HeaderMap, HeaderName, HeaderValue, AGE, CACHE_CONTROL, CONNECTION, CONTENT_LENGTH, DATE, ETAG,
+    EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, PRAGMA, TE, TRANSFER_ENCODING,
+    UPGRADE, VARY,
+};
+use http::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::lunatic_impl::request::{header_map_from_pairs, pairs_from_header_map};
+use crate::middleware::Middleware;
+use crate::{HttpResponse, Request, Url, Version};
+
+/// How a response was produced by the cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CacheStatus {
+    /// The response was answered from the cache without contacting the
+    /// server.
+    Hit,
+    /// The response came from the server.
+    Miss,
+    /// The server confirmed that the stored response is still valid.
+    Revalidated,
+}
+
+/// A storage for cached responses.
+///
+/// Stores are keyed by the method and URL of the request. Each key holds
+/// the variants of a response that differ in the request headers named by
+/// `Vary`.
+pub trait CacheStore: 'static {
+    /// Returns the stored variants for `key`.
+    fn get(&mut self, key: &str) -> Vec<CacheEntry>;
+
+    /// Replaces the stored variants for `key`.
+    fn put(&mut self, key: &str, entries: Vec<CacheEntry>);
+
+    /// Removes everything stored for `key`.
+    fn remove(&mut self, key: &str);
+}
+
+/// A stored response.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CacheEntry {
+    status: u16,
+    version: Version,
+    headers: Vec<(String, Vec<u8>)>,
+    body: Vec<u8>,
+    url: Url,
+    // the request headers named by `Vary`, with the values they had
+    vary: Vec<(String, Vec<Vec<u8>>)>,
+    request_time: SystemTime,
+    response_time: SystemTime,
+}
+
+/// A `CacheStore` that keeps the responses for up to `capacity` URLs in
+/// memory, evicting the least recently used one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MemoryStore {
+    capacity: usize,
+    entries: HashMap<String, Vec<CacheEntry>>,
+    order: VecDeque<String>,
+}
+
+impl MemoryStore {
+    /// Create an empty `MemoryStore`.
+    pub fn new(capacity: usize) -> Self {
+        MemoryStore {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn touch(&mut self, key: &str) {
+        if let Some(position) = self.order.iter().position(|k| k == key) {
+            self.order.remove(position);
+        }
+        self.order.push_back(key.to_string());
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&mut self, key: &str) -> Vec<CacheEntry> {
+        match self.entries.get(key).cloned() {
+            Some(entries) => {
+                self.touch(key);
+                entries
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn put(&mut self, key: &str, entries: Vec<CacheEntry>) {
+        self.entries.insert(key.to_string(), entries);
+        self.touch(key);
+        while self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
+        self.order.retain(|k| k != key);
+    }
+}
+
+/// A `CacheStore` that keeps every URL's responses in a JSON file in a
+/// directory.
+///
+/// The directory must be accessible to the client process.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DiskStore {
+    dir: PathBuf,
+}
+
+impl DiskStore {
+    /// Create a `DiskStore` in `dir`, which is created when the first
+    /// response is stored.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        DiskStore { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        // FNV-1a, so that file names stay the same between builds
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        self.dir.join(format!("{:016x}.json", hash))
+    }
+}
+
+impl CacheStore for DiskStore {
+    fn get(&mut self, key: &str) -> Vec<CacheEntry> {
+        fs::read(self.path(key))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<(String, Vec<CacheEntry>)>(&bytes).ok())
+            // tell apart keys that share a hash
+            .filter(|(stored_key, _)| stored_key == key)
+            .map(|(_, entries)| entries)
+            .unwrap_or_default()
+    }
+
+    fn put(&mut self, key: &str, entries: Vec<CacheEntry>) {
+        let result = fs::create_dir_all(&self.dir).and_then(|_| {
+            let bytes = serde_json::to_vec(&(key, entries))?;
+            fs::write(self.path(key), bytes)
+        });
+        if let Err(e) = result {
+            lunatic_log::error!("Failed to store cached response {:?}", e);
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        let _ = fs::remove_file(self.path(key));
+    }
+}
+
+/// The cache, running as the innermost `Middleware` of a client.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Cache<S> {
+    store: S,
+}
+
+/// What the cache keeps about a request while it's being sent, set on the
+/// request by `handle_request`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Pending {
+    key: String,
+    stale: Option<CacheEntry>,
+    request_time: SystemTime,
+    // whether the cache added the conditional headers itself
+    conditional: bool,
+}
+
+impl<S: CacheStore> Cache<S> {
+    pub(crate) fn new(store: S) -> Self {
+        Cache { store }
+    }
+
+    fn store_response(&mut self, key: &str, req: &Request, entry: CacheEntry) {
+        let sent = sent_headers(req);
+        let mut entries = self.store.get(key);
+        entries.retain(|stored| !stored.matches(&sent));
+        entries.push(entry);
+        self.store.put(key, entries);
+    }
+}
+
+impl<S: CacheStore> Middleware for Cache<S> {
+    fn handle_request(&mut self, req: &mut Request) -> Option<crate::Result<HttpResponse>> {
+        req.cache_pending = None;
+        if req.method() != Method::GET {
+            return None;
+        }
+        let key = cache_key(req.method(), req.url());
+        let now = SystemTime::now();
+        let req_directives = Directives::parse(req.headers());
+        let sent = sent_headers(req);
+        let entry = self
+            .store
+            .get(&key)
+            .into_iter()
+            .find(|entry| entry.matches(&sent));
+
+        let stale = match entry {
+            Some(entry) => {
+                let headers = entry.headers();
+                let res_directives = Directives::parse(&headers);
+                let age = entry.current_age(&headers, now);
+                let lifetime = entry.freshness_lifetime(&headers, &res_directives);
+                if is_fresh(lifetime, age, &req_directives, &res_directives) {
+                    return Some(Ok(entry.to_response(Some(age), CacheStatus::Hit)));
+                }
+                Some(entry)
+            }
+            None => None,
+        };
+        if req_directives.only_if_cached {
+            let mut res = HttpResponse::new(StatusCode::GATEWAY_TIMEOUT, req.url().clone(), vec![]);
+            res.cache_status = Some(CacheStatus::Miss);
+            return Some(Ok(res));
+        }
+
+        let mut conditional = false;
+        if let Some(ref stale) = stale {
+            let headers = stale.headers();
+            let user_conditional = req.headers().contains_key(IF_NONE_MATCH)
+                || req.headers().contains_key(IF_MODIFIED_SINCE);
+            if !user_conditional {
+                if let Some(etag) = headers.get(ETAG) {
+                    req.headers_mut().insert(IF_NONE_MATCH, etag.clone());
+                    conditional = true;
+                } else if let Some(last_modified) = headers.get(LAST_MODIFIED) {
+                    req.headers_mut()
+                        .insert(IF_MODIFIED_SINCE, last_modified.clone());
+                    conditional = true;
+                }
+            }
+        }
+        req.cache_pending = Some(Pending {
+            key,
+            stale,
+            request_time: now,
+            conditional,
+        });
+        None
+    }
+
+    fn handle_response(
+        &mut self,
+        req: &Request,
+        res: crate::Result<HttpResponse>,
+    ) -> crate::Result<HttpResponse> {
+        let pending = req.cache_pending.clone();
+        let mut res = res?;
+        if is_unsafe(req.method()) {
+            // a successful change invalidates what was stored for the URL
+            if res.status.is_success() || res.status.is_redirection() {
+                self.store.remove(&cache_key(&Method::GET, req.url()));
+            }
+            return Ok(res);
+        }
+        let pending = match pending {
+            Some(pending) => pending,
+            None => return Ok(res),
+        };
+        let response_time = SystemTime::now();
+
+        if res.status == StatusCode::NOT_MODIFIED {
+            if let Some(mut stale) = pending.stale {
+                stale.merge_not_modified(&res.headers, pending.request_time, response_time);
+                self.store_response(&pending.key, req, stale.clone());
+                if pending.conditional {
+                    return Ok(stale.to_response(None, CacheStatus::Revalidated));
+                }
+                res.cache_status = Some(CacheStatus::Revalidated);
+                return Ok(res);
+            }
+        }
+
+        res.cache_status = Some(CacheStatus::Miss);
+        if is_storable(req, &res) {
+            let entry = CacheEntry::new(req, &res, pending.request_time, response_time);
+            self.store_response(&pending.key, req, entry);
+        } else if res.status.is_success() {
+            self.store.remove(&pending.key);
+        }
+        Ok(res)
+    }
+}
+
+impl CacheEntry {
+    fn new(
+        req: &Request,
+        res: &HttpResponse,
+        request_time: SystemTime,
+        response_time: SystemTime,
+    ) -> Self {
+        let sent = sent_headers(req);
+        let vary = vary_names(&res.headers)
+            .into_iter()
+            .map(|name| {
+                let values = sent
+                    .get_all(name.as_str())
+                    .iter()
+                    .map(|value| value.as_bytes().to_vec())
+                    .collect();
+                (name, values)
+            })
+            .collect();
+        CacheEntry {
+            status: res.status.as_u16(),
+            version: res.version,
+            headers: pairs_from_header_map(&res.headers),
+            body: res.body.clone(),
+            url: res.url.clone(),
+            vary,
+            request_time,
+            response_time,
+        }
+    }
+
+    fn headers(&self) -> HeaderMap {
+        header_map_from_pairs(self.headers.clone())
+    }
+
+    /// Returns true if this variant was stored for a request with the same
+    /// values of the headers named by `Vary`.
+    fn matches(&self, req_headers: &HeaderMap) -> bool {
+        self.vary.iter().all(|(name, values)| {
+            name != "*"
+                && req_headers
+                    .get_all(name.as_str())
+                    .iter()
+                    .map(|value| value.as_bytes())
+                    .eq(values.iter().map(|value| value.as_slice()))
+        })
+    }
+
+    /// RFC 9111, section 4.2.1
+    fn freshness_lifetime(&self, headers: &HeaderMap, directives: &Directives) -> Duration {
+        if let Some(max_age) = directives.max_age {
+            return max_age;
+        }
+        let date = header_date(headers, DATE).unwrap_or(self.response_time);
+        if let Some(expires) = headers.get(EXPIRES) {
+            // an invalid date, like "0", means already expired
+            return parse_date(expires)
+                .and_then(|expires| expires.duration_since(date).ok())
+                .unwrap_or_default();
+        }
+        let heuristic = StatusCode::from_u16(self.status)
+            .map(is_heuristically_cacheable)
+            .unwrap_or(false);
+        match header_date(headers, LAST_MODIFIED) {
+            // RFC 9111, section 4.2.2
+            Some(last_modified) if heuristic => {
+                let since_modified = date.duration_since(last_modified).unwrap_or_default();
+                (since_modified / 10).min(MAX_HEURISTIC_FRESHNESS)
+            }
+            _ => Duration::from_secs(0),
+        }
+    }
+
+    /// RFC 9111, section 4.2.3
+    fn current_age(&self, headers: &HeaderMap, now: SystemTime) -> Duration {
+        let date = header_date(headers, DATE).unwrap_or(self.response_time);
+        let apparent_age = self.response_time.duration_since(date).unwrap_or_default();
+        let age_value = headers
+            .get(AGE)
+            .and_then(|age| age.to_str().ok())
+            .and_then(|age| age.trim().parse().ok())
+            .map(Duration::from_secs)
+            .unwrap_or_default();
+        let response_delay = self
+            .response_time
+            .duration_since(self.request_time)
+            .unwrap_or_default();
+        let corrected_initial_age = apparent_age.max(age_value + response_delay);
+        let resident_time = now.duration_since(self.response_time).unwrap_or_default();
+        corrected_initial_age + resident_time
+    }
+
+    /// RFC 9111, section 4.3.4
+    fn merge_not_modified(
+        &mut self,
+        not_modified: &HeaderMap,
+        request_time: SystemTime,
+        response_time: SystemTime,
+    ) {
+        let mut headers = self.headers();
+        for name in not_modified.keys() {
+            if is_excluded_from_update(name) {
+                continue;
+            }
+            headers.remove(name);
+            for value in not_modified.get_all(name) {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+        self.headers = pairs_from_header_map(&headers);
+        self.request_time = request_time;
+        self.response_time = response_time;
+    }
+
+    fn to_response(&self, age: Option<Duration>, status: CacheStatus) -> HttpResponse {
+        let mut res = HttpResponse::new(
+            StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK),
+            self.url.clone(),
+            self.body.clone(),
+        );
+        res.version = self.version;
+        res.headers = self.headers();
+        if let Some(age) = age {
+            res.headers.insert(AGE, HeaderValue::from(age.as_secs()));
+        }
+        res.cache_status = Some(status);
+        res
+    }
+}
+
+const MAX_HEURISTIC_FRESHNESS: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Default)]
+struct Directives {
+    no_store: bool,
+    no_cache: bool,
+    must_revalidate: bool,
+    public: bool,
+    only_if_cached: bool,
+    max_age: Option<Duration>,
+    min_fresh: Option<Duration>,
+    // `Some(None)` is `max-stale` without a limit
+    max_stale: Option<Option<Duration>>,
+}
+
+impl Directives {
+    fn parse(headers: &HeaderMap) -> Self {
+        let mut directives = Directives::default();
+        let seconds = |arg: Option<&str>| {
+            arg.and_then(|arg| arg.parse().ok())
+                .map(Duration::from_secs)
+        };
+        for value in headers.get_all(CACHE_CONTROL) {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            for directive in value.split(',') {
+                let mut parts = directive.splitn(2, '=');
+                let name = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+                let arg = parts.next().map(|arg| arg.trim().trim_matches('"'));
+                match name.as_str() {
+                    "no-store" => directives.no_store = true,
+                    "no-cache" => directives.no_cache = true,
+                    "must-revalidate" | "proxy-revalidate" => directives.must_revalidate = true,
+                    "public" => directives.public = true,
+                    "only-if-cached" => directives.only_if_cached = true,
+                    // an invalid max-age means already stale
+                    "max-age" => directives.max_age = Some(seconds(arg).unwrap_or_default()),
+                    "min-fresh" => directives.min_fresh = seconds(arg),
+                    "max-stale" => directives.max_stale = Some(seconds(arg)),
+                    _ => {}
+                }
+            }
+        }
+        // RFC 9111, section 5.4
+        if !headers.contains_key(CACHE_CONTROL) {
+            directives.no_cache = headers
+                .get_all(PRAGMA)
+                .iter()
+                .any(|value| value.as_bytes().eq_ignore_ascii_case(b"no-cache"));
+        }
+        directives
+    }
+}
+
+fn is_fresh(lifetime: Duration, age: Duration, req: &Directives, res: &Directives) -> bool {
+    if req.no_cache || res.no_cache {
+        return false;
+    }
+    if req.max_age.map_or(false, |max_age| age > max_age) {
+        return false;
+    }
+    if lifetime >= age + req.min_fresh.unwrap_or_default() {
+        return true;
+    }
+    // RFC 9111, section 4.2.4
+    if res.must_revalidate {
+        return false;
+    }
+    match req.max_stale {
+        Some(None) => true,
+        Some(Some(max_stale)) => age <= lifetime + max_stale,
+        None => false,
+    }
+}
+
+/// RFC 9111, section 3
+fn is_storable(req: &Request, res: &HttpResponse) -> bool {
+    let req_directives = Directives::parse(req.headers());
+    let res_directives = Directives::parse(&res.headers);
+    req.method() == Method::GET
+        && !req_directives.no_store
+        && !res_directives.no_store
+        // responses after redirects belong to another URL
+        && res.url == *req.url()
+        && res.status != StatusCode::PARTIAL_CONTENT
+        && !res.status.is_informational()
+        && !vary_names(&res.headers).iter().any(|name| name == "*")
+        && (res_directives.max_age.is_some()
+            || res.headers.contains_key(EXPIRES)
+            || res_directives.public
+            || res_directives.no_cache
+            || is_heuristically_cacheable(res.status))
+}
+
+/// RFC 9110, section 15.1
+fn is_heuristically_cacheable(status: StatusCode) -> bool {
+    matches!(
+        status.as_u16(),
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    )
+}
+
+fn is_unsafe(method: &Method) -> bool {
+    !matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+fn is_excluded_from_update(name: &HeaderName) -> bool {
+    *name == CONTENT_LENGTH
+        || *name == CONNECTION
+        || *name == TE
+        || *name == TRANSFER_ENCODING
+        || *name == UPGRADE
+        || name.as_str() == "keep-alive"
+        || name.as_str().starts_with("proxy-")
+}
+
+/// Returns the headers the client sends with `req`, which also has the
+/// default headers and cookies of the client for names it doesn't set.
+fn sent_headers(req: &Request) -> HeaderMap {
+    let mut headers = req.headers().clone();
+    for name in req.client_headers.keys() {
+        if !headers.contains_key(name) {
+            for value in req.client_headers.get_all(name) {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+    }
+    headers
+}
+
+fn vary_names(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn header_date(headers: &HeaderMap, name: HeaderName) -> Option<SystemTime> {
+    headers.get(name).and_then(parse_date)
+}
+
+fn parse_date(value: &HeaderValue) -> Option<SystemTime> {
+    httpdate::parse_http_date(value.to_str().ok()?).ok()
+}
+
+fn cache_key(method: &Method, url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    format!("{} {}", method, url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry_with(headers: &[(&str, &str)], age: Duration) -> CacheEntry {
+        let now = SystemTime::now();
+        CacheEntry {
+            status: 200,
+            version: Version::HTTP_11,
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.as_bytes().to_vec()))
+                .collect(),
+            body: vec![],
+            url: Url::parse("http://localhost/").unwrap(),
+            vary: vec![],
+            request_time: now - age,
+            response_time: now - age,
+        }
+    }
+
+    #[lunatic::test]
+    fn test_cache_freshness() {
+        let entry = entry_with(&[("cache-control", "max-age=60")], Duration::from_secs(10));
+        let headers = entry.headers();
+        let res = Directives::parse(&headers);
+        let lifetime = entry.freshness_lifetime(&headers, &res);
+        let age = entry.current_age(&headers, SystemTime::now());
+        assert_eq!(lifetime, Duration::from_secs(60));
+        assert!(is_fresh(lifetime, age, &Directives::default(), &res));
+
+        let mut req = HeaderMap::new();
+        req.insert(CACHE_CONTROL, HeaderValue::from_static("max-age=5"));
+        assert!(!is_fresh(lifetime, age, &Directives::parse(&req), &res));
+
+        // Expires relative to Date
+        let date = SystemTime::now();
+        let expires = date + Duration::from_secs(120);
+        let entry = entry_with(
+            &[
+                ("date", httpdate::fmt_http_date(date).as_str()),
+                ("expires", httpdate::fmt_http_date(expires).as_str()),
+            ],
+            Duration::from_secs(0),
+        );
+        let headers = entry.headers();
+        let lifetime = entry.freshness_lifetime(&headers, &Directives::parse(&headers));
+        assert_eq!(lifetime, Duration::from_secs(120));
+    }
+
+    #[lunatic::test]
+    fn test_cache_heuristic_freshness_and_age() {
+        let date = SystemTime::now();
+        let last_modified = date - Duration::from_secs(1000);
+        let entry = entry_with(
+            &[
+                ("date", httpdate::fmt_http_date(date).as_str()),
+                (
+                    "last-modified",
+                    httpdate::fmt_http_date(last_modified).as_str(),
+                ),
+                ("age", "30"),
+            ],
+            Duration::from_secs(0),
+        );
+        let headers = entry.headers();
+        let lifetime = entry.freshness_lifetime(&headers, &Directives::parse(&headers));
+        assert_eq!(lifetime, Duration::from_secs(100));
+        assert!(entry.current_age(&headers, SystemTime::now()) >= Duration::from_secs(30));
+    }
+
+    #[lunatic::test]
+    fn test_cache_directives() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CACHE_CONTROL,
+            HeaderValue::from_static("no-cache, max-stale, min-fresh=\"10\""),
+        );
+        let directives = Directives::parse(&headers);
+        assert!(directives.no_cache);
+        assert_eq!(directives.max_stale, Some(None));
+        assert_eq!(directives.min_fresh, Some(Duration::from_secs(10)));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(PRAGMA, HeaderValue::from_static("no-cache"));
+        assert!(Directives::parse(&headers).no_cache);
+    }
+
+    #[lunatic::test]
+    fn test_memory_store_evicts_least_recently_used() {
+        let entry = entry_with(&[], Duration::from_secs(0));
+        let mut store = MemoryStore::new(2);
+        store.put("a", vec![entry.clone()]);
+        store.put("b", vec![entry.clone()]);
+        assert_eq!(store.get("a").len(), 1);
+        store.put("c", vec![entry]);
+        assert_eq!(store.get("b").len(), 0);
+        assert_eq!(store.get("a").len(), 1);
+        assert_eq!(store.get("c").len(), 1);
+    }
+}
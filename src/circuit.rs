@@ -0,0 +1,354 @@
+//! Circuit Breaking
+//!
+//! A `Client` can stop sending requests to a host that keeps failing, so
+//! that callers fail fast instead of waiting for timeouts. A
+//! `circuit::Policy` set on a `ClientBuilder` enables a circuit breaker for
+//! every host the client talks to.
+//!
+//! A breaker starts *closed* and lets every request through. It *opens* when
+//! too many requests fail, either in a row or as a share of the recent
+//! requests, and then rejects requests with an error for which
+//! [`Error::is_circuit_open`](crate::Error::is_circuit_open) returns true.
+//! After a while it becomes *half-open* and lets probe requests through one
+//! at a time, which close it again if they succeed.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::lunatic_impl::client::HostRef;
+
+/// A type that controls when the circuit breaker of a host opens and for
+/// how long.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # fn run() -> Result<(), nightfly::Error> {
+/// let policy = nightfly::circuit::Policy::default()
+///     .consecutive_failures(3)
+///     .open_duration(Duration::from_secs(10));
+/// let client = nightfly::Client::builder().circuit_breaker(policy).build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Policy {
+    consecutive_failures: u32,
+    failure_ratio: f64,
+    window: usize,
+    min_requests: usize,
+    open_duration: Duration,
+    half_open_requests: u32,
+    count_timeouts: bool,
+    failure_statuses: Vec<u16>,
+}
+
+/// The state of the circuit breaker of a host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum State {
+    /// Requests are sent normally.
+    Closed,
+    /// Requests are rejected without being sent.
+    Open,
+    /// Probe requests are sent one at a time to find out whether the host
+    /// has recovered.
+    HalfOpen,
+}
+
+impl Policy {
+    /// Open the breaker after `n` failed requests in a row.
+    ///
+    /// Default is `5`.
+    pub fn consecutive_failures(mut self, n: u32) -> Self {
+        self.consecutive_failures = n.max(1);
+        self
+    }
+
+    /// Open the breaker when at least `ratio` of the last `window` requests
+    /// failed, once at least `min_requests` requests were made.
+    ///
+    /// Default is half of the last 20 requests, with at least 10 requests.
+    pub fn failure_ratio(mut self, ratio: f64, window: usize, min_requests: usize) -> Self {
+        self.failure_ratio = ratio.clamp(0.0, 1.0);
+        self.window = window.max(1);
+        self.min_requests = min_requests.clamp(1, self.window);
+        self
+    }
+
+    /// Set how long the breaker stays open before it lets probe requests
+    /// through.
+    ///
+    /// Default is 30 seconds.
+    pub fn open_duration(mut self, duration: Duration) -> Self {
+        self.open_duration = duration;
+        self
+    }
+
+    /// Set how many probe requests must succeed while half-open to close
+    /// the breaker again. Probes are sent one at a time, other requests are
+    /// rejected while a probe is in flight.
+    ///
+    /// Default is `1`.
+    pub fn half_open_requests(mut self, n: u32) -> Self {
+        self.half_open_requests = n.max(1);
+        self
+    }
+
+    /// Set whether timed out requests count as failures.
+    ///
+    /// Default is `true`.
+    pub fn count_timeouts(mut self, enabled: bool) -> Self {
+        self.count_timeouts = enabled;
+        self
+    }
+
+    /// Set the response status codes that count as failures.
+    ///
+    /// Default is `500`, `502`, `503` and `504`.
+    pub fn failure_statuses<I>(mut self, statuses: I) -> Self
+    where
+        I: IntoIterator<Item = StatusCode>,
+    {
+        self.failure_statuses = statuses.into_iter().map(|s| s.as_u16()).collect();
+        self
+    }
+
+    pub(crate) fn counts_timeouts(&self) -> bool {
+        self.count_timeouts
+    }
+
+    pub(crate) fn is_failure_status(&self, status: StatusCode) -> bool {
+        self.failure_statuses.contains(&status.as_u16())
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            consecutive_failures: 5,
+            failure_ratio: 0.5,
+            window: 20,
+            min_requests: 10,
+            open_duration: Duration::from_secs(30),
+            half_open_requests: 1,
+            count_timeouts: true,
+            failure_statuses: vec![500, 502, 503, 504],
+        }
+    }
+}
+
+/// The circuit breakers of all hosts a client talks to.
+#[derive(Clone, Debug)]
+pub(crate) struct Breakers {
+    policy: Policy,
+    hosts: HashMap<HostRef, Breaker>,
+}
+
+#[derive(Clone, Debug)]
+struct Breaker {
+    state: State,
+    consecutive_failures: u32,
+    // the outcomes of the last requests, `true` for failures
+    outcomes: VecDeque<bool>,
+    opened_at: Option<Instant>,
+    probe_successes: u32,
+    // whether a probe request is in flight while half-open
+    probing: bool,
+}
+
+impl Breakers {
+    pub(crate) fn new(policy: Policy) -> Self {
+        Breakers {
+            policy,
+            hosts: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Returns the current state for `host`, moving an open breaker to
+    /// half-open once its open duration has passed.
+    pub(crate) fn state(&mut self, host: &HostRef) -> State {
+        let open_duration = self.policy.open_duration;
+        match self.hosts.get_mut(host) {
+            Some(breaker) => {
+                if breaker.state == State::Open
+                    && breaker
+                        .opened_at
+                        .map_or(true, |at| at.elapsed() >= open_duration)
+                {
+                    breaker.state = State::HalfOpen;
+                    breaker.probe_successes = 0;
+                    breaker.probing = false;
+                }
+                breaker.state
+            }
+            None => State::Closed,
+        }
+    }
+
+    /// Returns true if a request to `host` may be sent. While half-open,
+    /// only one probe request is let through until its outcome is recorded.
+    pub(crate) fn allows(&mut self, host: &HostRef) -> bool {
+        match self.state(host) {
+            State::Closed => true,
+            State::Open => false,
+            State::HalfOpen => match self.hosts.get_mut(host) {
+                Some(breaker) if !breaker.probing => {
+                    breaker.probing = true;
+                    true
+                }
+                _ => false,
+            },
+        }
+    }
+
+    /// Lets the next probe through after a request whose outcome doesn't
+    /// count, like a timeout if timeouts don't count as failures.
+    pub(crate) fn record_ignored(&mut self, host: &HostRef) {
+        if let Some(breaker) = self.hosts.get_mut(host) {
+            breaker.probing = false;
+        }
+    }
+
+    pub(crate) fn record_success(&mut self, host: &HostRef) {
+        let policy = &self.policy;
+        let breaker = self.hosts.entry(host.clone()).or_insert_with(Breaker::new);
+        breaker.probing = false;
+        breaker.consecutive_failures = 0;
+        breaker.push_outcome(false, policy.window);
+        if breaker.state == State::HalfOpen {
+            breaker.probe_successes += 1;
+            if breaker.probe_successes >= policy.half_open_requests {
+                lunatic_log::debug!("Closing circuit breaker for {:?}", host);
+                *breaker = Breaker::new();
+            }
+        }
+    }
+
+    pub(crate) fn record_failure(&mut self, host: &HostRef) {
+        let policy = &self.policy;
+        let breaker = self.hosts.entry(host.clone()).or_insert_with(Breaker::new);
+        breaker.probing = false;
+        // requests sent before the breaker opened don't keep it open longer
+        if breaker.state == State::Open {
+            return;
+        }
+        breaker.consecutive_failures += 1;
+        breaker.push_outcome(true, policy.window);
+        let failures = breaker.outcomes.iter().filter(|failed| **failed).count();
+        let ratio_exceeded = breaker.outcomes.len() >= policy.min_requests
+            && failures as f64 >= policy.failure_ratio * breaker.outcomes.len() as f64;
+        if breaker.state == State::HalfOpen
+            || breaker.consecutive_failures >= policy.consecutive_failures
+            || ratio_exceeded
+        {
+            lunatic_log::debug!("Opening circuit breaker for {:?}", host);
+            breaker.state = State::Open;
+            breaker.opened_at = Some(Instant::now());
+            breaker.outcomes.clear();
+        }
+    }
+}
+
+impl Breaker {
+    fn new() -> Self {
+        Breaker {
+            state: State::Closed,
+            consecutive_failures: 0,
+            outcomes: VecDeque::new(),
+            opened_at: None,
+            probe_successes: 0,
+            probing: false,
+        }
+    }
+
+    fn push_outcome(&mut self, failed: bool, window: usize) {
+        if self.outcomes.len() >= window {
+            self.outcomes.pop_front();
+        }
+        self.outcomes.push_back(failed);
+    }
+}
+
+#[lunatic::test]
+fn test_circuit_opens_after_consecutive_failures() {
+    let host = HostRef::Http("localhost:80".to_string());
+    let mut breakers = Breakers::new(
+        Policy::default()
+            .consecutive_failures(2)
+            .open_duration(Duration::from_secs(60)),
+    );
+
+    breakers.record_failure(&host);
+    assert_eq!(breakers.state(&host), State::Closed);
+    breakers.record_success(&host);
+    breakers.record_failure(&host);
+    assert_eq!(breakers.state(&host), State::Closed);
+    breakers.record_failure(&host);
+    assert_eq!(breakers.state(&host), State::Open);
+    assert!(!breakers.allows(&host));
+}
+
+#[lunatic::test]
+fn test_circuit_opens_on_failure_ratio() {
+    let host = HostRef::Http("localhost:80".to_string());
+    let mut breakers = Breakers::new(
+        Policy::default()
+            .consecutive_failures(100)
+            .failure_ratio(0.5, 4, 4),
+    );
+
+    breakers.record_failure(&host);
+    breakers.record_success(&host);
+    breakers.record_success(&host);
+    assert_eq!(breakers.state(&host), State::Closed);
+    breakers.record_failure(&host);
+    assert_eq!(breakers.state(&host), State::Open);
+}
+
+#[lunatic::test]
+fn test_circuit_half_open_probes() {
+    let host = HostRef::Http("localhost:80".to_string());
+    let mut breakers = Breakers::new(
+        Policy::default()
+            .consecutive_failures(1)
+            .open_duration(Duration::from_millis(0)),
+    );
+
+    breakers.record_failure(&host);
+    assert_eq!(breakers.state(&host), State::HalfOpen);
+    // a single probe is let through at a time
+    assert!(breakers.allows(&host));
+    assert!(!breakers.allows(&host));
+    breakers.record_failure(&host);
+    assert_eq!(breakers.state(&host), State::HalfOpen);
+    assert!(breakers.allows(&host));
+    breakers.record_ignored(&host);
+    assert!(breakers.allows(&host));
+    breakers.record_success(&host);
+    assert_eq!(breakers.state(&host), State::Closed);
+}
+
+#[lunatic::test]
+fn test_circuit_failure_while_open_keeps_open_duration() {
+    let host = HostRef::Http("localhost:80".to_string());
+    let mut breakers = Breakers::new(
+        Policy::default()
+            .consecutive_failures(1)
+            .open_duration(Duration::from_secs(60)),
+    );
+
+    breakers.record_failure(&host);
+    let opened_at = breakers.hosts[&host].opened_at;
+    lunatic::sleep(Duration::from_millis(5));
+    breakers.record_failure(&host);
+    assert_eq!(breakers.state(&host), State::Open);
+    assert_eq!(breakers.hosts[&host].opened_at, opened_at);
+}
@@ -2,12 +2,18 @@
 
 use std::convert::TryInto;
 use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 use std::sync::RwLock;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::header::{HeaderValue, SET_COOKIE};
 use bytes::Bytes;
 use http::HeaderMap;
+use lunatic::abstract_process;
+use lunatic::ap::{AbstractProcess, Config, ProcessRef};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Actions for a persistent cookie store providing session support.
 pub trait CookieStore: Send + Sync {
@@ -26,12 +32,93 @@ pub struct Cookie<'a>(cookie_crate::Cookie<'a>);
 /// This type is exposed to allow creating one and filling it with some
 /// existing cookies more easily, before creating a `Client`.
 ///
-/// For more advanced scenarios, such as needing to serialize the store or
-/// manipulate it between requests, you may refer to the
-/// [reqwest_cookie_store crate](https://crates.io/crates/reqwest_cookie_store).
+/// A `Jar` is serializable, so that it can be passed to the client process.
+/// The client then works on its own copy of the jar. To share cookies
+/// between several clients, use a [`SharedJar`] instead.
 #[derive(Debug, Default)]
 pub struct Jar(RwLock<cookie_store::CookieStore>);
 
+/// A `Jar` living in a process of its own, which several `Client`s can use at
+/// the same time.
+///
+/// Like a `Client`, a `SharedJar` only holds a reference to its process, so
+/// it can be cloned and sent to other processes. The process is linked to
+/// the process that created the `SharedJar`.
+///
+/// # Example
+///
+/// ```rust
+/// # fn run() -> Result<(), nightfly::Error> {
+/// use nightfly::cookie::{Jar, SharedJar};
+///
+/// let jar = SharedJar::new(Jar::default());
+/// let first = nightfly::Client::builder()
+///     .shared_cookie_provider(jar.clone())
+///     .build()?;
+/// let second = nightfly::Client::builder()
+///     .shared_cookie_provider(jar)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SharedJar(ProcessRef<JarProcess>);
+
+/// The process behind a [`SharedJar`].
+pub struct JarProcess(Jar);
+
+/// A cookie held by a cookie store.
+///
+/// Returned by `Client::cookies()`, and used to add cookies to a store with
+/// `Client::insert_cookie()`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredCookie {
+    /// The name of the cookie.
+    pub name: String,
+    /// The value of the cookie.
+    pub value: String,
+    /// The domain the cookie is sent to.
+    pub domain: String,
+    /// Whether the cookie is only sent to `domain` itself, and not to its
+    /// subdomains.
+    pub host_only: bool,
+    /// The path the cookie is sent to.
+    pub path: String,
+    /// Whether the cookie is only sent over HTTPS.
+    pub secure: bool,
+    /// Whether the 'HttpOnly' directive is enabled.
+    pub http_only: bool,
+    /// When the cookie expires, or `None` if it's a session cookie.
+    pub expires: Option<SystemTime>,
+}
+
+/// Selects the cookies of a store by name, domain or path.
+///
+/// An empty filter selects all cookies.
+///
+/// # Example
+///
+/// ```rust
+/// use nightfly::cookie::CookieFilter;
+///
+/// let client = nightfly::Client::new();
+/// let cookies = client.cookies(CookieFilter::new().domain("example.com"));
+/// client.delete_cookies(CookieFilter::new().name("session"));
+/// ```
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CookieFilter {
+    name: Option<String>,
+    domain: Option<String>,
+    path: Option<String>,
+}
+
+/// The cookie store a client was configured with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) enum Provider {
+    Jar(Jar),
+    Shared(SharedJar),
+}
+
 // ===== impl Cookie =====
 
 impl<'a> Cookie<'a> {
@@ -162,28 +249,617 @@ impl Jar {
     }
 }
 
-impl CookieStore for Jar {
-    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &url::Url) {
-        let iter =
-            cookie_headers.filter_map(|val| Cookie::parse(val).map(|c| c.0.into_owned()).ok());
+impl Jar {
+    /// Returns the unexpired cookies selected by `filter`.
+    pub fn list(&self, filter: &CookieFilter) -> Vec<StoredCookie> {
+        self.0
+            .read()
+            .unwrap()
+            .iter_unexpired()
+            .map(StoredCookie::from_store)
+            .filter(|cookie| filter.matches(cookie))
+            .collect()
+    }
+
+    /// Add a cookie to this jar, as if it was received from its domain and
+    /// path.
+    pub fn insert(&self, cookie: StoredCookie) -> crate::Result<()> {
+        let url = cookie.url()?;
+        self.0
+            .write()
+            .unwrap()
+            .insert_raw(&cookie.to_raw(), &url)
+            .map(|_| ())
+            .map_err(crate::error::builder)
+    }
 
-        self.0.write().unwrap().store_response_cookies(iter, url);
+    /// Removes the cookies selected by `filter` and returns how many were
+    /// removed.
+    pub fn remove(&self, filter: &CookieFilter) -> usize {
+        self.remove_where(|cookie| filter.matches(&StoredCookie::from_store(cookie)))
     }
 
-    fn cookies(&self, url: &url::Url) -> Option<HeaderValue> {
-        let s = self
-            .0
+    /// Removes expired cookies and returns how many were removed.
+    pub fn clear_expired(&self) -> usize {
+        self.remove_where(|cookie| cookie.is_expired())
+    }
+
+    /// Removes session cookies, those without an expiry time, and returns
+    /// how many were removed.
+    pub fn clear_session(&self) -> usize {
+        self.remove_where(|cookie| !cookie.is_persistent())
+    }
+
+    /// Removes all cookies.
+    pub fn clear(&self) {
+        self.0.write().unwrap().clear();
+    }
+
+    /// Writes the unexpired persistent cookies of this jar to `writer` as
+    /// JSON, one cookie per line.
+    pub fn save_json<W: Write>(&self, writer: &mut W) -> crate::Result<()> {
+        self.0
             .read()
             .unwrap()
-            .get_request_values(url)
-            .map(|(name, value)| format!("{}={}", name, value))
-            .collect::<Vec<_>>()
-            .join("; ");
+            .save_json(writer)
+            .map_err(crate::error::serialization)
+    }
+
+    /// Reads a jar written by `save_json`, skipping expired cookies.
+    pub fn load_json<R: BufRead>(reader: R) -> crate::Result<Jar> {
+        cookie_store::CookieStore::load_json(reader)
+            .map(|store| Jar(RwLock::new(store)))
+            .map_err(crate::error::serialization)
+    }
+
+    /// Writes the unexpired cookies of this jar to `writer` in the Netscape
+    /// `cookies.txt` format used by curl and browsers.
+    ///
+    /// Like curl, session cookies are written with an expiry time of `0`.
+    pub fn save_netscape<W: Write>(&self, writer: &mut W) -> crate::Result<()> {
+        writeln!(writer, "# Netscape HTTP Cookie File").map_err(crate::error::serialization)?;
+        for cookie in self.list(&CookieFilter::new()) {
+            writeln!(writer, "{}", cookie.to_netscape()).map_err(crate::error::serialization)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a jar from the Netscape `cookies.txt` format, skipping expired
+    /// cookies and cookies the jar doesn't accept, like those for a public
+    /// suffix.
+    pub fn load_netscape<R: BufRead>(reader: R) -> crate::Result<Jar> {
+        let jar = Jar::default();
+        for line in reader.lines() {
+            let line = line.map_err(crate::error::serialization)?;
+            if let Some(cookie) = StoredCookie::from_netscape(&line)? {
+                if cookie
+                    .expires
+                    .map_or(true, |expires| expires > SystemTime::now())
+                {
+                    insert_or_skip(cookie, |cookie| jar.insert(cookie));
+                }
+            }
+        }
+        Ok(jar)
+    }
 
-        if s.is_empty() {
-            return None;
+    fn remove_where<F>(&self, f: F) -> usize
+    where
+        F: Fn(&cookie_store::Cookie<'static>) -> bool,
+    {
+        let mut store = self.0.write().unwrap();
+        let remove = store
+            .iter_any()
+            .filter(|cookie| f(cookie))
+            .map(|cookie| {
+                (
+                    String::from(&cookie.domain),
+                    String::from(&cookie.path),
+                    cookie.name().to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        for (domain, path, name) in &remove {
+            store.remove(domain, path, name);
         }
+        remove.len()
+    }
+}
+
+impl Clone for Jar {
+    fn clone(&self) -> Self {
+        let store = self.0.read().unwrap();
+        Jar(RwLock::new(store_from_cookies(
+            store.iter_unexpired().cloned(),
+        )))
+    }
+}
+
+impl Serialize for Jar {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // unlike `cookie_store::CookieStore`, session cookies are kept, as the
+        // jar is usually serialized to be used by the client process
+        serializer.collect_seq(self.0.read().unwrap().iter_unexpired())
+    }
+}
+
+impl<'de> Deserialize<'de> for Jar {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let cookies = Vec::<cookie_store::Cookie<'static>>::deserialize(deserializer)?;
+        Ok(Jar(RwLock::new(store_from_cookies(cookies))))
+    }
+}
+
+/// Inserts a loaded cookie with `insert`, logging it instead of failing if
+/// it's rejected, so that one cookie doesn't keep the others from loading.
+fn insert_or_skip<F>(cookie: StoredCookie, insert: F)
+where
+    F: FnOnce(StoredCookie) -> crate::Result<()>,
+{
+    let name = cookie.name.clone();
+    let domain = cookie.domain.clone();
+    if let Err(e) = insert(cookie) {
+        lunatic_log::warn!("Skipping cookie {:?} for {:?}: {}", name, domain, e);
+    }
+}
+
+fn store_from_cookies<I>(cookies: I) -> cookie_store::CookieStore
+where
+    I: IntoIterator<Item = cookie_store::Cookie<'static>>,
+{
+    let mut store = cookie_store::CookieStore::default();
+    for cookie in cookies {
+        // the cookie was accepted for its domain and path before, so it is
+        // accepted again from a URL made of them
+        let url = format!(
+            "http://{}{}",
+            String::from(&cookie.domain),
+            String::from(&cookie.path)
+        );
+        if let Ok(url) = url.parse() {
+            let _ = store.insert(cookie, &url);
+        }
+    }
+    store
+}
+
+// ===== impl StoredCookie =====
+
+impl StoredCookie {
+    /// Creates a session cookie for `domain` and all of its paths.
+    pub fn new(
+        name: impl Into<String>,
+        value: impl Into<String>,
+        domain: impl Into<String>,
+    ) -> StoredCookie {
+        StoredCookie {
+            name: name.into(),
+            value: value.into(),
+            domain: domain.into(),
+            host_only: true,
+            path: "/".to_string(),
+            secure: false,
+            http_only: false,
+            expires: None,
+        }
+    }
+
+    fn from_store(cookie: &cookie_store::Cookie<'static>) -> StoredCookie {
+        let raw = cookie_crate::Cookie::from(cookie.clone());
+        StoredCookie {
+            name: cookie.name().to_string(),
+            value: cookie.value().to_string(),
+            domain: String::from(&cookie.domain),
+            // only cookies set with a Domain attribute are sent to subdomains
+            host_only: raw.domain().is_none(),
+            path: String::from(&cookie.path),
+            secure: cookie.secure().unwrap_or(false),
+            http_only: cookie.http_only().unwrap_or(false),
+            expires: match raw.expires() {
+                Some(cookie_crate::Expiration::DateTime(offset)) => Some(SystemTime::from(offset)),
+                None | Some(cookie_crate::Expiration::Session) => None,
+            },
+        }
+    }
+
+    fn to_raw(&self) -> cookie_crate::Cookie<'static> {
+        let mut raw = cookie_crate::Cookie::build(self.name.clone(), self.value.clone())
+            .path(self.path.clone())
+            .secure(self.secure)
+            .http_only(self.http_only);
+        if !self.host_only {
+            raw = raw.domain(self.domain.clone());
+        }
+        if let Some(expires) = self.expires {
+            raw = raw.expires(time::OffsetDateTime::from(expires));
+        }
+        raw.finish()
+    }
+
+    fn to_netscape(&self) -> String {
+        let flag = |enabled| if enabled { "TRUE" } else { "FALSE" };
+        let expires = self
+            .expires
+            .and_then(|expires| expires.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |expires| expires.as_secs());
+        format!(
+            "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            if self.http_only { "#HttpOnly_" } else { "" },
+            if self.host_only { "" } else { "." },
+            self.domain,
+            flag(!self.host_only),
+            self.path,
+            flag(self.secure),
+            expires,
+            self.name,
+            self.value
+        )
+    }
+
+    /// Parses a line of a `cookies.txt` file, which may be a comment.
+    fn from_netscape(line: &str) -> crate::Result<Option<StoredCookie>> {
+        let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let invalid = || crate::error::serialization(format!("invalid cookies.txt line: {}", line));
+        let fields = line.split('\t').collect::<Vec<_>>();
+        if fields.len() != 7 {
+            return Err(invalid());
+        }
+        let expires = fields[4].parse::<u64>().map_err(|_| invalid())?;
+        Ok(Some(StoredCookie {
+            name: fields[5].to_string(),
+            value: fields[6].to_string(),
+            domain: fields[0].trim_start_matches('.').to_string(),
+            host_only: !fields[1].eq_ignore_ascii_case("TRUE"),
+            path: fields[2].to_string(),
+            secure: fields[3].eq_ignore_ascii_case("TRUE"),
+            http_only,
+            expires: match expires {
+                0 => None,
+                secs => Some(UNIX_EPOCH + Duration::from_secs(secs)),
+            },
+        }))
+    }
+
+    /// The URL the cookie counts as received from.
+    fn url(&self) -> crate::Result<url::Url> {
+        let scheme = if self.secure { "https" } else { "http" };
+        format!("{}://{}{}", scheme, self.domain, self.path)
+            .parse()
+            .map_err(crate::error::builder)
+    }
+}
+
+// ===== impl CookieFilter =====
+
+impl CookieFilter {
+    /// Creates a filter selecting all cookies.
+    pub fn new() -> CookieFilter {
+        CookieFilter::default()
+    }
+
+    /// Select the cookies with the given name.
+    pub fn name(mut self, name: impl Into<String>) -> CookieFilter {
+        self.name = Some(name.into());
+        self
+    }
 
+    /// Select the cookies of `domain` and its subdomains.
+    pub fn domain(mut self, domain: impl Into<String>) -> CookieFilter {
+        self.domain = Some(domain.into().trim_start_matches('.').to_ascii_lowercase());
+        self
+    }
+
+    /// Select the cookies of `path` and the paths below it.
+    pub fn path(mut self, path: impl Into<String>) -> CookieFilter {
+        self.path = Some(path.into());
+        self
+    }
+
+    fn matches(&self, cookie: &StoredCookie) -> bool {
+        let name = self.name.as_ref().map_or(true, |name| cookie.name == *name);
+        let domain = self.domain.as_ref().map_or(true, |domain| {
+            cookie.domain == *domain
+                || cookie
+                    .domain
+                    .strip_suffix(domain.as_str())
+                    .map_or(false, |sub| sub.ends_with('.'))
+        });
+        let path = self.path.as_ref().map_or(true, |path| {
+            cookie.path == *path
+                || cookie
+                    .path
+                    .strip_prefix(path.as_str())
+                    .map_or(false, |rest| path.ends_with('/') || rest.starts_with('/'))
+        });
+        name && domain && path
+    }
+}
+
+// ===== impl SharedJar =====
+
+impl SharedJar {
+    /// Spawns a process holding `jar`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the process cannot be spawned.
+    pub fn new(jar: Jar) -> SharedJar {
+        let proc = JarProcess::link()
+            .start(jar)
+            .expect("failed to spawn cookie jar");
+        SharedJar(proc)
+    }
+}
+
+impl CookieStore for SharedJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &url::Url) {
+        let cookies = cookie_headers
+            .filter_map(|val| val.to_str().ok())
+            .map(String::from)
+            .collect();
+        self.0.store_cookies((cookies, url.clone()));
+    }
+
+    fn cookies(&self, url: &url::Url) -> Option<HeaderValue> {
+        let s = self.0.request_cookies(url.clone())?;
         HeaderValue::from_maybe_shared(Bytes::from(s)).ok()
     }
 }
+
+#[abstract_process(visibility = pub)]
+impl JarProcess {
+    #[init]
+    fn init(_: Config<Self>, jar: Jar) -> Result<Self, ()> {
+        Ok(JarProcess(jar))
+    }
+
+    #[handle_message]
+    fn store_cookies(&mut self, cookies: (Vec<String>, url::Url)) {
+        let (cookies, url) = cookies;
+        for cookie in cookies {
+            self.0.add_cookie_str(&cookie, &url);
+        }
+    }
+
+    #[handle_request]
+    fn request_cookies(&mut self, url: url::Url) -> Option<String> {
+        self.0
+            .cookies(&url)
+            .and_then(|value| value.to_str().ok().map(String::from))
+    }
+
+    #[handle_request]
+    fn list_cookies(&mut self, filter: CookieFilter) -> Vec<StoredCookie> {
+        self.0.list(&filter)
+    }
+
+    #[handle_request]
+    fn insert_cookie(&mut self, cookie: StoredCookie) -> crate::Result<()> {
+        self.0.insert(cookie)
+    }
+
+    #[handle_request]
+    fn remove_cookies(&mut self, filter: CookieFilter) -> usize {
+        self.0.remove(&filter)
+    }
+
+    #[handle_request]
+    fn clear_expired_cookies(&mut self) -> usize {
+        self.0.clear_expired()
+    }
+
+    #[handle_request]
+    fn clear_session_cookies(&mut self) -> usize {
+        self.0.clear_session()
+    }
+
+    #[handle_request]
+    fn clear_cookies(&mut self) {
+        self.0.clear()
+    }
+
+    #[handle_request]
+    fn export_json(&mut self) -> crate::Result<Vec<u8>> {
+        let mut json = Vec::new();
+        self.0.save_json(&mut json)?;
+        Ok(json)
+    }
+}
+
+// ===== impl Provider =====
+
+impl Provider {
+    pub(crate) fn list(&self, filter: CookieFilter) -> Vec<StoredCookie> {
+        match self {
+            Provider::Jar(jar) => jar.list(&filter),
+            Provider::Shared(jar) => jar.0.list_cookies(filter),
+        }
+    }
+
+    pub(crate) fn insert(&self, cookie: StoredCookie) -> crate::Result<()> {
+        match self {
+            Provider::Jar(jar) => jar.insert(cookie),
+            Provider::Shared(jar) => jar.0.insert_cookie(cookie),
+        }
+    }
+
+    pub(crate) fn remove(&self, filter: CookieFilter) -> usize {
+        match self {
+            Provider::Jar(jar) => jar.remove(&filter),
+            Provider::Shared(jar) => jar.0.remove_cookies(filter),
+        }
+    }
+
+    pub(crate) fn clear_expired(&self) -> usize {
+        match self {
+            Provider::Jar(jar) => jar.clear_expired(),
+            Provider::Shared(jar) => jar.0.clear_expired_cookies(),
+        }
+    }
+
+    pub(crate) fn clear_session(&self) -> usize {
+        match self {
+            Provider::Jar(jar) => jar.clear_session(),
+            Provider::Shared(jar) => jar.0.clear_session_cookies(),
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        match self {
+            Provider::Jar(jar) => jar.clear(),
+            Provider::Shared(jar) => jar.0.clear_cookies(),
+        }
+    }
+
+    /// Adds the cookies saved at `path` by `save_to`, if the file exists.
+    pub(crate) fn load_from(&self, path: &Path) -> crate::Result<()> {
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(crate::error::serialization(e)),
+        };
+        let saved = Jar::load_json(BufReader::new(file))?;
+        for cookie in saved.list(&CookieFilter::new()) {
+            insert_or_skip(cookie, |cookie| self.insert(cookie));
+        }
+        Ok(())
+    }
+
+    /// Saves the persistent cookies to `path` as JSON.
+    pub(crate) fn save_to(&self, path: &Path) -> crate::Result<()> {
+        let json = match self {
+            Provider::Jar(jar) => {
+                let mut json = Vec::new();
+                jar.save_json(&mut json)?;
+                json
+            }
+            Provider::Shared(jar) => jar.0.export_json()?,
+        };
+        // replace the file at once, so that it's never left half written,
+        // from a file no other client process writes to
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(format!(
+            ".{}-{}.tmp",
+            lunatic::host::node_id(),
+            lunatic::host::process_id()
+        ));
+        let tmp = PathBuf::from(tmp);
+        fs::write(&tmp, json)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(crate::error::serialization)
+    }
+}
+
+impl CookieStore for Provider {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &url::Url) {
+        match self {
+            Provider::Jar(jar) => jar.set_cookies(cookie_headers, url),
+            Provider::Shared(jar) => jar.set_cookies(cookie_headers, url),
+        }
+    }
+
+    fn cookies(&self, url: &url::Url) -> Option<HeaderValue> {
+        match self {
+            Provider::Jar(jar) => jar.cookies(url),
+            Provider::Shared(jar) => jar.cookies(url),
+        }
+    }
+}
+
+#[lunatic::test]
+fn test_jar_filter_and_clear() {
+    let url = "http://example.com/account/login".parse().unwrap();
+    let jar = Jar::default();
+    jar.add_cookie_str("session=1; Path=/", &url);
+    jar.add_cookie_str("theme=dark; Path=/account; Max-Age=3600", &url);
+    jar.add_cookie_str("lang=en; Domain=example.com", &url);
+
+    assert_eq!(
+        jar.list(&CookieFilter::new().domain("example.com")).len(),
+        3
+    );
+    assert_eq!(
+        jar.list(&CookieFilter::new().domain("www.example.com"))
+            .len(),
+        0
+    );
+    assert_eq!(jar.list(&CookieFilter::new().path("/account")).len(), 2);
+    let lang = jar.list(&CookieFilter::new().name("lang"));
+    assert_eq!(lang.len(), 1);
+    assert!(!lang[0].host_only);
+    assert_eq!(lang[0].path, "/account");
+
+    // `session` and `lang` have no expiry time
+    assert_eq!(jar.clear_session(), 2);
+    assert_eq!(jar.remove(&CookieFilter::new().name("theme")), 1);
+    assert!(jar.list(&CookieFilter::new()).is_empty());
+}
+
+#[lunatic::test]
+fn test_jar_insert_and_serialize() {
+    let jar = Jar::default();
+    let mut cookie = StoredCookie::new("id", "42", "example.com");
+    cookie.secure = true;
+    jar.insert(cookie.clone()).unwrap();
+    assert_eq!(jar.list(&CookieFilter::new()), vec![cookie.clone()]);
+
+    // session cookies are kept when the jar is sent to another process
+    let copy: Jar = serde_json::from_str(&serde_json::to_string(&jar).unwrap()).unwrap();
+    assert_eq!(copy.list(&CookieFilter::new()), vec![cookie]);
+}
+
+#[lunatic::test]
+fn test_jar_netscape_and_json() {
+    let txt = "# Netscape HTTP Cookie File\n\
+               .example.com\tTRUE\t/\tFALSE\t4102444800\tlang\ten\n\
+               #HttpOnly_example.com\tFALSE\t/account\tTRUE\t0\tsession\tabc\n\
+               example.com\tFALSE\t/\tFALSE\t1\texpired\tyes\n";
+    let jar = Jar::load_netscape(txt.as_bytes()).unwrap();
+    let lang = jar.list(&CookieFilter::new().name("lang")).remove(0);
+    assert!(!lang.host_only);
+    assert_eq!(
+        lang.expires,
+        Some(UNIX_EPOCH + Duration::from_secs(4102444800))
+    );
+    let session = jar.list(&CookieFilter::new().name("session")).remove(0);
+    assert!(session.host_only && session.secure && session.http_only);
+    assert_eq!(session.expires, None);
+    assert_eq!(jar.list(&CookieFilter::new()).len(), 2);
+
+    let mut out = Vec::new();
+    jar.save_netscape(&mut out).unwrap();
+    let copy = Jar::load_netscape(&out[..]).unwrap();
+    assert_eq!(copy.list(&CookieFilter::new()).len(), 2);
+
+    // only persistent cookies are saved as JSON
+    let mut json = Vec::new();
+    jar.save_json(&mut json).unwrap();
+    let copy = Jar::load_json(&json[..]).unwrap();
+    assert_eq!(copy.list(&CookieFilter::new()), vec![lang]);
+
+    assert!(Jar::load_netscape("example.com\tFALSE\t/".as_bytes()).is_err());
+}
+
+#[lunatic::test]
+fn test_jar_skips_rejected_cookies() {
+    // the domain isn't valid in a URL, so the jar can't accept the cookie
+    let txt = "exa mple.com\tFALSE\t/\tFALSE\t0\tbroken\tyes\n\
+               example.com\tFALSE\t/\tFALSE\t0\tsession\tabc\n";
+    let jar = Jar::load_netscape(txt.as_bytes()).unwrap();
+    let cookies = jar.list(&CookieFilter::new());
+    assert_eq!(cookies.len(), 1);
+    assert_eq!(cookies[0].name, "session");
+
+    // values aren't cut off at characters that delimit attributes
+    let cookie = StoredCookie::new("id", "a;b=c", "example.com");
+    jar.insert(cookie.clone()).unwrap();
+    assert_eq!(jar.list(&CookieFilter::new().name("id")), vec![cookie]);
+}
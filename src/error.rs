@@ -119,6 +119,12 @@ impl Error {
 
     /// Returns true if the error is related to a timeout.
     pub fn is_timeout(&self) -> bool {
+        // the kind tells it after the error was sent between processes,
+        // which drops the source
+        if matches!(self.inner.kind, Kind::Timeout | Kind::QueueTimeout) {
+            return true;
+        }
+
         let mut source = self.source();
 
         while let Some(err) = source {
@@ -131,6 +137,17 @@ impl Error {
         false
     }
 
+    /// Returns true if the request timed out while waiting for a free
+    /// connection slot.
+    pub fn is_queue_timeout(&self) -> bool {
+        matches!(self.inner.kind, Kind::QueueTimeout)
+    }
+
+    /// Returns true if the error is related to connecting to the server.
+    pub fn is_connect(&self) -> bool {
+        matches!(self.inner.kind, Kind::Connect)
+    }
+
     /// Returns true if the error is related to the request
     pub fn is_request(&self) -> bool {
         matches!(self.inner.kind, Kind::Request)
@@ -151,6 +168,28 @@ impl Error {
         matches!(self.inner.kind, Kind::Decode)
     }
 
+    /// Returns true if the error is caused by an unsupported HTTP version.
+    pub fn is_version(&self) -> bool {
+        matches!(self.inner.kind, Kind::Version)
+    }
+
+    /// Returns true if the request was rejected because the circuit breaker
+    /// of the host is open.
+    pub fn is_circuit_open(&self) -> bool {
+        matches!(self.inner.kind, Kind::CircuitOpen)
+    }
+
+    /// Returns true if the request was rejected by the rate limiter.
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(self.inner.kind, Kind::RateLimited)
+    }
+
+    /// Returns true if the HTTP message signature of a response is missing
+    /// or invalid.
+    pub fn is_signature(&self) -> bool {
+        matches!(self.inner.kind, Kind::Signature)
+    }
+
     /// Returns the status code, if the error was generated from a response.
     pub fn status(&self) -> Option<StatusCode> {
         match self.inner.kind {
@@ -161,6 +200,31 @@ impl Error {
 
     // private
 
+    /// Returns a short name for the kind of the error, used as a label in
+    /// the metrics of a `Client`.
+    pub(crate) fn kind_name(&self) -> &'static str {
+        // a queue timeout is a timeout too, but counted on its own
+        if !self.is_queue_timeout() && self.is_timeout() {
+            return "timeout";
+        }
+        match self.inner.kind {
+            Kind::Builder => "builder",
+            Kind::Request => "request",
+            Kind::Timeout => "timeout",
+            Kind::Connect => "connect",
+            Kind::Redirect => "redirect",
+            Kind::Status(_) => "status",
+            Kind::Body => "body",
+            Kind::Decode => "decode",
+            Kind::Serialization => "serialization",
+            Kind::Version => "version",
+            Kind::CircuitOpen => "circuit_open",
+            Kind::RateLimited => "rate_limited",
+            Kind::QueueTimeout => "queue_timeout",
+            Kind::Signature => "signature",
+        }
+    }
+
     #[allow(unused)]
     pub(crate) fn into_io(self) -> io::Error {
         io::Error::new(io::ErrorKind::Other, self)
@@ -189,10 +253,17 @@ impl fmt::Display for Error {
         match self.inner.kind {
             Kind::Builder => f.write_str("builder error")?,
             Kind::Request => f.write_str("error sending request")?,
+            Kind::Timeout => f.write_str("request timed out")?,
+            Kind::Connect => f.write_str("error connecting to server")?,
             Kind::Body => f.write_str("request or response body error")?,
             Kind::Decode => f.write_str("error decoding response body")?,
             Kind::Redirect => f.write_str("error following redirect")?,
             Kind::Serialization => f.write_str("error while serialising body")?,
+            Kind::Version => f.write_str("unsupported HTTP version")?,
+            Kind::CircuitOpen => f.write_str("circuit breaker is open")?,
+            Kind::RateLimited => f.write_str("rate limit exceeded")?,
+            Kind::QueueTimeout => f.write_str("error waiting for a free connection")?,
+            Kind::Signature => f.write_str("error verifying message signature")?,
             // Kind::Upgrade => f.write_str("error upgrading connection")?,
             Kind::Status(ref code) => {
                 let status = StatusCode::from_u16(*code).unwrap();
@@ -228,11 +299,18 @@ impl StdError for Error {
 pub(crate) enum Kind {
     Builder,
     Request,
+    Timeout,
+    Connect,
     Redirect,
     Status(u16),
     Body,
     Decode,
     Serialization,
+    Version,
+    CircuitOpen,
+    RateLimited,
+    QueueTimeout,
+    Signature,
     // Upgrade,
 }
 
@@ -258,14 +336,38 @@ pub(crate) fn request<E: Into<BoxError>>(e: E) -> Error {
     Error::new(Kind::Request, Some(e))
 }
 
+pub(crate) fn version<E: Into<BoxError>>(e: E) -> Error {
+    Error::new(Kind::Version, Some(e))
+}
+
+pub(crate) fn connect<E: Into<BoxError>>(e: E) -> Error {
+    Error::new(Kind::Connect, Some(e))
+}
+
+pub(crate) fn circuit_open(url: Url) -> Error {
+    Error::new(Kind::CircuitOpen, None::<Error>).with_url(url)
+}
+
+pub(crate) fn rate_limited(url: Url) -> Error {
+    Error::new(Kind::RateLimited, None::<Error>).with_url(url)
+}
+
+pub(crate) fn queue_timeout(url: Url) -> Error {
+    Error::new(Kind::QueueTimeout, Some(TimedOut)).with_url(url)
+}
+
 pub(crate) fn timeout(url: Url) -> Error {
-    Error::new(Kind::Request, Some(TimedOut)).with_url(url)
+    Error::new(Kind::Timeout, Some(TimedOut)).with_url(url)
 }
 
 pub(crate) fn redirect<E: Into<BoxError>>(e: E, url: Url) -> Error {
     Error::new(Kind::Redirect, Some(e)).with_url(url)
 }
 
+pub(crate) fn signature<E: Into<BoxError>>(e: E, url: Url) -> Error {
+    Error::new(Kind::Signature, Some(e)).with_url(url)
+}
+
 pub(crate) fn status_code(url: Url, status: StatusCode) -> Error {
     Error::new(Kind::Status(status.as_u16()), None::<Error>).with_url(url)
 }
@@ -350,4 +452,21 @@ mod tests {
         let nested = super::request(io);
         assert!(nested.is_timeout());
     }
+
+    #[lunatic::test]
+    fn is_timeout_after_serialization() {
+        let url = Url::parse("http://localhost:3000/api").unwrap();
+        for err in vec![super::timeout(url.clone()), super::queue_timeout(url)] {
+            let json = serde_json::to_string(&err).unwrap();
+            let err: Error = serde_json::from_str(&json).unwrap();
+            assert!(err.is_timeout());
+        }
+    }
+
+    #[lunatic::test]
+    fn kind_name_of_timeouts() {
+        let url = Url::parse("http://localhost:3000/api").unwrap();
+        assert_eq!(super::timeout(url.clone()).kind_name(), "timeout");
+        assert_eq!(super::queue_timeout(url).kind_name(), "queue_timeout");
+    }
 }
@@ -111,10 +111,17 @@
 //! maximum redirect chain of 10 hops. To customize this behavior, a
 //! [`redirect::Policy`][redirect] can be used with a `ClientBuilder`.
 //!
+//! ## Retries
+//!
+//! Failed requests are not retried by default. A [`retry::Policy`][retry] can
+//! be set on a `ClientBuilder` or a single request to retry connection errors
+//! and responses like `503 Service Unavailable` with exponential backoff.
+//!
 //! ## Cookies
 //!
-//! The automatic storing and sending of session cookies can be enabled with
-//! the [`cookie_store`][ClientBuilder::cookie_store] method on `ClientBuilder`.
+//! Cookies are stored and sent automatically. This can be turned off with
+//! the [`cookie_store`][ClientBuilder::cookie_store] method on `ClientBuilder`,
+//! and a jar can be shared between clients with a [`cookie::SharedJar`].
 //!
 //! ## Optional Features
 //!
@@ -130,6 +137,7 @@
 //! [builder]: ./struct.RequestBuilder.html
 //! [serde]: http://serde.rs
 //! [redirect]: crate::redirect
+//! [retry]: crate::retry
 //! [cargo-features]: https://doc.rust-lang.org/stable/cargo/reference/manifest.html#the-features-section
 
 pub use http::header;
@@ -205,16 +213,24 @@ fn _assert_impls() {
 // #[cfg(feature = "multipart")]
 // pub use self::lunatic_impl::multipart;
 pub use self::lunatic_impl::{
-    Body, Client, ClientBuilder, HttpResponse, Request, RequestBuilder, SerializableResponse,
+    Body, Client, ClientBuilder, HttpInfo, HttpResponse, QueueStatus, Request, RequestBuilder,
+    SerializableResponse, Timings,
 };
 #[cfg(feature = "__tls")]
 // Re-exports, to be removed in a future release
 pub use tls::{Certificate, Identity};
 
+pub mod auth;
+pub mod cache;
+pub mod circuit;
 #[cfg(feature = "cookies")]
 pub mod cookie;
 mod lunatic_impl;
+pub mod metrics;
+pub mod middleware;
+pub mod rate_limit;
 pub mod redirect;
+pub mod retry;
 #[cfg(feature = "__tls")]
 pub mod tls;
 mod util;
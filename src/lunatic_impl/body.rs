@@ -77,6 +77,11 @@ impl Body {
         self.0.is_empty()
     }
 
+    /// borrow body
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
     /// retrieve body
     pub fn inner(self) -> Vec<u8> {
         self.0
@@ -1,29 +1,34 @@
 use core::fmt;
-#[cfg(feature = "cookies")]
-use std::sync::Arc;
 use std::{
     collections::HashMap,
     convert::TryInto,
     net::{IpAddr, SocketAddr},
+    path::PathBuf,
     time::Duration,
 };
 
 use http::{
-    header::{ACCEPT, USER_AGENT},
+    header::{ACCEPT, LOCATION, SET_COOKIE, USER_AGENT},
     HeaderMap, HeaderValue,
 };
-use lunatic::AbstractProcess;
-use serde::{Deserialize, Serialize};
+use lunatic::{
+    ap::{ProcessRef, StartupError},
+    AbstractProcess,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 #[cfg(feature = "cookies")]
-use crate::cookie::Jar;
+use crate::cookie::{self, Jar, SharedJar};
 
 use crate::{
+    auth::{self, oauth2::TokenSource},
+    cache, circuit,
     lunatic_impl::{decoder::Accepts, request::header_map_from_hashmap},
-    redirect, Client,
+    metrics::Metrics,
+    middleware, rate_limit, redirect, retry, Client,
 };
 
-use super::InnerClient;
+use super::{queue::AdmissionProcess, CallerConfig, InnerClient};
 
 /// A `ClientBuilder` can be used to create a `Client` with custom configuration.
 #[must_use]
@@ -32,6 +37,19 @@ pub struct ClientBuilder {
     pub(crate) config: Config,
 }
 
+/// The argument the client process is started with.
+///
+/// `ClientBuilder::build()` starts the middleware and the redirect rules in
+/// processes of their own and sends them along in this type, which nothing
+/// else creates.
+#[derive(Serialize, Deserialize)]
+pub struct Spawn {
+    builder: ClientBuilder,
+    middleware: middleware::Stack,
+    redirect_rules: redirect::Rules,
+    admission: Option<ProcessRef<AdmissionProcess>>,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 enum HttpVersionPref {
     Http1,
@@ -52,13 +70,26 @@ pub(crate) struct Config {
     connection_verbose: bool,
     pool_idle_timeout: Option<Duration>,
     pool_max_idle_per_host: usize,
+    max_connections_per_host: Option<usize>,
+    max_in_flight: Option<usize>,
+    queue_timeout: Option<Duration>,
     tcp_keepalive: Option<Duration>,
     #[cfg(any(feature = "native-tls", feature = "__rustls"))]
     identity: Option<Identity>,
     // proxies: Vec<Proxy>,
     // auto_sys_proxy: bool,
     redirect_policy: redirect::Policy,
+    // not serialized, `ClientBuilder::build()` starts them and sends them
+    // to the client process in a `Spawn`
+    #[serde(skip)]
+    redirect_rules: HashMap<String, redirect::Registered>,
+    redirect_history_headers: Vec<String>,
     referer: bool,
+    retry_policy: Option<retry::Policy>,
+    circuit_policy: Option<circuit::Policy>,
+    rate_limit_policy: Option<rate_limit::Policy>,
+    middleware: Vec<middleware::Registered>,
+    oauth2: Option<auth::OAuth2>,
     timeout: Option<Duration>,
     #[cfg(feature = "__tls")]
     root_certs: Vec<Certificate>,
@@ -71,6 +102,7 @@ pub(crate) struct Config {
     http_version_pref: HttpVersionPref,
     http09_responses: bool,
     http1_title_case_headers: bool,
+    http1_preserve_header_case: bool,
     http1_allow_obsolete_multiline_headers_in_responses: bool,
     http2_initial_stream_window_size: Option<u32>,
     http2_initial_connection_window_size: Option<u32>,
@@ -81,8 +113,10 @@ pub(crate) struct Config {
     http2_keep_alive_while_idle: bool,
     local_address: Option<IpAddr>,
     nodelay: bool,
-    // #[cfg(feature = "cookies")]
-    // cookie_store: Option<Arc<Jar>>,
+    #[cfg(feature = "cookies")]
+    cookie_store: Option<cookie::Provider>,
+    #[cfg(feature = "cookies")]
+    cookie_autosave: Option<PathBuf>,
     // trust_dns: bool,
     error: Option<crate::Error>,
     https_only: bool,
@@ -94,12 +128,15 @@ impl Config {
         // Instead of deriving Debug, only print fields when their output
         // would provide relevant or interesting data.
 
-        // #[cfg(feature = "cookies")]
-        // {
-        //     if let Some(_) = self.cookie_store {
-        //         f.field("cookie_store", &true);
-        //     }
-        // }
+        #[cfg(feature = "cookies")]
+        {
+            if let Some(ref cookie_store) = self.cookie_store {
+                f.field("cookie_store", cookie_store);
+            }
+            if let Some(ref path) = self.cookie_autosave {
+                f.field("cookie_autosave", path);
+            }
+        }
 
         f.field("accepts", &self.accepts);
 
@@ -111,16 +148,48 @@ impl Config {
             f.field("redirect_policy", &self.redirect_policy);
         }
 
+        if !self.redirect_rules.is_empty() {
+            f.field("redirect_rules", &self.redirect_rules.keys());
+        }
+
         if self.referer {
             f.field("referer", &true);
         }
 
+        if let Some(ref policy) = self.retry_policy {
+            f.field("retry_policy", policy);
+        }
+
+        if let Some(ref policy) = self.circuit_policy {
+            f.field("circuit_policy", policy);
+        }
+
+        if let Some(ref policy) = self.rate_limit_policy {
+            f.field("rate_limit_policy", policy);
+        }
+
+        if !self.middleware.is_empty() {
+            f.field("middleware", &self.middleware);
+        }
+
+        if let Some(ref oauth2) = self.oauth2 {
+            f.field("oauth2", oauth2);
+        }
+
         f.field("default_headers", &self.headers);
 
+        if self.http09_responses {
+            f.field("http09_responses", &true);
+        }
+
         if self.http1_title_case_headers {
             f.field("http1_title_case_headers", &true);
         }
 
+        if self.http1_preserve_header_case {
+            f.field("http1_preserve_header_case", &true);
+        }
+
         if self.http1_allow_obsolete_multiline_headers_in_responses {
             f.field("http1_allow_obsolete_multiline_headers_in_responses", &true);
         }
@@ -141,6 +210,14 @@ impl Config {
             f.field("timeout", d);
         }
 
+        if let Some(ref max) = self.max_connections_per_host {
+            f.field("max_connections_per_host", max);
+        }
+
+        if let Some(ref max) = self.max_in_flight {
+            f.field("max_in_flight", max);
+        }
+
         if let Some(ref v) = self.local_address {
             f.field("local_address", v);
         }
@@ -211,13 +288,23 @@ impl ClientBuilder {
                 connection_verbose: false,
                 pool_idle_timeout: Some(Duration::from_secs(90)),
                 pool_max_idle_per_host: std::usize::MAX,
+                max_connections_per_host: None,
+                max_in_flight: None,
+                queue_timeout: None,
                 // TODO: Re-enable default duration once hyper's HttpConnector is fixed
                 // to no longer error when an option fails.
                 tcp_keepalive: None, //Some(Duration::from_secs(60)),
                 // proxies: Vec::new(),
                 // auto_sys_proxy: true,
                 redirect_policy: crate::redirect::Policy::default(),
+                redirect_rules: HashMap::new(),
+                redirect_history_headers: vec![LOCATION.to_string(), SET_COOKIE.to_string()],
                 referer: true,
+                retry_policy: None,
+                circuit_policy: None,
+                rate_limit_policy: None,
+                middleware: Vec::new(),
+                oauth2: None,
                 timeout: None,
                 #[cfg(feature = "__tls")]
                 root_certs: Vec::new(),
@@ -234,6 +321,7 @@ impl ClientBuilder {
                 http_version_pref: HttpVersionPref::All,
                 http09_responses: false,
                 http1_title_case_headers: false,
+                http1_preserve_header_case: false,
                 http1_allow_obsolete_multiline_headers_in_responses: false,
                 http2_initial_stream_window_size: None,
                 http2_initial_connection_window_size: None,
@@ -244,8 +332,10 @@ impl ClientBuilder {
                 http2_keep_alive_while_idle: false,
                 local_address: None,
                 nodelay: true,
-                // #[cfg(feature = "cookies")]
-                // cookie_store: None,
+                #[cfg(feature = "cookies")]
+                cookie_store: Some(cookie::Provider::Jar(Jar::default())),
+                #[cfg(feature = "cookies")]
+                cookie_autosave: None,
                 https_only: false,
                 dns_overrides: HashMap::new(),
             },
@@ -256,8 +346,9 @@ impl ClientBuilder {
     ///
     /// # Errors
     ///
-    /// This method fails if a TLS backend cannot be initialized, or the resolver
-    /// cannot load the system configuration.
+    /// This method fails if a TLS backend cannot be initialized, the resolver
+    /// cannot load the system configuration, or the cookies saved for
+    /// `cookie_autosave` cannot be read.
     pub fn build(self) -> crate::Result<Client> {
         // let config = self.config;
 
@@ -265,6 +356,23 @@ impl ClientBuilder {
             return Err(err);
         }
 
+        // named redirect policies are checked before the config is sent to
+        // the client process, so that unknown names fail here
+        self.config
+            .redirect_policy
+            .check_registered(&self.config.redirect_rules)?;
+
+        // load the saved cookies here, so that an unreadable file fails the
+        // build instead of the start of the client process
+        #[cfg(feature = "cookies")]
+        {
+            if let (Some(store), Some(path)) =
+                (&self.config.cookie_store, &self.config.cookie_autosave)
+            {
+                store.load_from(path)?;
+            }
+        }
+
         // let mut proxies = config.proxies;
         // if config.auto_sys_proxy {
         //     proxies.push(Proxy::system());
@@ -320,31 +428,109 @@ impl ClientBuilder {
 
         // let proxies_maybe_http_auth = proxies.iter().any(|p| p.maybe_has_http_auth());
 
-        let proc = InnerClient::link()
-            .start(self)
-            .expect("Failed to spawn InnerClient");
-        Ok(Client(proc))
+        let limited =
+            self.config.max_connections_per_host.is_some() || self.config.max_in_flight.is_some();
+        let admission = if limited {
+            let limits = (
+                self.config.max_connections_per_host,
+                self.config.max_in_flight,
+                self.config.queue_timeout,
+            );
+            let admission = AdmissionProcess::link()
+                .start(limits)
+                .map_err(|_| crate::error::builder("failed to spawn the admission process"))?;
+            Some(admission)
+        } else {
+            None
+        };
+        let middleware = match middleware::Stack::start(&self.config.middleware) {
+            Ok(middleware) => middleware,
+            Err(err) => {
+                if let Some(admission) = admission {
+                    admission.shutdown();
+                }
+                return Err(err);
+            }
+        };
+        let redirect_rules = match redirect::Rules::start(&self.config.redirect_rules) {
+            Ok(redirect_rules) => redirect_rules,
+            Err(err) => {
+                if let Some(admission) = admission {
+                    admission.shutdown();
+                }
+                middleware.stop();
+                return Err(err);
+            }
+        };
+        let caller_config = CallerConfig {
+            retry_policy: self.config.retry_policy.clone(),
+            request_timeout: self.config.timeout,
+            rate_limited: self.config.rate_limit_policy.is_some(),
+        };
+        let spawn = Spawn {
+            builder: self,
+            middleware: middleware.clone(),
+            redirect_rules: redirect_rules.clone(),
+            admission,
+        };
+
+        let proc = match InnerClient::link().start(spawn) {
+            Ok(proc) => proc,
+            Err(err) => {
+                if let Some(admission) = admission {
+                    admission.shutdown();
+                }
+                middleware.stop();
+                redirect_rules.stop();
+                return Err(match err {
+                    StartupError::Custom(err) => err,
+                    _ => crate::error::builder("failed to spawn the client process"),
+                });
+            }
+        };
+        Ok(Client(proc, admission, caller_config))
     }
 
-    pub(crate) fn build_inner(self) -> Result<InnerClient, crate::Error> {
-        let config = self.config;
+    pub(crate) fn build_inner(spawn: Spawn) -> Result<InnerClient, crate::Error> {
+        let config = spawn.builder.config;
 
         if let Some(err) = config.error {
             return Err(err);
         }
 
+        // the client process goes down with its middleware and rules
+        spawn.middleware.link();
+        spawn.redirect_rules.link();
+
         Ok(InnerClient {
             accepts: config.accepts,
             #[cfg(feature = "cookies")]
-            cookie_store: Some(Arc::new(Jar::default())),
+            cookie_store: config.cookie_store,
+            #[cfg(feature = "cookies")]
+            cookie_autosave: config.cookie_autosave,
             headers: header_map_from_hashmap(config.headers),
             redirect_policy: config.redirect_policy,
+            redirect_rules: spawn.redirect_rules,
             referer: config.referer,
             request_timeout: config.timeout,
+            circuit_breakers: config.circuit_policy.map(circuit::Breakers::new),
+            rate_limiter: config.rate_limit_policy.map(rate_limit::Limiter::new),
+            middleware: spawn.middleware,
+            redirect_history_headers: config.redirect_history_headers,
+            admission: spawn.admission,
+            deadline: None,
+            digest: Default::default(),
+            oauth2: config.oauth2.map(TokenSource::new).transpose()?,
             // proxies,
             // proxies_maybe_http_auth: false,
             https_only: config.https_only,
+            http09_responses: config.http09_responses,
+            title_case_headers: config.http1_title_case_headers,
+            preserve_header_case: config.http1_preserve_header_case,
+            allow_obsolete_multiline_headers_in_responses: config
+                .http1_allow_obsolete_multiline_headers_in_responses,
             stream_map: HashMap::new(),
+            metrics: Metrics::default(),
         })
     }
 
@@ -441,35 +627,61 @@ impl ClientBuilder {
         self
     }
 
-    // /// Enable a persistent cookie store for the client.
-    // ///
-    // /// Cookies received in responses will be preserved and included in
-    // /// additional requests.
-    // ///
-    // /// By default, no cookie store is used.
-    // ///
-    // #[cfg(feature = "cookies")]
-    // pub fn cookie_store(mut self, enable: bool) -> ClientBuilder {
-    //     if enable {
-    //         self.cookie_provider(Jar::default())
-    //     } else {
-    //         self.config.cookie_store = None;
-    //         self
-    //     }
-    // }
+    /// Enable a persistent cookie store for the client.
+    ///
+    /// Cookies received in responses will be preserved and included in
+    /// additional requests.
+    ///
+    /// By default, an empty cookie store is used.
+    #[cfg(feature = "cookies")]
+    pub fn cookie_store(mut self, enable: bool) -> ClientBuilder {
+        if enable {
+            self.cookie_provider(Jar::default())
+        } else {
+            self.config.cookie_store = None;
+            self
+        }
+    }
 
-    // /// Set the persistent cookie store for the client.
-    // ///
-    // /// Cookies received in responses will be passed to this store, and
-    // /// additional requests will query this store for cookies.
-    // ///
-    // /// By default, no cookie store is used.
-    // ///
-    // #[cfg(feature = "cookies")]
-    // pub fn cookie_provider(mut self, cookie_store: Jar) -> ClientBuilder {
-    //     self.config.cookie_store = Some(Arc::new(cookie_store));
-    //     self
-    // }
+    /// Set the persistent cookie store for the client.
+    ///
+    /// Cookies received in responses will be passed to this store, and
+    /// additional requests will query this store for cookies.
+    ///
+    /// The jar is copied into the client process, so the cookies the client
+    /// receives are not added to the `Jar` passed here. Use
+    /// `shared_cookie_provider` to share a jar between clients.
+    #[cfg(feature = "cookies")]
+    pub fn cookie_provider(mut self, cookie_store: Jar) -> ClientBuilder {
+        self.config.cookie_store = Some(cookie::Provider::Jar(cookie_store));
+        self
+    }
+
+    /// Set a cookie store living in its own process, which can be shared
+    /// with other clients.
+    ///
+    /// Cookies received by any of the clients using the jar are sent with
+    /// the requests of all of them.
+    #[cfg(feature = "cookies")]
+    pub fn shared_cookie_provider(mut self, cookie_store: SharedJar) -> ClientBuilder {
+        self.config.cookie_store = Some(cookie::Provider::Shared(cookie_store));
+        self
+    }
+
+    /// Save the persistent cookies of the client to a file at `path`.
+    ///
+    /// The cookies are written as JSON, see `Jar::save_json`, whenever they
+    /// change and when the client is shut down with `Client::shutdown`.
+    /// Cookies already saved at `path` are added to the cookie store when
+    /// the client is built, so that a restarted client keeps its sessions.
+    /// `build()` fails if the file exists but can't be read.
+    ///
+    /// Does nothing if the cookie store is disabled.
+    #[cfg(feature = "cookies")]
+    pub fn cookie_autosave<P: Into<PathBuf>>(mut self, path: P) -> ClientBuilder {
+        self.config.cookie_autosave = Some(path.into());
+        self
+    }
 
     /// Enable auto gzip decompression by checking the `Content-Encoding` response header.
     ///
@@ -558,6 +770,42 @@ impl ClientBuilder {
         self
     }
 
+    /// Register a `Rule` as the redirect policy `name`, for use with
+    /// `redirect::Policy::named()`.
+    ///
+    /// The rule is serialized and restored in a process of its own, see the
+    /// [`redirect`](crate::redirect) module. Registered rules aren't part
+    /// of a serialized `ClientBuilder`.
+    pub fn register_redirect_policy<N, R>(mut self, name: N, rule: R) -> ClientBuilder
+    where
+        N: Into<String>,
+        R: redirect::Rule + Serialize + DeserializeOwned,
+    {
+        match redirect::Registered::new(&rule) {
+            Ok(registered) => {
+                self.config.redirect_rules.insert(name.into(), registered);
+            }
+            Err(e) => self.config.error = Some(e),
+        }
+        self
+    }
+
+    /// Set the headers of redirect responses recorded in
+    /// `HttpResponse::redirect_history()`.
+    ///
+    /// Default is `Location` and `Set-Cookie`.
+    pub fn redirect_history_headers<I, H>(mut self, names: I) -> ClientBuilder
+    where
+        I: IntoIterator<Item = H>,
+        H: Into<String>,
+    {
+        self.config.redirect_history_headers = names
+            .into_iter()
+            .map(|name| name.into().to_ascii_lowercase())
+            .collect();
+        self
+    }
+
     /// Enable or disable automatic setting of the `Referer` header.
     ///
     /// Default is `true`.
@@ -566,6 +814,78 @@ impl ClientBuilder {
         self
     }
 
+    // Retry options
+
+    /// Set a `retry::Policy` for this client.
+    ///
+    /// Default is to never retry.
+    pub fn retry(mut self, policy: retry::Policy) -> ClientBuilder {
+        self.config.retry_policy = Some(policy);
+        self
+    }
+
+    /// Set a `circuit::Policy` to enable a circuit breaker for every host
+    /// this client sends requests to.
+    ///
+    /// Default is no circuit breaker.
+    pub fn circuit_breaker(mut self, policy: circuit::Policy) -> ClientBuilder {
+        self.config.circuit_policy = Some(policy);
+        self
+    }
+
+    /// Set a `rate_limit::Policy` to limit how fast this client sends
+    /// requests.
+    ///
+    /// Default is no rate limit.
+    pub fn rate_limit(mut self, policy: rate_limit::Policy) -> ClientBuilder {
+        self.config.rate_limit_policy = Some(policy);
+        self
+    }
+
+    // Middleware options
+
+    /// Add a `Middleware` that runs around every request of this client.
+    ///
+    /// The middleware is serialized and restored in a process of its own,
+    /// see the [`middleware`](crate::middleware) module.
+    pub fn middleware<M>(mut self, middleware: M) -> ClientBuilder
+    where
+        M: middleware::Middleware + Serialize + DeserializeOwned,
+    {
+        match middleware::Registered::new(&middleware) {
+            Ok(registered) => self.config.middleware.push(registered),
+            Err(e) => self.config.error = Some(e),
+        }
+        self
+    }
+
+    /// Enable caching responses in `store`.
+    ///
+    /// The cache runs as a `Middleware`, after the middleware added before
+    /// this call and before the one added after it.
+    ///
+    /// Default is no cache.
+    pub fn cache<S>(self, store: S) -> ClientBuilder
+    where
+        S: cache::CacheStore + Serialize + DeserializeOwned,
+    {
+        self.middleware(cache::Cache::new(store))
+    }
+
+    // Authentication options
+
+    /// Authenticate the requests of this client with OAuth 2.0 access
+    /// tokens, see [`auth::OAuth2`].
+    ///
+    /// Default is no OAuth 2.0 authentication.
+    pub fn oauth2(mut self, oauth2: auth::OAuth2) -> ClientBuilder {
+        match TokenSource::new(oauth2.clone()) {
+            Ok(_) => self.config.oauth2 = Some(oauth2),
+            Err(e) => self.config.error = Some(e),
+        }
+        self
+    }
+
     // Proxy options
 
     // /// Add a `Proxy` to the list of proxies the `Client` will use.
@@ -641,17 +961,66 @@ impl ClientBuilder {
         self
     }
 
+    /// Sets the maximum number of requests in flight to a single host.
+    ///
+    /// Further requests to the host wait in line in the calling process until
+    /// one of them is done, instead of behind the requests the client is
+    /// sending. A request counts as in flight until the client is done with
+    /// it, even if the caller stopped waiting for it because of a timeout.
+    ///
+    /// Default is no limit.
+    pub fn max_connections_per_host(mut self, max: usize) -> ClientBuilder {
+        self.config.max_connections_per_host = Some(max.max(1));
+        self
+    }
+
+    /// Sets the maximum number of requests in flight to all hosts.
+    ///
+    /// Further requests wait in line until one of them is done.
+    ///
+    /// Default is no limit.
+    pub fn max_in_flight(mut self, max: usize) -> ClientBuilder {
+        self.config.max_in_flight = Some(max.max(1));
+        self
+    }
+
+    /// Set a timeout for waiting in line for a free connection, after which
+    /// the request fails with an error for which `Error::is_queue_timeout`
+    /// returns true.
+    ///
+    /// Default is to wait without a timeout.
+    pub fn queue_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.config.queue_timeout = Some(timeout);
+        self
+    }
+
     /// Send headers as title case instead of lowercase.
     pub fn http1_title_case_headers(mut self) -> ClientBuilder {
         self.config.http1_title_case_headers = true;
         self
     }
 
+    /// Send header names with the exact casing they were added with.
+    ///
+    /// This applies to headers added with
+    /// [`RequestBuilder::header_with_case`](crate::RequestBuilder::header_with_case),
+    /// all other headers are sent lowercase, or title cased if
+    /// `http1_title_case_headers` is set.
+    ///
+    /// Default is `false`.
+    pub fn http1_preserve_header_case(mut self, enabled: bool) -> ClientBuilder {
+        self.config.http1_preserve_header_case = enabled;
+        self
+    }
+
     /// Set whether HTTP/1 connections will accept obsolete line folding for
     /// header values.
     ///
     /// Newline codepoints (`\r` and `\n`) will be transformed to spaces when
     /// parsing.
+    ///
+    /// If this is disabled, which is the default, responses with folded
+    /// headers fail with a decode error.
     pub fn http1_allow_obsolete_multiline_headers_in_responses(
         mut self,
         value: bool,
@@ -668,6 +1037,11 @@ impl ClientBuilder {
     }
 
     /// Allow HTTP/0.9 responses
+    ///
+    /// A response that doesn't start with an HTTP/1.x status line is then
+    /// read as a bare HTTP/0.9 body until the server closes the connection.
+    /// Otherwise such responses fail with an error for which
+    /// [`Error::is_version`](crate::Error::is_version) returns true.
     pub fn http09_responses(mut self) -> ClientBuilder {
         self.config.http09_responses = true;
         self
@@ -1,49 +1,87 @@
 pub mod builder;
+mod queue;
+mod service;
 
 pub use builder::*;
+pub use queue::QueueStatus;
+use queue::{AdmissionProcess, AdmissionProcessMessages, AdmissionProcessRequests};
 
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::fmt;
 use std::io::Write;
-use std::time::Duration;
+#[cfg(feature = "cookies")]
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
 
-use http::header::{self, Entry, HeaderMap, HeaderValue, ACCEPT_ENCODING, RANGE};
-use http::Version;
+use http::header::{
+    self, Entry, HeaderMap, HeaderName, HeaderValue, ACCEPT_ENCODING, RANGE, TRANSFER_ENCODING,
+};
+use http::{StatusCode, Version};
 use lunatic::ap::{AbstractProcess, Config, ProcessRef};
-use lunatic::{abstract_process, Tag};
+use lunatic::{abstract_process, Mailbox, Tag};
 use serde::{Deserialize, Serialize};
 
+use crate::auth::{digest, oauth2};
 #[cfg(feature = "cookies")]
 use crate::cookie;
 use crate::error;
-use crate::lunatic_impl::request::{hashmap_from_header_map, InnerRequest};
-use crate::lunatic_impl::response::SerializableResponse;
+use crate::lunatic_impl::request::{push_header_order, HeaderCase, InnerRequest};
+use crate::lunatic_impl::response::{HttpInfo, SerializableResponse};
 use crate::lunatic_impl::{
-    decoder::{parse_response, Accepts},
-    http_stream::HttpStream,
+    decoder::{is_keep_alive, parse_response, Accepts, ParseResponseError},
+    http_stream::{Connection, HttpStream},
     request::{PendingRequest, Request, RequestBuilder},
     response::HttpResponse,
 };
-use crate::redirect;
+use crate::metrics::Metrics;
+use crate::{circuit, middleware, rate_limit, redirect, retry};
 pub use crate::{Body, ClientBuilder};
 use crate::{IntoUrl, Method, Url};
-#[cfg(feature = "cookies")]
-use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct InnerClient {
     pub(crate) accepts: Accepts,
     #[cfg(feature = "cookies")]
-    pub(crate) cookie_store: Option<Arc<cookie::Jar>>,
+    pub(crate) cookie_store: Option<cookie::Provider>,
+    #[cfg(feature = "cookies")]
+    pub(crate) cookie_autosave: Option<PathBuf>,
     pub(crate) headers: HeaderMap,
     pub(crate) redirect_policy: redirect::Policy,
+    pub(crate) redirect_rules: redirect::Rules,
     pub(crate) referer: bool,
     pub(crate) request_timeout: Option<Duration>,
+    pub(crate) circuit_breakers: Option<circuit::Breakers>,
+    pub(crate) rate_limiter: Option<rate_limit::Limiter>,
+    pub(crate) middleware: middleware::Stack,
+    pub(crate) redirect_history_headers: Vec<String>,
+    pub(crate) admission: Option<ProcessRef<AdmissionProcess>>,
+    // when the caller of the request being handled stops waiting for it
+    pub(crate) deadline: Option<SystemTime>,
+    pub(crate) digest: digest::Cache,
+    pub(crate) oauth2: Option<oauth2::TokenSource>,
     // pub(crate) proxies: Arc<Vec<Proxy>>,
     // pub(crate) proxies_maybe_http_auth: bool,
     pub(crate) https_only: bool,
-    pub(crate) stream_map: HashMap<HostRef, HttpStream>,
+    pub(crate) http09_responses: bool,
+    pub(crate) title_case_headers: bool,
+    pub(crate) preserve_header_case: bool,
+    pub(crate) allow_obsolete_multiline_headers_in_responses: bool,
+    pub(crate) stream_map: HashMap<HostRef, Connection>,
+    pub(crate) metrics: Metrics,
+}
+
+/// How `request_to_vec` writes the request headers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeaderWriteOptions<'a> {
+    /// Headers are written in this order, followed by any headers missing
+    /// from it.
+    pub order: &'a [String],
+    /// The original casing of header names, written instead of lowercase.
+    pub header_case: Option<&'a HeaderCase>,
+    /// Whether header names without an original casing are title cased.
+    pub title_case: bool,
 }
 
 /// encode request as http text
@@ -53,10 +91,13 @@ pub fn request_to_vec(
     mut headers: HeaderMap,
     body: Option<Body>,
     version: Version,
+    options: &HeaderWriteOptions,
 ) -> Vec<u8> {
     let mut request_buffer: Vec<u8> = Vec::new();
     if let Some(body) = &body {
-        headers.append(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+        if let Entry::Vacant(entry) = headers.entry(header::CONTENT_LENGTH) {
+            entry.insert(HeaderValue::from(body.len()));
+        }
     }
 
     // writing status line
@@ -66,12 +107,33 @@ pub fn request_to_vec(
         uri.path().to_string()
     };
     request_buffer.extend(format!("{} {} {:?}\r\n", method, path, version,).as_bytes());
-    // writing headers
-    for (key, value) in headers.iter() {
-        if let Ok(value) = String::from_utf8(value.as_ref().to_vec()) {
-            request_buffer.extend(format!("{}: {}\r\n", key, value).as_bytes());
+    // writing headers in the order they were added, with `Host` going first
+    let mut names: Vec<HeaderName> = vec![header::HOST];
+    for name in options.order {
+        if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
         }
     }
+    for name in headers.keys() {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    let ordered = names.iter().flat_map(|name| {
+        headers
+            .get_all(name)
+            .into_iter()
+            .map(move |value| (name, value))
+    });
+    for (key, value) in ordered {
+        request_buffer.extend(header_name(key, options.header_case, options.title_case).as_bytes());
+        request_buffer.extend(b": ");
+        // values are written as-is, they don't need to be valid UTF-8
+        request_buffer.extend(value.as_bytes());
+        request_buffer.extend(b"\r\n");
+    }
     // separator between header and data
     request_buffer.extend("\r\n".as_bytes());
     if let Some(body) = body {
@@ -81,18 +143,50 @@ pub fn request_to_vec(
     request_buffer
 }
 
+fn header_name<'a>(
+    name: &'a HeaderName,
+    header_case: Option<&'a HeaderCase>,
+    title_case: bool,
+) -> Cow<'a, str> {
+    if let Some(original) = header_case.and_then(|case| case.get(name.as_str())) {
+        return Cow::Borrowed(original);
+    }
+    if !title_case {
+        return Cow::Borrowed(name.as_str());
+    }
+    // upper case the first letter and every letter following a dash
+    let mut upper = true;
+    let name = name
+        .as_str()
+        .chars()
+        .map(|c| {
+            let c = if upper { c.to_ascii_uppercase() } else { c };
+            upper = c == '-';
+            c
+        })
+        .collect();
+    Cow::Owned(name)
+}
+
 #[abstract_process(visibility = pub)]
 impl InnerClient {
     // type Arg = ClientBuilder;
     // type State = Self;
 
     #[init]
-    fn init(_: Config<Self>, builder: ClientBuilder) -> Result<Self, crate::Error> {
-        builder.build_inner()
+    fn init(_: Config<Self>, spawn: Spawn) -> Result<Self, crate::Error> {
+        ClientBuilder::build_inner(spawn)
     }
 
     #[terminate]
     fn terminate(&self) {
+        #[cfg(feature = "cookies")]
+        self.autosave_cookies();
+        if let Some(admission) = self.admission {
+            admission.shutdown();
+        }
+        self.middleware.stop();
+        self.redirect_rules.stop();
         println!("Shutdown process");
     }
 
@@ -101,25 +195,174 @@ impl InnerClient {
         println!("Link trapped");
     }
 
+    /// Sends a request. If the request got a slot from the admission
+    /// process, the slot is given back once the request is done, even if
+    /// the caller stopped waiting for it at its deadline.
     #[handle_request]
     fn handle_http_request(
         &mut self,
-        request: InnerRequest,
+        send: (InnerRequest, bool, Option<SystemTime>),
     ) -> crate::Result<SerializableResponse> {
-        let res = self.execute_request(request, vec![])?;
-        Ok(SerializableResponse {
-            body: res.body,
-            status: res.status.as_u16(),
-            version: res.version,
-            headers: hashmap_from_header_map(res.headers),
-            url: res.url,
-            redirect_chain: res.redirect_chain,
-        })
+        let (request, has_slot, deadline) = send;
+        let url = request.url.clone();
+        let started = Instant::now();
+        self.deadline = deadline;
+        let res = if self.middleware.is_empty() {
+            self.execute_request(request, vec![])
+        } else {
+            self.execute_with_middleware(request)
+        };
+        // the caller already gave up on a request that is done after its
+        // deadline, so it only counts as a timeout
+        let timed_out = self.is_past_deadline();
+        self.deadline = None;
+        if let (true, Some(admission)) = (has_slot, self.admission) {
+            admission.release_slot(url.clone());
+        }
+        let res = match res {
+            Ok(_) if timed_out => {
+                self.metrics.record_error("timeout");
+                return Err(error::timeout(url));
+            }
+            Ok(res) => {
+                self.metrics.latency.record(started.elapsed());
+                res
+            }
+            Err(e) => {
+                let kind = if timed_out { "timeout" } else { e.kind_name() };
+                self.metrics.record_error(kind);
+                return Err(e);
+            }
+        };
+        Ok(res.into())
+    }
+
+    /// Returns the circuit breaker state for the host of `url`, or `None` if
+    /// the client has no circuit breaker.
+    #[handle_request]
+    fn circuit_state(&mut self, url: Url) -> Option<circuit::State> {
+        let host_ref = HostRef::new(&url);
+        self.circuit_breakers
+            .as_mut()
+            .map(|breakers| breakers.state(&host_ref))
+    }
+
+    /// Takes a rate limit token for a request and returns how long it has to
+    /// wait, or how long it would have had to wait if it was rejected.
+    #[handle_request]
+    fn acquire_rate_limit(
+        &mut self,
+        acquire: (Url, Option<String>, Option<rate_limit::Mode>),
+    ) -> Result<Duration, Duration> {
+        let (url, key, mode) = acquire;
+        let acquired = match self.rate_limiter {
+            Some(ref mut limiter) => limiter.acquire(HostRef::new(&url), key, mode),
+            None => Ok(Duration::from_secs(0)),
+        };
+        // the caller fails the request without sending it to this process
+        if acquired.is_err() {
+            self.metrics.record_error("rate_limited");
+        }
+        acquired
+    }
+
+    #[handle_request]
+    fn metrics(&mut self) -> Metrics {
+        let mut metrics = self.metrics.clone();
+        for host_ref in self.stream_map.keys() {
+            *metrics
+                .open_connections
+                .entry(host_ref.as_str().to_string())
+                .or_default() += 1;
+        }
+        // queue timeouts happen in the calling process, the admission
+        // process counts them
+        if let Some(admission) = self.admission {
+            let queue_timeouts = admission.queue_timeouts();
+            if queue_timeouts > 0 {
+                *metrics
+                    .errors
+                    .entry("queue_timeout".to_string())
+                    .or_default() += queue_timeouts;
+            }
+        }
+        metrics
+    }
+
+    #[handle_request]
+    fn reset_metrics(&mut self) {
+        self.metrics = Metrics::default();
+        if let Some(admission) = self.admission {
+            admission.reset_queue_timeouts();
+        }
     }
 
+    #[cfg(feature = "cookies")]
     #[handle_request]
-    fn get_request_timeout(&mut self) -> Option<Duration> {
-        self.request_timeout
+    fn list_cookies(&mut self, filter: cookie::CookieFilter) -> Vec<cookie::StoredCookie> {
+        self.cookie_store
+            .as_ref()
+            .map(|store| store.list(filter))
+            .unwrap_or_default()
+    }
+
+    #[cfg(feature = "cookies")]
+    #[handle_request]
+    fn insert_cookie(&mut self, cookie: cookie::StoredCookie) -> crate::Result<()> {
+        match self.cookie_store {
+            Some(ref store) => store.insert(cookie)?,
+            None => return Err(error::builder("the cookie store is disabled")),
+        }
+        self.autosave_cookies();
+        Ok(())
+    }
+
+    #[cfg(feature = "cookies")]
+    #[handle_request]
+    fn delete_cookies(&mut self, filter: cookie::CookieFilter) -> usize {
+        let removed = self
+            .cookie_store
+            .as_ref()
+            .map_or(0, |store| store.remove(filter));
+        if removed > 0 {
+            self.autosave_cookies();
+        }
+        removed
+    }
+
+    #[cfg(feature = "cookies")]
+    #[handle_request]
+    fn clear_expired_cookies(&mut self) -> usize {
+        let removed = self
+            .cookie_store
+            .as_ref()
+            .map_or(0, |store| store.clear_expired());
+        if removed > 0 {
+            self.autosave_cookies();
+        }
+        removed
+    }
+
+    #[cfg(feature = "cookies")]
+    #[handle_request]
+    fn clear_session_cookies(&mut self) -> usize {
+        let removed = self
+            .cookie_store
+            .as_ref()
+            .map_or(0, |store| store.clear_session());
+        if removed > 0 {
+            self.autosave_cookies();
+        }
+        removed
+    }
+
+    #[cfg(feature = "cookies")]
+    #[handle_request]
+    fn clear_cookies(&mut self) {
+        if let Some(ref store) = self.cookie_store {
+            store.clear();
+            self.autosave_cookies();
+        }
     }
 }
 
@@ -141,13 +384,30 @@ impl InnerClient {
 /// so it's easy to pass around between processes. A client can connect to multiple
 /// different hosts and manage different connections.
 #[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct Client(pub ProcessRef<InnerClient>);
+pub struct Client(
+    pub ProcessRef<InnerClient>,
+    // hands out slots if the client limits the requests in flight, see
+    // `AdmissionProcess`
+    pub(crate) Option<ProcessRef<AdmissionProcess>>,
+    pub(crate) CallerConfig,
+);
+
+/// The part of the configuration of a `Client` that the calling process
+/// needs for every attempt, kept in the `Client` so that it doesn't have to
+/// ask the client process for it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct CallerConfig {
+    pub(crate) retry_policy: Option<retry::Policy>,
+    pub(crate) request_timeout: Option<Duration>,
+    // whether the client process has a rate limiter to ask
+    pub(crate) rate_limited: bool,
+}
 
 impl Default for Client {
     fn default() -> Self {
-        let builder = ClientBuilder::new();
-        let proc = InnerClient::link().start(builder);
-        Client(proc.expect("failed to spawn client"))
+        ClientBuilder::new()
+            .build()
+            .expect("failed to spawn client")
     }
 }
 
@@ -247,19 +507,219 @@ impl Client {
     ///
     /// This method fails if there was an error while sending request,
     /// redirect loop was detected or redirect limit was exhausted.
-    pub fn execute(&mut self, request: Request) -> Result<HttpResponse, crate::Error> {
+    pub fn execute(&mut self, mut request: Request) -> Result<HttpResponse, crate::Error> {
+        let policy = request.retry.take().or_else(|| self.2.retry_policy.clone());
         let inner: InnerRequest = request.try_into()?;
+        let user_timeout = inner.timeout.or(self.2.request_timeout);
+        let policy = match policy {
+            Some(policy) if policy.allows_method(&inner.method.parse().unwrap_or_default()) => {
+                policy
+            }
+            _ => return self.send_attempt(inner, user_timeout),
+        };
+
+        // retries are driven from the calling process so that waiting for the
+        // next attempt doesn't block the client process for everybody else
+        let started = Instant::now();
+        let mut attempt = 1;
+        let mut attempt_timings = vec![];
+        loop {
+            let remaining = policy
+                .total_deadline()
+                .map(|deadline| deadline.saturating_sub(started.elapsed()));
+            let timeout = match (user_timeout, remaining) {
+                (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+                (timeout, remaining) => timeout.or(remaining),
+            };
+            let result = self.send_attempt(inner.clone(), timeout);
+            let delay = match result {
+                Ok(ref res) => policy.retry_response(res, attempt),
+                Err(ref err) => policy.retry_error(err, attempt),
+            };
+            let delay = match (delay, remaining) {
+                (Some(delay), Some(remaining)) if delay >= remaining => None,
+                (delay, _) => delay,
+            };
+            match delay {
+                Some(delay) => {
+                    if let Ok(res) = result {
+                        attempt_timings.push(res.timings);
+                    }
+                    lunatic::sleep(delay);
+                    attempt += 1;
+                }
+                None => {
+                    return result.map(|mut res| {
+                        res.attempts = attempt;
+                        res.attempt_timings = attempt_timings;
+                        res
+                    })
+                }
+            }
+        }
+    }
+
+    fn send_attempt(
+        &mut self,
+        inner: InnerRequest,
+        timeout: Option<Duration>,
+    ) -> crate::Result<HttpResponse> {
         let url = inner.url.clone();
-        let user_timeout = inner.timeout.or_else(|| self.0.get_request_timeout());
-        let res = if let Some(timeout) = user_timeout {
+        if self.2.rate_limited {
+            let acquire = (
+                url.clone(),
+                inner.rate_limit_key.clone(),
+                inner.rate_limit_mode,
+            );
+            match self.0.acquire_rate_limit(acquire) {
+                Ok(wait) if wait > Duration::from_secs(0) => lunatic::sleep(wait),
+                Ok(_) => {}
+                Err(_) => return Err(crate::error::rate_limited(url)),
+            }
+        }
+
+        let queue_wait = self.wait_for_slot(&url)?;
+        // the client process gives the slot back once the request is done,
+        // and treats it as timed out if it's done after the deadline
+        let deadline = timeout.map(|timeout| SystemTime::now() + timeout);
+        let send = (inner, self.1.is_some(), deadline);
+        let res = if let Some(timeout) = timeout {
             self.0
                 .with_timeout(timeout)
-                .handle_http_request(inner)
-                .unwrap_or_else(|_| Err(crate::error::timeout(url)))?
+                .handle_http_request(send)
+                .unwrap_or_else(|_| Err(crate::error::timeout(url.clone())))
         } else {
-            self.0.handle_http_request(inner)?
+            self.0.handle_http_request(send)
+        };
+
+        let mut res = HttpResponse::try_from(res?)?;
+        res.queue_wait = queue_wait;
+        Ok(res)
+    }
+
+    /// Waits in the queue of the host of `url` until the request may be
+    /// sent, and returns how long that took.
+    fn wait_for_slot(&mut self, url: &Url) -> crate::Result<Duration> {
+        let admission = match self.1 {
+            Some(admission) => admission,
+            None => return Ok(Duration::from_secs(0)),
         };
-        res.try_into()
+        // SAFETY: only the ticket is received, with a tag unique to this wait
+        let mailbox: Mailbox<u64> = unsafe { Mailbox::new() };
+        let tag = Tag::new();
+        let started = Instant::now();
+        // the admission process sends the ticket with `tag` once the request
+        // gets a slot, and drops it if this process dies while waiting
+        let ticket = match admission.queue_slot((url.clone(), mailbox.this(), tag)) {
+            Ok(_) => return Ok(Duration::from_secs(0)),
+            Err(ticket) => ticket,
+        };
+        let got_slot = match admission.get_queue_timeout() {
+            Some(timeout) => {
+                let remaining = timeout.saturating_sub(started.elapsed());
+                mailbox.tag_receive_timeout(&[tag], remaining).is_ok()
+            }
+            None => {
+                mailbox.tag_receive(&[tag]);
+                true
+            }
+        };
+        if got_slot {
+            return Ok(started.elapsed());
+        }
+        if admission.cancel_slot((url.clone(), ticket)) {
+            // the slot came just too late, drop the message telling so
+            let _ = mailbox.tag_receive_timeout(&[tag], Duration::from_secs(0));
+        }
+        Err(crate::error::queue_timeout(url.clone()))
+    }
+
+    /// Returns how many requests are in flight and waiting for the host of
+    /// `url`.
+    ///
+    /// Requests only wait if `ClientBuilder::max_connections_per_host()` or
+    /// `ClientBuilder::max_in_flight()` is set.
+    pub fn queue_status<U: IntoUrl>(&self, url: U) -> crate::Result<QueueStatus> {
+        let url = url.into_url()?;
+        Ok(self
+            .1
+            .map(|admission| admission.queue_status(url))
+            .unwrap_or_default())
+    }
+
+    /// Returns the state of the circuit breaker for the host of `url`.
+    ///
+    /// Returns `None` if no `circuit::Policy` was configured with
+    /// `ClientBuilder::circuit_breaker()`.
+    pub fn circuit_state<U: IntoUrl>(&self, url: U) -> crate::Result<Option<circuit::State>> {
+        Ok(self.0.circuit_state(url.into_url()?))
+    }
+
+    /// Returns a snapshot of the counters of the client, like the number of
+    /// requests and errors, bytes sent and received and latencies.
+    ///
+    /// See the [`metrics`](crate::metrics) module.
+    pub fn metrics(&self) -> Metrics {
+        self.0.metrics()
+    }
+
+    /// Resets the counters of the client to zero.
+    pub fn reset_metrics(&self) {
+        self.0.reset_metrics()
+    }
+
+    /// Returns the cookies in the cookie store of the client that are
+    /// selected by `filter`.
+    ///
+    /// Returns no cookies if the cookie store is disabled.
+    #[cfg(feature = "cookies")]
+    pub fn cookies(&self, filter: cookie::CookieFilter) -> Vec<cookie::StoredCookie> {
+        self.0.list_cookies(filter)
+    }
+
+    /// Adds a cookie to the cookie store of the client.
+    ///
+    /// # Errors
+    ///
+    /// This method fails if the cookie store is disabled, or the cookie is
+    /// not valid for its domain.
+    #[cfg(feature = "cookies")]
+    pub fn insert_cookie(&self, cookie: cookie::StoredCookie) -> crate::Result<()> {
+        self.0.insert_cookie(cookie)
+    }
+
+    /// Removes the cookies selected by `filter` from the cookie store of the
+    /// client, and returns how many were removed.
+    #[cfg(feature = "cookies")]
+    pub fn delete_cookies(&self, filter: cookie::CookieFilter) -> usize {
+        self.0.delete_cookies(filter)
+    }
+
+    /// Removes the expired cookies from the cookie store of the client, and
+    /// returns how many were removed.
+    #[cfg(feature = "cookies")]
+    pub fn clear_expired_cookies(&self) -> usize {
+        self.0.clear_expired_cookies()
+    }
+
+    /// Removes the session cookies from the cookie store of the client, and
+    /// returns how many were removed.
+    #[cfg(feature = "cookies")]
+    pub fn clear_session_cookies(&self) -> usize {
+        self.0.clear_session_cookies()
+    }
+
+    /// Removes all cookies from the cookie store of the client.
+    #[cfg(feature = "cookies")]
+    pub fn clear_cookies(&self) {
+        self.0.clear_cookies()
+    }
+
+    /// Stops the client process.
+    ///
+    /// Requests sent with any clone of this `Client` afterwards will fail.
+    pub fn shutdown(self) {
+        self.0.shutdown();
     }
 
     /// Creates a `ClientBuilder` to configure a `Client`.
@@ -285,6 +745,12 @@ impl HostRef {
         let conn_str = format!("{}:{}", url.host().unwrap(), url.port().unwrap_or(80));
         HostRef::Http(conn_str)
     }
+
+    pub(crate) fn as_str(&self) -> &str {
+        match self {
+            HostRef::Http(host) | HostRef::Https(host) => host,
+        }
+    }
 }
 
 impl InnerClient {
@@ -293,14 +759,100 @@ impl InnerClient {
     }
 
     /// ensures connection
-    pub fn ensure_connection(&mut self, url: Url) -> crate::Result<HttpStream> {
+    pub fn ensure_connection(&mut self, url: Url) -> crate::Result<Connection> {
         let host_ref = HostRef::new(&url);
-        if let Some(stream) = self.stream_map.get(&host_ref) {
-            return Ok(stream.to_owned());
+        if let Some(conn) = self.stream_map.get(&host_ref) {
+            return Ok(conn.to_owned());
         }
         HttpStream::connect(url)
     }
 
+    /// Returns true if an OAuth 2.0 access token is sent with a request to
+    /// `url`, which isn't the case if `url` isn't one of the resources of the
+    /// token, the request sets its own `Authorization` header or was
+    /// redirected to another host.
+    pub(crate) fn uses_oauth2(&self, url: &Url, has_authorization: bool, urls: &[Url]) -> bool {
+        self.oauth2
+            .as_ref()
+            .map_or(false, |source| source.covers(url))
+            && !has_authorization
+            && !redirect::is_cross_host(url, &urls[..urls.len().min(1)])
+    }
+
+    fn is_past_deadline(&self) -> bool {
+        self.deadline
+            .map_or(false, |deadline| SystemTime::now() >= deadline)
+    }
+
+    /// Returns the cached access token, or fetches a new one from the token
+    /// endpoint.
+    fn oauth2_token(&mut self) -> crate::Result<String> {
+        let source = match self.oauth2 {
+            Some(ref source) => source,
+            None => return Err(error::builder("no OAuth 2.0 token source")),
+        };
+        if let Some(token) = source.cached() {
+            return Ok(token);
+        }
+
+        let mut res = self.request_oauth2_token()?;
+        let rejected = matches!(
+            res.status(),
+            StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED
+        );
+        if rejected
+            && self
+                .oauth2
+                .as_mut()
+                .map_or(false, |s| s.refresh_token_rejected())
+        {
+            // the refresh token expired, fall back to the client credentials
+            res = self.request_oauth2_token()?;
+        }
+        if !res.status().is_success() {
+            return Err(error::status_code(res.url().clone(), res.status()));
+        }
+        match self.oauth2 {
+            Some(ref mut source) => source.store(&res.body),
+            None => Err(error::builder("no OAuth 2.0 token source")),
+        }
+    }
+
+    fn request_oauth2_token(&mut self) -> crate::Result<HttpResponse> {
+        let mut req = match self.oauth2 {
+            Some(ref source) => {
+                let (url, form) = source.token_request();
+                let mut req = Request::new(Method::POST, url);
+                let mut authorization =
+                    HeaderValue::from_str(&source.basic_credentials()).map_err(error::builder)?;
+                authorization.set_sensitive(true);
+                req.headers_mut()
+                    .insert(header::AUTHORIZATION, authorization);
+                let form = serde_urlencoded::to_string(form).map_err(error::builder)?;
+                *req.body_mut() = Some(form.into());
+                req
+            }
+            None => return Err(error::builder("no OAuth 2.0 token source")),
+        };
+        req.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+        req.headers_mut()
+            .insert(header::ACCEPT, HeaderValue::from_static("application/json"));
+        self.execute_request(req.try_into()?, vec![])
+    }
+
+    /// Saves the cookies to the file set with `ClientBuilder::cookie_autosave`.
+    #[cfg(feature = "cookies")]
+    pub(crate) fn autosave_cookies(&self) {
+        if let (Some(store), Some(path)) = (&self.cookie_store, &self.cookie_autosave) {
+            if let Err(e) = store.save_to(path) {
+                lunatic_log::warn!("Failed to save cookies to {:?}: {}", path, e);
+            }
+        }
+    }
+
     fn fmt_fields(&self, f: &mut fmt::DebugStruct<'_, '_>) {
         // Instead of deriving Debug, only print fields when their output
         // would provide relevant or interesting data.
@@ -333,6 +885,37 @@ impl InnerClient {
         }
     }
 
+    fn execute_with_middleware(&mut self, request: InnerRequest) -> crate::Result<HttpResponse> {
+        // the stack is taken out while it runs so that it can call back
+        // into the client to send the request
+        let stack = std::mem::take(&mut self.middleware);
+        let mut request: crate::Request = request.into();
+        request.client_headers = self.client_headers(&request.url);
+        let res = stack.run(request, |req| {
+            let inner = InnerRequest::try_from(req)?;
+            self.execute_request(inner, vec![])
+        });
+        self.middleware = stack;
+        res
+    }
+
+    /// Returns the headers `execute_request` adds to a request to `url` for
+    /// the names the request doesn't set itself.
+    fn client_headers(&self, url: &Url) -> HeaderMap {
+        let mut headers = self.headers.clone();
+        #[cfg(feature = "cookies")]
+        {
+            if let Some(cookie_store) = self.cookie_store.as_ref() {
+                if !headers.contains_key(crate::header::COOKIE) {
+                    add_cookie_header(&mut headers, cookie_store, url);
+                }
+            }
+        }
+        #[cfg(not(feature = "cookies"))]
+        let _ = url;
+        headers
+    }
+
     pub(crate) fn execute_request(
         &mut self,
         req: InnerRequest,
@@ -367,7 +950,29 @@ impl InnerClient {
         {
             if let Some(cookie_store) = self.cookie_store.as_ref() {
                 if headers.get(crate::header::COOKIE).is_none() {
-                    add_cookie_header(&mut headers, cookie_store.clone(), &url);
+                    add_cookie_header(&mut headers, cookie_store, &url);
+                }
+            }
+        }
+
+        let has_authorization = headers.contains_key(header::AUTHORIZATION);
+        let uses_oauth2 = self.uses_oauth2(&url, has_authorization, &urls);
+        if uses_oauth2 {
+            let token = self.oauth2_token()?;
+            let mut value =
+                HeaderValue::from_str(&format!("Bearer {}", token)).map_err(error::builder)?;
+            value.set_sensitive(true);
+            headers.insert(header::AUTHORIZATION, value);
+        }
+
+        // Answer a digest challenge received before for this protection space.
+        if let Some(ref credentials) = req.digest_auth {
+            if !headers.contains_key(header::AUTHORIZATION) {
+                let authorization = self
+                    .digest
+                    .authorization(credentials, method.as_str(), &url);
+                if let Some(value) = authorization {
+                    headers.insert(header::AUTHORIZATION, value);
                 }
             }
         }
@@ -380,16 +985,65 @@ impl InnerClient {
             }
         }
 
+        // set here already, so that a signature can cover it
+        if let Some(ref body) = body {
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+        }
+
+        // headers added by the client go after the ones of the request
+        let mut order = req.header_order.clone();
+        for name in headers.keys() {
+            push_header_order(&mut order, name);
+        }
+
+        // HTTP/1.0 has no chunked transfer coding, the body is always sent
+        // with a `Content-Length`
+        if version < crate::Version::HTTP_11 {
+            headers.remove(TRANSFER_ENCODING);
+        }
+
+        // Sign last, so that the signature covers the headers as they're sent.
+        if let Some(ref sigv4) = req.aws_sigv4 {
+            let payload = body.as_ref().map(Body::as_bytes).unwrap_or_default();
+            sigv4.sign(
+                method.as_str(),
+                &url,
+                &mut headers,
+                payload,
+                SystemTime::now(),
+            )?;
+        }
+        if let Some(ref signer) = req.message_signature {
+            let payload = body.as_ref().map(Body::as_bytes).unwrap_or_default();
+            signer.sign(
+                method.as_str(),
+                &url,
+                &mut headers,
+                payload,
+                SystemTime::now(),
+            )?;
+        }
+
         // let uri = expect_uri(&url);
 
         // self.proxy_auth(&uri, &mut headers);
 
+        let header_case = if self.preserve_header_case {
+            Some(&req.header_case)
+        } else {
+            None
+        };
         let encoded = request_to_vec(
-            method,
+            method.clone(),
             url.clone(),
             headers.clone(),
             body,
-            version.try_into().unwrap(),
+            version.into(),
+            &HeaderWriteOptions {
+                order: &order,
+                header_case,
+                title_case: self.title_case_headers,
+            },
         );
         lunatic_log::debug!(
             "Encoded headers {:?} | Encoded request {:?}",
@@ -397,21 +1051,116 @@ impl InnerClient {
             String::from_utf8(encoded.clone())
         );
 
-        let mut stream = self.ensure_connection(url)?;
+        // the caller gave up waiting while the request was queued
+        if self.is_past_deadline() {
+            return Err(error::timeout(url));
+        }
+
+        let host_ref = HostRef::new(&url);
+        if let Some(ref mut breakers) = self.circuit_breakers {
+            if !breakers.allows(&host_ref) {
+                return Err(error::circuit_open(url));
+            }
+        }
+
+        let sent_at = Instant::now();
+        let result = self.send_pooled(&host_ref, &url, &method, &headers, &encoded, &req);
+        // a response that arrives after the caller gave up counts as a
+        // timeout, not as a success
+        let timed_out = self.is_past_deadline();
+        if let Some(ref mut breakers) = self.circuit_breakers {
+            match result {
+                _ if timed_out && !breakers.policy().counts_timeouts() => {
+                    breakers.record_ignored(&host_ref)
+                }
+                Ok(ref res) if !timed_out && !breakers.policy().is_failure_status(res.status) => {
+                    breakers.record_success(&host_ref)
+                }
+                _ => breakers.record_failure(&host_ref),
+            }
+        }
+        PendingRequest::new(result?, self, req, urls, sent_at.elapsed(), uses_oauth2).resolve()
+    }
+
+    /// Sends the encoded request over a pooled or a new connection to `url`.
+    fn send_pooled(
+        &mut self,
+        host_ref: &HostRef,
+        url: &Url,
+        method: &Method,
+        headers: &HeaderMap,
+        encoded: &[u8],
+        req: &InnerRequest,
+    ) -> crate::Result<HttpResponse> {
+        let mut reused = self.stream_map.contains_key(host_ref);
+        self.metrics.record_connection(reused);
+        let mut conn = self.ensure_connection(url.clone())?;
         // if let Some(timeout) = self.request_timeout {
         //     stream.set
         // }
 
-        stream.write_all(&encoded).unwrap();
-
-        let response_buffer = Vec::new();
+        let mut result = self.send_encoded(&mut conn.stream, encoded, req);
+
+        // the server may have closed a pooled connection while it was idle,
+        // so a request that got no response at all is sent once more on a
+        // fresh connection if it is safe to replay
+        if let Err(ref e) = result {
+            if reused && e.is_stale_connection() && retry::is_idempotent(method) {
+                lunatic_log::debug!("Pooled connection is stale, reconnecting {:?}", host_ref);
+                self.stream_map.remove(host_ref);
+                conn = HttpStream::connect(url.clone())?;
+                reused = false;
+                self.metrics.record_connection(reused);
+                result = self.send_encoded(&mut conn.stream, encoded, req);
+            }
+        }
 
-        match parse_response(response_buffer, stream.clone(), req.clone(), self) {
-            Ok(res) => PendingRequest::new(res, self, req, urls).resolve(),
-            Err(_e) => unimplemented!(),
+        match result {
+            Ok(mut res) => {
+                res.info = Some(HttpInfo::new(&conn, res.version, reused));
+                if !reused {
+                    res.timings.record_connection(&conn.timings);
+                }
+                // a response the caller doesn't wait for anymore is counted
+                // as a timeout by `handle_http_request`
+                if !self.is_past_deadline() {
+                    self.metrics.record_response(method, res.status);
+                }
+                self.metrics
+                    .time_to_first_byte
+                    .record(res.timings.first_byte());
+                // only keep the connection around if both sides agree on it
+                if is_keep_alive(&res, headers, method) {
+                    self.stream_map.insert(host_ref.clone(), conn);
+                } else {
+                    self.stream_map.remove(host_ref);
+                }
+                Ok(res)
+            }
+            Err(e) => {
+                self.stream_map.remove(host_ref);
+                Err(crate::Error::from(e).with_url(url.clone()))
+            }
         }
     }
 
+    fn send_encoded(
+        &mut self,
+        stream: &mut HttpStream,
+        encoded: &[u8],
+        req: &InnerRequest,
+    ) -> Result<HttpResponse, ParseResponseError> {
+        let started = Instant::now();
+        stream
+            .write_all(encoded)
+            .map_err(ParseResponseError::TcpStreamWriteFailed)?;
+        let request_write = started.elapsed();
+        self.metrics.record_sent(encoded.len());
+        let mut res = parse_response(Vec::new(), stream.clone(), req.clone(), self)?;
+        res.timings.record_write(request_write);
+        Ok(res)
+    }
+
     // fn proxy_auth(&self, dst: &Uri, headers: &mut HeaderMap) {
     //     if !self.proxies_maybe_http_auth {
     //         return;
@@ -525,7 +1274,7 @@ impl fmt::Debug for ClientBuilder {
 #[cfg(feature = "cookies")]
 pub(crate) fn add_cookie_header(
     headers: &mut HeaderMap,
-    cookie_store: Arc<cookie::Jar>,
+    cookie_store: &cookie::Provider,
     url: &Url,
 ) {
     use crate::cookie::CookieStore;
@@ -537,6 +1286,99 @@ pub(crate) fn add_cookie_header(
 
 #[cfg(test)]
 mod tests {
+    use super::{request_to_vec, HeaderWriteOptions};
+    use crate::lunatic_impl::request::HeaderCase;
+    use http::{HeaderMap, HeaderValue, Method, Version};
+
+    fn encode_headers(header_case: Option<&HeaderCase>, title_case: bool) -> String {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", HeaderValue::from_static("1"));
+        headers.insert("accept", HeaderValue::from_static("*/*"));
+        headers.insert("host", HeaderValue::from_static("example.com"));
+        let encoded = request_to_vec(
+            Method::GET,
+            url::Url::parse("http://example.com/").unwrap(),
+            headers,
+            None,
+            Version::HTTP_11,
+            &HeaderWriteOptions {
+                header_case,
+                title_case,
+                ..Default::default()
+            },
+        );
+        String::from_utf8(encoded).unwrap()
+    }
+
+    #[lunatic::test]
+    fn request_headers_keep_insertion_order() {
+        assert_eq!(
+            encode_headers(None, false),
+            "GET / HTTP/1.1\r\nhost: example.com\r\nx-request-id: 1\r\naccept: */*\r\n\r\n"
+        );
+    }
+
+    #[lunatic::test]
+    fn request_headers_keep_order_after_remove() {
+        let mut headers = HeaderMap::new();
+        let mut order = vec![];
+        for name in ["x-first", "x-second", "x-third", "x-fourth"] {
+            headers.insert(name, HeaderValue::from_static("1"));
+            order.push(name.to_string());
+        }
+        // moves the last header into the place of the removed one
+        headers.remove("x-second");
+        headers.append("x-first", HeaderValue::from_static("2"));
+        let encoded = request_to_vec(
+            Method::GET,
+            url::Url::parse("http://example.com/").unwrap(),
+            headers,
+            None,
+            Version::HTTP_11,
+            &HeaderWriteOptions {
+                order: &order,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            String::from_utf8(encoded).unwrap(),
+            "GET / HTTP/1.1\r\nx-first: 1\r\nx-first: 2\r\nx-third: 1\r\nx-fourth: 1\r\n\r\n"
+        );
+    }
+
+    #[lunatic::test]
+    fn request_headers_title_case() {
+        assert_eq!(
+            encode_headers(None, true),
+            "GET / HTTP/1.1\r\nHost: example.com\r\nX-Request-Id: 1\r\nAccept: */*\r\n\r\n"
+        );
+    }
+
+    #[lunatic::test]
+    fn request_headers_preserve_case() {
+        let mut header_case = HeaderCase::new();
+        header_case.insert("x-request-id".to_string(), "X-REQUEST-ID".to_string());
+        assert_eq!(
+            encode_headers(Some(&header_case), true),
+            "GET / HTTP/1.1\r\nHost: example.com\r\nX-REQUEST-ID: 1\r\nAccept: */*\r\n\r\n"
+        );
+    }
+
+    #[lunatic::test]
+    fn request_header_values_are_written_as_bytes() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-latin1", HeaderValue::from_bytes(b"caf\xe9").unwrap());
+        let encoded = request_to_vec(
+            Method::GET,
+            url::Url::parse("http://example.com/").unwrap(),
+            headers,
+            None,
+            Version::HTTP_11,
+            &HeaderWriteOptions::default(),
+        );
+        assert!(encoded.windows(14).any(|w| w == b"x-latin1: caf\xe9"));
+    }
+
     #[lunatic::test]
     fn execute_request_rejects_invald_urls() {
         let url_str = "hxxps://www.rust-lang.org/";
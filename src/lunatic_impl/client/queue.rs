@@ -0,0 +1,351 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use lunatic::abstract_process;
+use lunatic::ap::Config;
+use lunatic::{Process, Tag};
+use serde::{Deserialize, Serialize};
+
+use super::HostRef;
+use crate::Url;
+
+/// A snapshot of the requests a `Client` is sending to a host.
+///
+/// Returned by `Client::queue_status()`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueStatus {
+    /// Number of requests being sent to the host.
+    pub in_flight: usize,
+    /// Number of requests waiting for a free slot for the host.
+    pub queued: usize,
+    /// Number of requests being sent to all hosts.
+    pub total_in_flight: usize,
+    /// How long the first request in the queue for the host has been waiting.
+    pub longest_wait: Option<Duration>,
+}
+
+/// The process of a `Client` that hands out slots with an `Admission`.
+///
+/// It runs next to the client process, so that waiting requests are answered
+/// right away instead of behind the requests the client process is sending.
+/// A request gets a slot from this process in the calling process, and the
+/// client process gives it back once it's done with the request, even if the
+/// caller stopped waiting for it.
+///
+/// A request that has to wait isn't polled for. This process sends its
+/// ticket to the waiting process once it gets a slot.
+pub struct AdmissionProcess {
+    admission: Admission,
+    queue_timeout: Option<Duration>,
+    // where to send the ticket of a waiting request, and with which tag
+    waiting: HashMap<u64, (Process<u64>, Tag)>,
+    // requests that gave up waiting, counted here for the client metrics
+    queue_timeouts: u64,
+}
+
+#[abstract_process(visibility = pub)]
+impl AdmissionProcess {
+    #[init]
+    fn init(
+        _: Config<Self>,
+        limits: (Option<usize>, Option<usize>, Option<Duration>),
+    ) -> Result<Self, ()> {
+        let (max_per_host, max_in_flight, queue_timeout) = limits;
+        Ok(AdmissionProcess {
+            admission: Admission::new(max_per_host, max_in_flight),
+            queue_timeout,
+            waiting: HashMap::new(),
+            queue_timeouts: 0,
+        })
+    }
+
+    /// Queues a request for a slot. Returns `Ok` with its ticket if it got a
+    /// slot right away, or `Err` with the ticket that is sent to `caller`
+    /// with `tag` once it gets one.
+    #[handle_request]
+    fn queue_slot(&mut self, queue: (Url, Process<u64>, Tag)) -> Result<u64, u64> {
+        let (url, caller, tag) = queue;
+        self.prune();
+        let result = self.admission.poll(HostRef::new(&url), caller.id());
+        if let Err(ticket) = result {
+            self.waiting.insert(ticket, (caller, tag));
+        }
+        result
+    }
+
+    /// Removes a request that gave up waiting from the queue. Returns true if
+    /// it got a slot before, which is given back then.
+    #[handle_request]
+    fn cancel_slot(&mut self, cancel: (Url, u64)) -> bool {
+        let (url, ticket) = cancel;
+        let host = HostRef::new(&url);
+        self.queue_timeouts += 1;
+        self.waiting.remove(&ticket);
+        if self.admission.cancel(host.clone(), ticket) {
+            return false;
+        }
+        self.admission.release(host);
+        self.grant();
+        true
+    }
+
+    #[handle_message]
+    fn release_slot(&mut self, url: Url) {
+        self.admission.release(HostRef::new(&url));
+        self.grant();
+    }
+
+    #[handle_request]
+    fn get_queue_timeout(&mut self) -> Option<Duration> {
+        self.queue_timeout
+    }
+
+    #[handle_request]
+    fn has_capacity(&mut self) -> bool {
+        self.admission.has_capacity()
+    }
+
+    #[handle_request]
+    fn queue_status(&mut self, url: Url) -> QueueStatus {
+        self.prune();
+        self.admission.status(&HostRef::new(&url))
+    }
+
+    /// Returns the number of requests that gave up waiting for a slot.
+    #[handle_request]
+    fn queue_timeouts(&mut self) -> u64 {
+        self.queue_timeouts
+    }
+
+    #[handle_request]
+    fn reset_queue_timeouts(&mut self) {
+        self.queue_timeouts = 0;
+    }
+}
+
+impl AdmissionProcess {
+    /// Drops the requests of processes that died while waiting.
+    fn prune(&mut self) {
+        self.admission.prune(is_alive);
+        self.waiting.retain(|_, (caller, _)| is_alive(caller.id()));
+        self.grant();
+    }
+
+    /// Hands out the free slots and tells the requests that got one.
+    fn grant(&mut self) {
+        for ticket in self.admission.grant() {
+            if let Some((caller, tag)) = self.waiting.remove(&ticket) {
+                caller.tag_send(tag, ticket);
+            }
+        }
+    }
+}
+
+fn is_alive(process_id: u64) -> bool {
+    // SAFETY: only asks the runtime whether a process with the id exists
+    unsafe { lunatic::host::api::process::exists(process_id) != 0 }
+}
+
+/// Hands out slots to requests, so that no more than the configured number
+/// of requests are in flight per host and in total. Requests that don't get
+/// a slot wait in a first in, first out queue per host.
+#[derive(Clone, Debug)]
+pub(crate) struct Admission {
+    max_per_host: Option<usize>,
+    max_in_flight: Option<usize>,
+    next_ticket: u64,
+    in_flight: HashMap<HostRef, usize>,
+    total_in_flight: usize,
+    queues: HashMap<HostRef, VecDeque<Waiting>>,
+}
+
+/// A request waiting in the queue of a host.
+#[derive(Clone, Debug)]
+struct Waiting {
+    ticket: u64,
+    // the process waiting for the slot
+    caller: u64,
+    since: Instant,
+}
+
+impl Admission {
+    pub(crate) fn new(max_per_host: Option<usize>, max_in_flight: Option<usize>) -> Self {
+        Admission {
+            max_per_host,
+            max_in_flight,
+            next_ticket: 1,
+            in_flight: HashMap::new(),
+            total_in_flight: 0,
+            queues: HashMap::new(),
+        }
+    }
+
+    /// Gives a slot to a new request of the process `caller`, or queues it
+    /// if there is none free or other requests for the host are waiting.
+    ///
+    /// Returns `Ok` with the ticket of the request if it got a slot, or `Err`
+    /// with the ticket it waits with until `grant` gives it one.
+    pub(crate) fn poll(&mut self, host: HostRef, caller: u64) -> Result<u64, u64> {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        if !self.queues.contains_key(&host) && self.has_room(&host) {
+            self.admit(host);
+            return Ok(ticket);
+        }
+        self.queues.entry(host).or_default().push_back(Waiting {
+            ticket,
+            caller,
+            since: Instant::now(),
+        });
+        Err(ticket)
+    }
+
+    /// Gives the free slots to the requests first in line for their host,
+    /// the one waiting longest first, and returns their tickets.
+    pub(crate) fn grant(&mut self) -> Vec<u64> {
+        let mut granted = vec![];
+        loop {
+            let next = self
+                .queues
+                .iter()
+                .filter(|(host, _)| self.has_room(host))
+                .filter_map(|(host, queue)| queue.front().map(|waiting| (host, waiting.since)))
+                .min_by_key(|(_, since)| *since)
+                .map(|(host, _)| host.clone());
+            let host = match next {
+                Some(host) => host,
+                None => return granted,
+            };
+            if let Some(queue) = self.queues.get_mut(&host) {
+                granted.extend(queue.pop_front().map(|waiting| waiting.ticket));
+                if queue.is_empty() {
+                    self.queues.remove(&host);
+                }
+            }
+            self.admit(host);
+        }
+    }
+
+    /// Removes a request that gave up waiting from the queue. Returns false
+    /// if it isn't queued, as it got a slot already.
+    pub(crate) fn cancel(&mut self, host: HostRef, ticket: u64) -> bool {
+        let mut queued = false;
+        if let Some(queue) = self.queues.get_mut(&host) {
+            queued = queue.iter().any(|waiting| waiting.ticket == ticket);
+            queue.retain(|waiting| waiting.ticket != ticket);
+            if queue.is_empty() {
+                self.queues.remove(&host);
+            }
+        }
+        queued
+    }
+
+    /// Returns true if another request may be sent without exceeding the
+    /// total limit.
+    pub(crate) fn has_capacity(&self) -> bool {
+        self.max_in_flight
+            .map_or(true, |max| self.total_in_flight < max)
+    }
+
+    fn has_room(&self, host: &HostRef) -> bool {
+        let in_flight = self.in_flight.get(host).copied().unwrap_or(0);
+        let host_full = self.max_per_host.map_or(false, |max| in_flight >= max);
+        !host_full && self.has_capacity()
+    }
+
+    fn admit(&mut self, host: HostRef) {
+        *self.in_flight.entry(host).or_default() += 1;
+        self.total_in_flight += 1;
+    }
+
+    /// Removes the requests of processes that died while waiting, so that
+    /// they don't hold up the requests queued after them.
+    pub(crate) fn prune<F>(&mut self, is_alive: F)
+    where
+        F: Fn(u64) -> bool,
+    {
+        for queue in self.queues.values_mut() {
+            queue.retain(|waiting| is_alive(waiting.caller));
+        }
+        self.queues.retain(|_, queue| !queue.is_empty());
+    }
+
+    /// Frees the slot of a request that is done.
+    pub(crate) fn release(&mut self, host: HostRef) {
+        if let Some(in_flight) = self.in_flight.get_mut(&host) {
+            *in_flight -= 1;
+            if *in_flight == 0 {
+                self.in_flight.remove(&host);
+            }
+            self.total_in_flight = self.total_in_flight.saturating_sub(1);
+        }
+    }
+
+    pub(crate) fn status(&self, host: &HostRef) -> QueueStatus {
+        let queue = self.queues.get(host);
+        QueueStatus {
+            in_flight: self.in_flight.get(host).copied().unwrap_or(0),
+            queued: queue.map_or(0, |queue| queue.len()),
+            total_in_flight: self.total_in_flight,
+            longest_wait: queue
+                .and_then(|queue| queue.front())
+                .map(|waiting| waiting.since.elapsed()),
+        }
+    }
+}
+
+#[lunatic::test]
+fn test_admission_per_host_fifo() {
+    let host = HostRef::Http("localhost:80".to_string());
+    let mut admission = Admission::new(Some(1), None);
+
+    let first = admission.poll(host.clone(), 1).unwrap();
+    let second = admission.poll(host.clone(), 2).unwrap_err();
+    let third = admission.poll(host.clone(), 3).unwrap_err();
+    assert_eq!(admission.status(&host).in_flight, 1);
+    assert_eq!(admission.status(&host).queued, 2);
+    assert!(admission.grant().is_empty());
+
+    // the second request goes before the third one
+    admission.release(host.clone());
+    assert_eq!(admission.grant(), vec![second]);
+    admission.release(host.clone());
+    assert_eq!(admission.grant(), vec![third]);
+    assert_ne!(first, third);
+}
+
+#[lunatic::test]
+fn test_admission_total_limit_and_cancel() {
+    let a = HostRef::Http("a:80".to_string());
+    let b = HostRef::Http("b:80".to_string());
+    let c = HostRef::Http("c:80".to_string());
+    let mut admission = Admission::new(None, Some(1));
+
+    assert!(admission.poll(a.clone(), 1).is_ok());
+    let cancelled = admission.poll(b.clone(), 2).unwrap_err();
+    let waiting = admission.poll(c.clone(), 3).unwrap_err();
+    assert!(admission.cancel(b.clone(), cancelled));
+    assert_eq!(admission.status(&b).queued, 0);
+
+    admission.release(a);
+    assert_eq!(admission.grant(), vec![waiting]);
+    // the request got its slot already
+    assert!(!admission.cancel(c, waiting));
+}
+
+#[lunatic::test]
+fn test_admission_prunes_dead_callers() {
+    let host = HostRef::Http("localhost:80".to_string());
+    let mut admission = Admission::new(Some(1), None);
+
+    admission.poll(host.clone(), 1).unwrap();
+    let dead = admission.poll(host.clone(), 2).unwrap_err();
+    let alive = admission.poll(host.clone(), 3).unwrap_err();
+    admission.release(host.clone());
+
+    // the process queued first died, so it can't hold up the next one
+    admission.prune(|caller| caller != 2);
+    assert_eq!(admission.status(&host).queued, 1);
+    assert_eq!(admission.grant(), vec![alive]);
+    assert_ne!(dead, alive);
+}
@@ -0,0 +1,54 @@
+use std::convert::TryFrom;
+use std::future::{ready, Ready};
+use std::task::{Context, Poll};
+
+use http::Request as HttpRequest;
+use tower_service::Service;
+
+use super::queue::AdmissionProcessRequests;
+use crate::{Body, Client, HttpResponse, Request};
+
+// Requests are sent synchronously in `call`, so the returned futures are
+// always ready. Waiting for a free slot for the host of a request happens in
+// `call` too, as only the request tells which host it needs a slot for.
+
+impl Service<Request> for Client {
+    type Response = HttpResponse;
+    type Error = crate::Error;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    /// The client is ready while fewer requests are in flight than allowed
+    /// by `ClientBuilder::max_in_flight()`. `call` still waits in line for a
+    /// slot for the host of the request, as limited by
+    /// `ClientBuilder::max_connections_per_host()`.
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.1.map_or(true, |admission| admission.has_capacity()) {
+            Poll::Ready(Ok(()))
+        } else {
+            // there is nothing to be notified by, so ask to be polled again
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        ready(self.execute(req))
+    }
+}
+
+impl<T> Service<HttpRequest<T>> for Client
+where
+    T: Into<Body>,
+{
+    type Response = HttpResponse;
+    type Error = crate::Error;
+    type Future = Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Service::<Request>::poll_ready(self, cx)
+    }
+
+    fn call(&mut self, req: HttpRequest<T>) -> Self::Future {
+        ready(Request::try_from(req).and_then(|req| self.execute(req)))
+    }
+}
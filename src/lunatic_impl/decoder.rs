@@ -1,11 +1,14 @@
 use std::fmt;
 use std::io::Read;
+use std::time::{Duration, Instant};
 use std::{convert::TryFrom, str::FromStr};
 
 use flate2::read::{GzDecoder, ZlibDecoder};
 
 use http::{
-    header::{CONTENT_ENCODING, CONTENT_LENGTH, TRANSFER_ENCODING},
+    header::{
+        HeaderName, HeaderValue, CONNECTION, CONTENT_ENCODING, CONTENT_LENGTH, TRANSFER_ENCODING,
+    },
     HeaderMap, Method,
 };
 
@@ -14,8 +17,9 @@ use serde::{Deserialize, Serialize};
 
 use super::http_stream::HttpStream;
 use super::request::InnerRequest;
+use super::response::Timings;
 use super::InnerClient;
-use crate::HttpResponse;
+use crate::{HttpResponse, Version};
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub(crate) struct Accepts {
@@ -87,64 +91,102 @@ impl Decoder {
         }
     }
 
-    pub fn decode(&mut self) -> HttpResponse {
+    pub fn decode(&mut self) -> ResponseResult {
         if let MessageEncoding::Octets = self.encoding {
             let reader = &mut self.reader;
             let body = if let Some(content_length) = reader.content_length() {
                 let mut body = vec![0u8; content_length];
-                reader.read_exact(&mut body).unwrap();
+                reader
+                    .read_exact(&mut body)
+                    .map_err(ParseResponseError::BodyReadFailed)?;
                 body
             } else if reader.no_content_length_required() {
                 vec![]
             } else {
-                // this should not happen
-                panic!("Content-encoded body without content-length");
+                // chunked bodies end with the last chunk, all others are
+                // delimited by the server closing the connection
+                let mut body = Vec::new();
+                reader
+                    .read_to_end(&mut body)
+                    .map_err(ParseResponseError::BodyReadFailed)?;
+                body
             };
-            return HttpResponse {
+            return Ok(HttpResponse {
                 headers: reader.res.headers().to_owned(),
                 status: reader.res.status().to_owned(),
-                // transform type into http::Version type
-                version: reader.res.version().into(),
+                // the parser only ever produces HTTP/1.x versions
+                version: Version::try_from(reader.res.version()).unwrap_or_default(),
                 body,
+                coded_body: None,
                 url: reader.req.url.clone(),
                 redirect_chain: vec![],
-            };
+                redirect_history: vec![],
+                info: None,
+                early_hints: HeaderMap::new(),
+                attempts: 1,
+                timings: Timings::default(),
+                attempt_timings: vec![],
+                queue_wait: Duration::from_secs(0),
+                cache_status: None,
+            });
         }
 
-        let buf = if !self.reader.no_content_length_required() {
-            match &self.encoding {
+        let (buf, coded_body) = if !self.reader.no_content_length_required() {
+            let mut coded = Vec::new();
+            self.reader
+                .read_to_end(&mut coded)
+                .map_err(ParseResponseError::BodyReadFailed)?;
+            let buf = match &self.encoding {
                 MessageEncoding::Brotli => {
-                    let mut decoder = brotli::Decompressor::new(&mut self.reader, 4096);
+                    let mut decoder = brotli::Decompressor::new(&coded[..], 4096);
                     let mut buf = Vec::new();
-                    let _ = decoder.read_to_end(&mut buf).unwrap();
+                    decoder
+                        .read_to_end(&mut buf)
+                        .map_err(ParseResponseError::DecompressionFailed)?;
                     buf
                 }
                 MessageEncoding::Gzip => {
-                    let mut decoder = GzDecoder::new(&mut self.reader);
+                    let mut decoder = GzDecoder::new(&coded[..]);
                     let mut buf = Vec::new();
-                    let _ = decoder.read_to_end(&mut buf).unwrap();
+                    decoder
+                        .read_to_end(&mut buf)
+                        .map_err(ParseResponseError::DecompressionFailed)?;
                     // end_buf
                     buf
                 }
                 MessageEncoding::Deflate => {
-                    let mut decoder = ZlibDecoder::new(&mut self.reader);
+                    let mut decoder = ZlibDecoder::new(&coded[..]);
                     let mut buf = Vec::new();
-                    let _ = decoder.read_to_end(&mut buf).unwrap();
+                    decoder
+                        .read_to_end(&mut buf)
+                        .map_err(ParseResponseError::DecompressionFailed)?;
                     buf
                 }
                 _ => panic!("Cannot happen"),
-            }
+            };
+            // a `Content-Digest` is over the content-coded body, see RFC 9530
+            let has_digest = self.reader.res.headers().contains_key("content-digest");
+            (buf, if has_digest { Some(coded) } else { None })
         } else {
-            vec![]
+            (vec![], None)
         };
-        HttpResponse {
+        Ok(HttpResponse {
             headers: self.reader.res.headers().to_owned(),
             status: self.reader.res.status().to_owned(),
-            version: self.reader.res.version().into(),
+            version: Version::try_from(self.reader.res.version()).unwrap_or_default(),
             body: buf,
+            coded_body,
             url: self.reader.req.url.clone(),
             redirect_chain: vec![],
-        }
+            redirect_history: vec![],
+            info: None,
+            early_hints: HeaderMap::new(),
+            attempts: 1,
+            timings: Timings::default(),
+            attempt_timings: vec![],
+            queue_wait: Duration::from_secs(0),
+            cache_status: None,
+        })
     }
 
     fn detect_encoding(headers: &mut HeaderMap, encoding_str: &str) -> bool {
@@ -204,11 +246,73 @@ type ResponseResult = Result<HttpResponse, ParseResponseError>;
 
 #[derive(Debug)]
 pub(crate) enum ParseResponseError {
+    TcpStreamWriteFailed(std::io::Error),
     TcpStreamClosed,
     TcpStreamClosedWithoutData,
     HttpParseError(httparse::Error),
     ResponseTooLarge,
     UnknownCode,
+    ObsoleteLineFolding,
+    BodyReadFailed(std::io::Error),
+    DecompressionFailed(std::io::Error),
+}
+
+impl fmt::Display for ParseResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseResponseError::TcpStreamWriteFailed(err) => {
+                write!(f, "failed to write the request: {}", err)
+            }
+            ParseResponseError::TcpStreamClosed => {
+                f.write_str("connection closed before the response was complete")
+            }
+            ParseResponseError::TcpStreamClosedWithoutData => {
+                f.write_str("connection closed before any response data was received")
+            }
+            ParseResponseError::HttpParseError(err) => write!(f, "invalid response: {}", err),
+            ParseResponseError::ResponseTooLarge => f.write_str("response head too large"),
+            ParseResponseError::UnknownCode => f.write_str("unknown response status code"),
+            ParseResponseError::ObsoleteLineFolding => f.write_str(
+                "response headers use obsolete line folding, which can be allowed with \
+                 `ClientBuilder::http1_allow_obsolete_multiline_headers_in_responses`",
+            ),
+            ParseResponseError::BodyReadFailed(err) => {
+                write!(f, "failed to read the response body: {}", err)
+            }
+            ParseResponseError::DecompressionFailed(err) => {
+                write!(f, "failed to decompress the response body: {}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseResponseError {}
+
+impl ParseResponseError {
+    /// Returns true if the connection failed before any part of the response
+    /// arrived, which is how an idle connection closed by the server shows up.
+    pub(crate) fn is_stale_connection(&self) -> bool {
+        matches!(
+            self,
+            ParseResponseError::TcpStreamWriteFailed(_)
+                | ParseResponseError::TcpStreamClosedWithoutData
+        )
+    }
+}
+
+impl From<ParseResponseError> for crate::Error {
+    fn from(err: ParseResponseError) -> crate::Error {
+        match err {
+            ParseResponseError::HttpParseError(httparse::Error::Version) => {
+                crate::error::version(err)
+            }
+            ParseResponseError::TcpStreamWriteFailed(_)
+            | ParseResponseError::TcpStreamClosed
+            | ParseResponseError::TcpStreamClosedWithoutData
+            | ParseResponseError::BodyReadFailed(_) => crate::error::request(err),
+            _ => crate::error::decode(err),
+        }
+    }
 }
 
 pub(crate) fn parse_response(
@@ -219,16 +323,45 @@ pub(crate) fn parse_response(
 ) -> ResponseResult {
     let mut buffer = [0_u8; REQUEST_BUFFER_SIZE];
     let mut headers = [EMPTY_HEADER; MAX_HEADERS];
-
-    // Loop until at least one complete response is read.
+    // header fields of any `103 Early Hints` responses preceding the final one
+    let mut early_hints = HeaderMap::new();
+    let mut parser_config = httparse::ParserConfig::default();
+    // the request has just been written, so this is where waiting starts
+    let sent = Instant::now();
+    let mut first_byte = None;
+    parser_config.allow_obsolete_multiline_headers_in_responses(
+        client.allow_obsolete_multiline_headers_in_responses,
+    );
+
+    // Loop until at least one complete final response is read.
     let (response_raw, offset) = loop {
         // In case of pipelined responses the `response_buffer` is going to come
         // prefilled with some data, and we should attempt to parse it into a response
         // before we decide to read more from `TcpStream`.
         let mut response_raw = httparse::Response::new(&mut headers);
-        match response_raw.parse(&response_buffer) {
+        match parser_config.parse_response(&mut response_raw, &response_buffer) {
             Ok(state) => match state {
                 Status::Complete(offset) => {
+                    let code = response_raw.code.unwrap_or_default();
+                    // Interim responses (1xx, except for `101 Switching Protocols`)
+                    // carry no body and are followed by the final response on the
+                    // same connection, so we consume them and keep reading.
+                    if (100..200).contains(&code) && code != 101 {
+                        lunatic_log::debug!("Skipping interim response {}", code);
+                        if code == 103 {
+                            for header in response_raw.headers.iter() {
+                                if let (Ok(name), Ok(value)) = (
+                                    HeaderName::from_bytes(header.name.as_bytes()),
+                                    HeaderValue::from_bytes(&unfold(header.value)),
+                                ) {
+                                    early_hints.append(name, value);
+                                }
+                            }
+                        }
+                        headers = [EMPTY_HEADER; MAX_HEADERS];
+                        response_buffer.drain(..offset);
+                        continue;
+                    }
                     // Continue outside the loop.
                     break (response_raw, offset);
                 }
@@ -243,6 +376,7 @@ pub(crate) fn parse_response(
                         }
                     }
                     let n = n.unwrap();
+                    first_byte.get_or_insert_with(Instant::now);
                     // Invalidate references in `headers` that could point to the previous
                     // `response_buffer` before extending it.
                     headers = [EMPTY_HEADER; MAX_HEADERS];
@@ -253,6 +387,21 @@ pub(crate) fn parse_response(
                     }
                 }
             },
+            // HTTP/0.9 responses have no status line at all
+            Err(httparse::Error::Version)
+                if client.http09_responses && !response_buffer.starts_with(b"HTTP/") =>
+            {
+                return parse_http09_response(response_buffer, stream, req).map(|mut res| {
+                    res.timings.record_response(sent, first_byte);
+                    client
+                        .metrics
+                        .record_received(res.body.len(), res.body.len());
+                    res
+                });
+            }
+            Err(_) if has_obsolete_line_folding(&response_buffer) => {
+                return Err(ParseResponseError::ObsoleteLineFolding);
+            }
             Err(err) => {
                 return Err(ParseResponseError::HttpParseError(err));
             }
@@ -270,12 +419,18 @@ pub(crate) fn parse_response(
             return Err(ParseResponseError::UnknownCode);
         }
     };
-    let response = http::Response::builder().status(status_code);
+    let version = match response_raw.version {
+        Some(0) => http::Version::HTTP_10,
+        _ => http::Version::HTTP_11,
+    };
+    let response = http::Response::builder()
+        .status(status_code)
+        .version(version);
     let response = response_raw
         .headers
         .iter()
         .fold(response, |response, header| {
-            response.header(header.name, header.value)
+            response.header(header.name, unfold(header.value))
         });
 
     let reader = HttpBodyReader {
@@ -288,7 +443,119 @@ pub(crate) fn parse_response(
         chunk_body: vec![],
         chunk_offset: 0,
     };
-    Ok(Decoder::detect(reader, client.accepts()).decode())
+    let mut decoder = Decoder::detect(reader, client.accepts());
+    let mut res = decoder.decode()?;
+    // everything up to the offset of the reader was consumed for this response
+    client
+        .metrics
+        .record_received(decoder.reader.offset, res.body.len());
+    res.early_hints = early_hints;
+    res.timings.record_response(sent, first_byte);
+    Ok(res)
+}
+
+/// Replaces each obsolete line folding in a header value, a line break and
+/// the whitespace starting the next line, with a single space.
+fn unfold(value: &[u8]) -> Vec<u8> {
+    let mut unfolded = Vec::with_capacity(value.len());
+    let mut bytes = value.iter().copied().peekable();
+    while let Some(b) = bytes.next() {
+        if b != b'\r' && b != b'\n' {
+            unfolded.push(b);
+            continue;
+        }
+        while let Some(b'\r') | Some(b'\n') | Some(b' ') | Some(b'\t') = bytes.peek() {
+            bytes.next();
+        }
+        unfolded.push(b' ');
+    }
+    unfolded
+}
+
+/// Checks whether the response head contains a header line continued with
+/// obsolete line folding, that is a line starting with a space or a tab.
+fn has_obsolete_line_folding(response_buffer: &[u8]) -> bool {
+    let head_end = response_buffer
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .unwrap_or(response_buffer.len());
+    response_buffer[..head_end]
+        .windows(2)
+        .any(|w| w[0] == b'\n' && (w[1] == b' ' || w[1] == b'\t'))
+}
+
+/// An HTTP/0.9 response is nothing but the body, which ends when the
+/// server closes the connection.
+fn parse_http09_response(
+    mut response_buffer: Vec<u8>,
+    mut stream: HttpStream,
+    req: InnerRequest,
+) -> ResponseResult {
+    let mut buffer = [0_u8; REQUEST_BUFFER_SIZE];
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => response_buffer.extend(&buffer[..n]),
+        }
+        if response_buffer.len() > MAX_REQUEST_SIZE {
+            return Err(ParseResponseError::ResponseTooLarge);
+        }
+    }
+    Ok(HttpResponse {
+        headers: HeaderMap::new(),
+        status: http::StatusCode::OK,
+        version: Version::HTTP_09,
+        body: response_buffer,
+        coded_body: None,
+        url: req.url,
+        redirect_chain: vec![],
+        redirect_history: vec![],
+        info: None,
+        early_hints: HeaderMap::new(),
+        attempts: 1,
+        timings: Timings::default(),
+        attempt_timings: vec![],
+        queue_wait: Duration::from_secs(0),
+        cache_status: None,
+    })
+}
+
+/// Tells whether the connection a response was read from can be used for
+/// another request.
+///
+/// HTTP/1.1 connections persist unless either side asks to close them, while
+/// HTTP/1.0 connections only persist if the server explicitly asks for
+/// `keep-alive`. A body delimited by the server closing the connection always
+/// ends the connection.
+pub(crate) fn is_keep_alive(res: &HttpResponse, req_headers: &HeaderMap, method: &Method) -> bool {
+    let has_token = |headers: &HeaderMap, token: &str| {
+        headers.get_all(CONNECTION).iter().any(|value| {
+            value
+                .to_str()
+                .map(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+                .unwrap_or(false)
+        })
+    };
+    if has_token(req_headers, "close") {
+        return false;
+    }
+    let persistent = match res.version {
+        Version::HTTP_11 => !has_token(&res.headers, "close"),
+        Version::HTTP_10 => has_token(&res.headers, "keep-alive"),
+        _ => false,
+    };
+    let status = res.status.as_u16();
+    let close_delimited = !res.headers.contains_key(CONTENT_LENGTH)
+        && !res
+            .headers
+            .get_all(TRANSFER_ENCODING)
+            .iter()
+            .any(|enc| enc == "chunked")
+        && *method != Method::HEAD
+        && status != 204
+        && status != 304
+        && !(100..200).contains(&status);
+    persistent && !close_delimited
 }
 
 pub struct HttpBodyReader {
@@ -1,10 +1,12 @@
 use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::time::Instant;
 
-use lunatic::net::{TcpStream, TlsStream};
+use lunatic::net::{self, TcpStream, TlsStream};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-use crate::error::Kind;
+use super::response::Timings;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum HttpStream {
@@ -12,34 +14,72 @@ pub enum HttpStream {
     Tls(TlsStream),
 }
 
+/// An open `HttpStream`, the address it is connected to and how long it
+/// took to open.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Connection {
+    pub stream: HttpStream,
+    pub remote_addr: Option<SocketAddr>,
+    pub timings: Timings,
+}
+
 impl HttpStream {
-    pub fn connect(url: Url) -> crate::Result<HttpStream> {
+    pub fn connect(url: Url) -> crate::Result<Connection> {
         let protocol = url.scheme();
         if protocol == "https" {
             let conn_str = format!("{}", url.host().unwrap());
-            return match TlsStream::connect(&conn_str, url.port().unwrap_or(443).into()) {
-                Ok(stream) => Ok(HttpStream::Tls(stream)),
+            let port = url.port().unwrap_or(443);
+            // the runtime resolves the host name itself for the TLS handshake
+            // and doesn't tell which address it connected to
+            let started = Instant::now();
+            return match TlsStream::connect(&conn_str, port.into()) {
+                Ok(stream) => {
+                    let mut timings = Timings::default();
+                    timings.record_connect(None, None, Some(started.elapsed()));
+                    Ok(Connection {
+                        stream: HttpStream::Tls(stream),
+                        remote_addr: None,
+                        timings,
+                    })
+                }
                 Err(e) => {
                     lunatic_log::error!("Failed to connect via TLS {:?}", e);
-                    Err(crate::Error::new(
-                        Kind::Builder,
-                        Some("Failed to connect".to_string()),
-                    ))
+                    Err(crate::error::connect(e).with_url(url))
                 }
             };
         }
         let conn_str = format!("{}:{}", url.host().unwrap(), url.port().unwrap_or(80));
         lunatic_log::debug!("Connecting {:?} | {:?}", protocol, conn_str);
-        match TcpStream::connect(conn_str) {
-            Ok(stream) => Ok(HttpStream::Tcp(stream)),
+        let started = Instant::now();
+        let addrs = match net::resolve(&conn_str) {
+            Ok(addrs) => addrs,
             Err(e) => {
-                lunatic_log::error!("Failed to connect via TCP {:?}", e);
-                Err(crate::Error::new(
-                    Kind::Builder,
-                    Some("Failed to connect".to_string()),
-                ))
+                lunatic_log::error!("Failed to resolve {:?} {:?}", conn_str, e);
+                return Err(crate::error::connect(e).with_url(url));
+            }
+        };
+        let resolve = started.elapsed();
+        let started = Instant::now();
+        let mut last_err = None;
+        for addr in addrs {
+            match TcpStream::connect(addr) {
+                Ok(stream) => {
+                    let mut timings = Timings::default();
+                    timings.record_connect(Some(resolve), Some(started.elapsed()), None);
+                    return Ok(Connection {
+                        stream: HttpStream::Tcp(stream),
+                        remote_addr: Some(addr),
+                        timings,
+                    });
+                }
+                Err(e) => last_err = Some(e),
             }
         }
+        let e = last_err.unwrap_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses to connect to")
+        });
+        lunatic_log::error!("Failed to connect via TCP {:?}", e);
+        Err(crate::error::connect(e).with_url(url))
     }
 }
 
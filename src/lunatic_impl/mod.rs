@@ -1,7 +1,7 @@
 pub use self::body::Body;
-pub use self::client::{Client, ClientBuilder, InnerClient};
+pub use self::client::{Client, ClientBuilder, InnerClient, QueueStatus};
 pub use self::request::{Request, RequestBuilder};
-pub use self::response::{HttpResponse, SerializableResponse};
+pub use self::response::{HttpInfo, HttpResponse, SerializableResponse, Timings};
 // pub use self::upgrade::Upgraded;
 
 pub mod body;
@@ -14,6 +14,7 @@ use super::client::InnerClient;
 // #[cfg(feature = "multipart")]
 // use super::multipart;
 use super::response::HttpResponse;
+use crate::auth::{digest, MessageSigner, SigV4};
 #[cfg(feature = "cookies")]
 use crate::cookie::{self, CookieStore};
 #[cfg(feature = "multipart")]
@@ -22,8 +23,8 @@ use crate::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
 use crate::into_url::try_uri;
 #[cfg(feature = "cookies")]
 use crate::lunatic_impl::client::add_cookie_header;
-use crate::redirect::remove_sensitive_headers;
-use crate::{error, redirect, Body, Client, Method, Url, Version};
+use crate::redirect::{is_cross_host, remove_sensitive_headers, RedirectChange, RedirectHop};
+use crate::{error, rate_limit, redirect, retry, Body, Client, Method, Url, Version};
 use http::{request::Parts, Request as HttpRequest};
 
 /// A request which can be executed with `Client::execute()`.
@@ -35,16 +36,53 @@ pub struct Request {
     pub(crate) body: Option<Body>,
     pub(crate) timeout: Option<Duration>,
     pub(crate) version: Version,
+    pub(crate) header_case: HeaderCase,
+    pub(crate) header_order: HeaderOrder,
+    pub(crate) retry: Option<retry::Policy>,
+    pub(crate) rate_limit_key: Option<String>,
+    pub(crate) rate_limit_mode: Option<rate_limit::Mode>,
+    pub(crate) digest_auth: Option<digest::Credentials>,
+    pub(crate) aws_sigv4: Option<SigV4>,
+    pub(crate) message_signature: Option<MessageSigner>,
+    // the headers the client process adds unless the request sets them,
+    // like default headers and cookies, filled in before middleware runs
+    pub(crate) client_headers: HeaderMap,
+    // what the cache keeps about the request until its response arrives
+    pub(crate) cache_pending: Option<crate::cache::Pending>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct InnerRequest {
     pub(crate) method: String,
     pub(crate) url: Url,
-    pub(crate) headers: HashMap<String, Vec<String>>,
+    // kept as ordered raw pairs so neither the order nor non-UTF-8 values get lost
+    pub(crate) headers: Vec<(String, Vec<u8>)>,
     pub(crate) body: Option<Body>,
     pub(crate) timeout: Option<Duration>,
     pub(crate) version: Version,
+    pub(crate) header_case: HeaderCase,
+    pub(crate) header_order: HeaderOrder,
+    pub(crate) rate_limit_key: Option<String>,
+    pub(crate) rate_limit_mode: Option<rate_limit::Mode>,
+    pub(crate) digest_auth: Option<digest::Credentials>,
+    pub(crate) aws_sigv4: Option<SigV4>,
+    pub(crate) message_signature: Option<MessageSigner>,
+    // whether the OAuth 2.0 access token was renewed after a `401`
+    pub(crate) oauth2_renewed: bool,
+}
+
+/// The original casing of header names, keyed by their lowercase form.
+pub(crate) type HeaderCase = HashMap<String, String>;
+
+/// The lowercase names of the headers of a request in the order they were
+/// first added, as a `HeaderMap` doesn't keep it once headers are removed.
+pub(crate) type HeaderOrder = Vec<String>;
+
+/// Adds `name` to `order` unless it's in there already.
+pub(crate) fn push_header_order(order: &mut HeaderOrder, name: &HeaderName) {
+    if !order.iter().any(|known| known == name.as_str()) {
+        order.push(name.as_str().to_string());
+    }
 }
 
 /// A builder to construct the properties of a `Request`.
@@ -64,14 +102,42 @@ impl TryFrom<Request> for InnerRequest {
         Ok(InnerRequest {
             method: value.method.to_string(),
             url: value.url,
-            headers: hashmap_from_header_map(value.headers),
+            headers: pairs_from_header_map(&value.headers),
             body: value.body,
             timeout: value.timeout,
             version: value.version,
+            header_case: value.header_case,
+            header_order: value.header_order,
+            rate_limit_key: value.rate_limit_key,
+            rate_limit_mode: value.rate_limit_mode,
+            digest_auth: value.digest_auth,
+            aws_sigv4: value.aws_sigv4,
+            message_signature: value.message_signature,
+            oauth2_renewed: false,
         })
     }
 }
 
+pub(crate) fn pairs_from_header_map(headers: &HeaderMap) -> Vec<(String, Vec<u8>)> {
+    headers
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.as_bytes().to_vec()))
+        .collect()
+}
+
+pub(crate) fn header_map_from_pairs(headers: Vec<(String, Vec<u8>)>) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for (k, v) in headers {
+        if let (Ok(k), Ok(v)) = (
+            HeaderName::from_bytes(k.as_bytes()),
+            HeaderValue::from_bytes(&v),
+        ) {
+            map.append(k, v);
+        }
+    }
+    map
+}
+
 pub(crate) fn hashmap_from_header_map(headers: HeaderMap) -> HashMap<String, Vec<String>> {
     let mut map: HashMap<String, Vec<String>> = HashMap::new();
     let mut curr_key = String::new();
@@ -116,7 +182,7 @@ impl InnerRequest {
         (
             Method::from_str(self.method.as_str()).unwrap(),
             self.url,
-            header_map_from_hashmap(self.headers),
+            header_map_from_pairs(self.headers),
             self.body,
             self.timeout,
             self.version,
@@ -124,6 +190,29 @@ impl InnerRequest {
     }
 }
 
+impl From<InnerRequest> for Request {
+    fn from(inner: InnerRequest) -> Request {
+        Request {
+            method: Method::from_str(inner.method.as_str()).unwrap_or_default(),
+            url: inner.url,
+            headers: header_map_from_pairs(inner.headers),
+            body: inner.body,
+            timeout: inner.timeout,
+            version: inner.version,
+            header_case: inner.header_case,
+            header_order: inner.header_order,
+            retry: None,
+            rate_limit_key: inner.rate_limit_key,
+            rate_limit_mode: inner.rate_limit_mode,
+            digest_auth: inner.digest_auth,
+            aws_sigv4: inner.aws_sigv4,
+            message_signature: inner.message_signature,
+            client_headers: HeaderMap::new(),
+            cache_pending: None,
+        }
+    }
+}
+
 impl Request {
     /// Constructs a new request.
     #[inline]
@@ -135,6 +224,16 @@ impl Request {
             body: None,
             timeout: None,
             version: Version::default(),
+            header_case: HeaderCase::new(),
+            header_order: HeaderOrder::new(),
+            retry: None,
+            rate_limit_key: None,
+            rate_limit_mode: None,
+            digest_auth: None,
+            aws_sigv4: None,
+            message_signature: None,
+            client_headers: HeaderMap::new(),
+            cache_pending: None,
         }
     }
 
@@ -239,6 +338,24 @@ impl RequestBuilder {
         self.header_sensitive(key, value, false)
     }
 
+    /// Add a `Header` to this Request, remembering the exact casing of its name.
+    ///
+    /// Header names are case-insensitive and are normally sent in lowercase.
+    /// If the `Client` was built with
+    /// [`http1_preserve_header_case`](crate::ClientBuilder::http1_preserve_header_case),
+    /// the name of this header is written exactly as given here instead.
+    pub fn header_with_case<V>(mut self, key: &str, value: V) -> RequestBuilder
+    where
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
+    {
+        if let Ok(ref mut req) = self.request {
+            req.header_case
+                .insert(key.to_ascii_lowercase(), key.to_string());
+        }
+        self.header(key, value)
+    }
+
     /// Add a `Header` to this Request with ability to define if header_value is sensitive.
     fn header_sensitive<K, V>(mut self, key: K, value: V, sensitive: bool) -> RequestBuilder
     where
@@ -258,6 +375,7 @@ impl RequestBuilder {
                         if sensitive {
                             value.set_sensitive(true);
                         }
+                        push_header_order(&mut req.header_order, &key);
                         req.headers_mut().append(key, value);
                     }
                     Err(e) => error = Some(crate::error::builder(e.into())),
@@ -276,6 +394,9 @@ impl RequestBuilder {
     /// The headers will be merged in to any already set.
     pub fn headers(mut self, headers: crate::header::HeaderMap) -> RequestBuilder {
         if let Ok(ref mut req) = self.request {
+            for name in headers.keys() {
+                push_header_order(&mut req.header_order, name);
+            }
             crate::util::replace_headers(req.headers_mut(), headers);
         }
         self
@@ -313,6 +434,66 @@ impl RequestBuilder {
         self.header_sensitive(crate::header::AUTHORIZATION, header_value, true)
     }
 
+    /// Enable HTTP digest authentication, see RFC 7616.
+    ///
+    /// If the server answers with `401 Unauthorized` and a
+    /// `WWW-Authenticate: Digest` challenge, the request is sent again with
+    /// an `Authorization` header answering the challenge. MD5 and SHA-256
+    /// are supported, with or without `qop=auth`.
+    ///
+    /// The client remembers the challenge, so that later requests with
+    /// `digest_auth` to the same protection space, including redirects, are
+    /// authenticated without a `401` first.
+    ///
+    /// ```rust
+    /// # use nightfly::Error;
+    /// #
+    /// # fn run() -> Result<(), Error> {
+    /// let client = nightfly::Client::new();
+    /// let resp = client.get("http://httpbin.org/digest-auth/auth/user/passwd")
+    ///     .digest_auth("user", "passwd")
+    ///     .send();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn digest_auth<U, P>(mut self, username: U, password: P) -> RequestBuilder
+    where
+        U: fmt::Display,
+        P: fmt::Display,
+    {
+        if let Ok(ref mut req) = self.request {
+            req.digest_auth = Some(digest::Credentials::new(
+                username.to_string(),
+                password.to_string(),
+            ));
+        }
+        self
+    }
+
+    /// Sign the request with AWS Signature Version 4.
+    ///
+    /// The request is signed right before it's sent, after the default
+    /// headers of the client are added, and signed again for redirects to
+    /// the same host and for retries. See [`SigV4`].
+    pub fn aws_sigv4(mut self, sigv4: SigV4) -> RequestBuilder {
+        if let Ok(ref mut req) = self.request {
+            req.aws_sigv4 = Some(sigv4);
+        }
+        self
+    }
+
+    /// Sign the request with HTTP Message Signatures, see RFC 9421.
+    ///
+    /// Like [`aws_sigv4`](RequestBuilder::aws_sigv4), the request is signed
+    /// right before it's sent, and signed again for redirects to the same
+    /// host and for retries. See [`MessageSigner`].
+    pub fn message_signature(mut self, signer: MessageSigner) -> RequestBuilder {
+        if let Ok(ref mut req) = self.request {
+            req.message_signature = Some(signer);
+        }
+        self
+    }
+
     /// Enable HTTP bearer authentication.
     pub fn bearer_auth<T>(self, token: T) -> RequestBuilder
     where
@@ -375,6 +556,37 @@ impl RequestBuilder {
         self
     }
 
+    /// Sets the retry policy for this request.
+    ///
+    /// It overrides the policy configured using `ClientBuilder::retry()`.
+    pub fn retry(mut self, policy: retry::Policy) -> RequestBuilder {
+        if let Ok(ref mut req) = self.request {
+            req.retry = Some(policy);
+        }
+        self
+    }
+
+    /// Sets the key this request is rate limited by, in addition to its
+    /// host, for example the user on whose behalf it is sent.
+    ///
+    /// See `rate_limit::Policy::per_key()`.
+    pub fn rate_limit_key<K: Into<String>>(mut self, key: K) -> RequestBuilder {
+        if let Ok(ref mut req) = self.request {
+            req.rate_limit_key = Some(key.into());
+        }
+        self
+    }
+
+    /// Sets what to do with this request if the rate limit is exceeded.
+    ///
+    /// It overrides the mode configured using `ClientBuilder::rate_limit()`.
+    pub fn rate_limit_mode(mut self, mode: rate_limit::Mode) -> RequestBuilder {
+        if let Ok(ref mut req) = self.request {
+            req.rate_limit_mode = Some(mode);
+        }
+        self
+    }
+
     // /// Sends a multipart/form-data body.
     // ///
     // /// ```
@@ -655,13 +867,24 @@ where
             ..
         } = parts;
         let url = Url::parse(&uri.to_string()).map_err(crate::error::builder)?;
+        let header_order = headers.keys().map(|name| name.to_string()).collect();
         Ok(Request {
             method,
             url,
             headers,
             body: Some(body.into()),
             timeout: None,
-            version: Version::from(version),
+            version: Version::try_from(version)?,
+            header_case: HeaderCase::new(),
+            header_order,
+            retry: None,
+            rate_limit_key: None,
+            rate_limit_mode: None,
+            digest_auth: None,
+            aws_sigv4: None,
+            message_signature: None,
+            client_headers: HeaderMap::new(),
+            cache_pending: None,
         })
     }
 }
@@ -699,6 +922,11 @@ pub(crate) struct PendingRequest<'a> {
     // client_process: ProcessRef<Client>,
     req: InnerRequest,
     urls: Vec<Url>,
+    /// time between sending the request and parsing the response
+    elapsed: Duration,
+    // whether the client sent its OAuth 2.0 access token, decided with the
+    // default headers in place
+    uses_oauth2: bool,
 }
 
 impl<'a> PendingRequest<'a> {
@@ -708,6 +936,8 @@ impl<'a> PendingRequest<'a> {
         // client_process: ProcessRef<Client>,
         req: InnerRequest,
         urls: Vec<Url>,
+        elapsed: Duration,
+        uses_oauth2: bool,
     ) -> Self {
         Self {
             res,
@@ -715,6 +945,8 @@ impl<'a> PendingRequest<'a> {
             // client_process,
             req,
             urls,
+            elapsed,
+            uses_oauth2,
         }
     }
 
@@ -728,32 +960,44 @@ impl<'a> PendingRequest<'a> {
                     cookie::extract_response_cookie_headers(self.res.headers()).peekable();
                 if cookies.peek().is_some() {
                     cookie_store.set_cookies(&mut cookies, &self.req.url);
+                    self.client.autosave_cookies();
                 }
             }
         }
-        self.urls.push(self.req.url.clone());
-        let should_redirect = match self.res.status() {
-            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND | StatusCode::SEE_OTHER => {
-                // self.body = None;
-                for header in &[
-                    TRANSFER_ENCODING,
-                    CONTENT_ENCODING,
-                    CONTENT_TYPE,
-                    CONTENT_LENGTH,
-                ] {
-                    self.res.headers.remove(header);
-                }
-
-                match self.req.method.as_str() {
-                    "GET" | "HEAD" => {}
-                    _ => {
-                        self.req.method = "GET".to_string();
-                    }
+        if self.res.status() == StatusCode::UNAUTHORIZED {
+            if let Some(ref credentials) = self.req.digest_auth {
+                let answer =
+                    self.client
+                        .digest
+                        .challenge(credentials, &self.req.url, self.res.headers());
+                if let Some(credentials) = answer {
+                    // send the request again, the client adds the
+                    // `Authorization` header from the stored challenge
+                    let mut req = self.req.clone();
+                    req.digest_auth = Some(credentials);
+                    return self.client.execute_request(req, self.urls);
                 }
-                true
             }
-            StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => true,
-            _ => false,
+        }
+        if self.res.status() == StatusCode::UNAUTHORIZED
+            && !self.req.oauth2_renewed
+            && self.uses_oauth2
+        {
+            // the access token may have been revoked, renew it once
+            if let Some(ref mut source) = self.client.oauth2 {
+                source.invalidate();
+            }
+            let mut req = self.req.clone();
+            req.oauth2_renewed = true;
+            return self.client.execute_request(req, self.urls);
+        }
+        self.urls.push(self.req.url.clone());
+        // see RFC 9110, section 15.4
+        let (should_redirect, rewrite_to_get) = match self.res.status() {
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => (true, self.req.method == "POST"),
+            StatusCode::SEE_OTHER => (true, self.req.method != "GET" && self.req.method != "HEAD"),
+            StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => (true, false),
+            _ => (false, false),
         };
         if should_redirect {
             let loc = self.res.headers().get(LOCATION).and_then(|val| {
@@ -785,7 +1029,7 @@ impl<'a> PendingRequest<'a> {
             });
 
             // map headers back to http type because it can handle multiple headers
-            let mut headers = header_map_from_hashmap(self.req.headers.clone());
+            let mut headers = header_map_from_pairs(self.req.headers.clone());
             if let Some(loc) = loc {
                 if self.client.referer {
                     if let Some(referer) = make_referer(&loc, &self.req.url) {
@@ -793,10 +1037,12 @@ impl<'a> PendingRequest<'a> {
                     }
                 }
 
-                let action = self
-                    .client
-                    .redirect_policy
-                    .check(self.res.status(), &loc, &self.urls);
+                let action = self.client.redirect_policy.check(
+                    self.res.status(),
+                    &loc,
+                    &self.urls,
+                    &self.client.redirect_rules,
+                );
 
                 match action {
                     redirect::ActionKind::Follow => {
@@ -811,33 +1057,85 @@ impl<'a> PendingRequest<'a> {
                             return Err(error::redirect(error::url_bad_scheme(loc.clone()), loc));
                         }
 
-                        self.req.url = loc.clone();
+                        let hop_url = std::mem::replace(&mut self.req.url, loc.clone());
+                        let mut changes = Vec::new();
+
+                        // it's fine to unwrap here because the method was constructed
+                        // with a valid builder
+                        let mut method = Method::from_str(self.req.method.as_str()).unwrap();
+                        let mut body = self.req.body.take();
+                        if rewrite_to_get {
+                            changes.push(RedirectChange::MethodRewritten {
+                                from: method.to_string(),
+                                to: Method::GET.to_string(),
+                            });
+                            method = Method::GET;
+                            if body.take().is_some() {
+                                changes.push(RedirectChange::BodyDropped);
+                            }
+                            for name in &[
+                                CONTENT_TYPE,
+                                CONTENT_LENGTH,
+                                CONTENT_ENCODING,
+                                TRANSFER_ENCODING,
+                            ] {
+                                if headers.remove(name).is_some() {
+                                    changes.push(RedirectChange::HeaderRemoved(name.to_string()));
+                                }
+                            }
+                        }
 
-                        remove_sensitive_headers(&mut headers, &self.req.url, &self.urls);
+                        let removed =
+                            remove_sensitive_headers(&mut headers, &self.req.url, &self.urls);
+                        changes.extend(removed.into_iter().map(RedirectChange::HeaderRemoved));
+                        // credentials only follow redirects to the same host
+                        let cross_host = is_cross_host(&self.req.url, &self.urls);
 
                         // push new url to chain of redirects
                         self.urls.push(loc);
 
-                        let mut req = Request::new(
-                            // it's fine to unwrap here because the method was constructed with a valid builder
-                            Method::from_str(self.req.method.as_str()).unwrap(),
-                            self.req.url.clone(),
-                        );
-                        req.headers = headers.clone();
-
                         // Add cookies from the cookie store.
                         #[cfg(feature = "cookies")]
                         {
                             if let Some(ref cookie_store) = self.client.cookie_store {
-                                add_cookie_header(
-                                    &mut headers,
-                                    cookie_store.clone(),
-                                    &self.req.url,
-                                );
+                                add_cookie_header(&mut headers, cookie_store, &self.req.url);
                             }
                         }
 
-                        return self.client.execute_request(req.try_into()?, self.urls);
+                        let mut req = Request::new(method, self.req.url.clone());
+                        req.headers = headers;
+                        req.body = body;
+                        req.header_case = self.req.header_case.clone();
+                        req.header_order = self.req.header_order.clone();
+                        if !cross_host {
+                            req.digest_auth = self.req.digest_auth.take();
+                            req.aws_sigv4 = self.req.aws_sigv4.take();
+                            req.message_signature = self.req.message_signature.take();
+                        }
+
+                        let res_headers = self.res.headers();
+                        let hop_headers = self
+                            .client
+                            .redirect_history_headers
+                            .iter()
+                            .flat_map(|name| {
+                                res_headers
+                                    .get_all(name.as_str())
+                                    .into_iter()
+                                    .map(move |value| (name.clone(), value.as_bytes().to_vec()))
+                            })
+                            .collect();
+                        let hop = RedirectHop::new(
+                            hop_url,
+                            self.res.status(),
+                            hop_headers,
+                            self.elapsed,
+                            self.res.timings.clone(),
+                            changes,
+                        );
+                        let mut res = self.client.execute_request(req.try_into()?, self.urls)?;
+                        res.redirect_history.insert(0, hop);
+                        return Ok(res);
                     }
                     redirect::ActionKind::Stop => {
                         lunatic_log::debug!("redirect policy disallowed redirection to '{}'", loc);
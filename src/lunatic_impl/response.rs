@@ -1,6 +1,7 @@
 use std::convert::TryFrom;
 use std::fmt;
 use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime};
 use std::{borrow::Cow, collections::HashMap};
 
 use bytes::Bytes;
@@ -10,24 +11,169 @@ use mime::Mime;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use url::Url;
 
+use crate::auth::SignatureVerifier;
+use crate::cache::CacheStatus;
 #[cfg(feature = "cookies")]
 use crate::cookie;
+use crate::redirect::RedirectHop;
 use crate::Version;
 
-use super::request::header_map_from_hashmap;
+use super::http_stream::{Connection, HttpStream};
+use super::request::{hashmap_from_header_map, header_map_from_hashmap};
+
+/// Information about the connection a `Response` was received on.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HttpInfo {
+    remote_addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
+    tls: bool,
+    version: Version,
+    reused: bool,
+}
 
-// /// Extra information about the transport when an HttpConnector is used.
-// #[derive(Clone, Debug)]
-// pub struct HttpInfo {
-//     remote_addr: SocketAddr,
-//     local_addr: SocketAddr,
-// }
+impl HttpInfo {
+    pub(crate) fn new(conn: &Connection, version: Version, reused: bool) -> HttpInfo {
+        HttpInfo {
+            remote_addr: conn.remote_addr,
+            // lunatic doesn't report the local end of a connection yet
+            local_addr: None,
+            tls: matches!(conn.stream, HttpStream::Tls(_)),
+            version,
+            reused,
+        }
+    }
+
+    /// Get the address of the server the connection was made to.
+    ///
+    /// This is `None` for TLS connections, as the runtime doesn't report
+    /// the address it connected to.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// Get the local address of the connection, if the runtime reports it.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Returns true if the connection used TLS.
+    pub fn is_tls(&self) -> bool {
+        self.tls
+    }
+
+    /// Get the HTTP version spoken on the connection.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Returns true if the connection was reused from the pool rather than
+    /// freshly opened.
+    pub fn is_reused(&self) -> bool {
+        self.reused
+    }
+}
+
+/// How long each phase of getting a `Response` took.
+///
+/// The connection phases are `None` if a pooled connection was reused.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Timings {
+    resolve: Option<Duration>,
+    connect: Option<Duration>,
+    tls_handshake: Option<Duration>,
+    request_write: Duration,
+    first_byte: Duration,
+    body: Duration,
+}
+
+impl Timings {
+    pub(crate) fn record_connect(
+        &mut self,
+        resolve: Option<Duration>,
+        connect: Option<Duration>,
+        tls_handshake: Option<Duration>,
+    ) {
+        self.resolve = resolve;
+        self.connect = connect;
+        self.tls_handshake = tls_handshake;
+    }
+
+    pub(crate) fn record_connection(&mut self, conn: &Timings) {
+        self.resolve = conn.resolve;
+        self.connect = conn.connect;
+        self.tls_handshake = conn.tls_handshake;
+    }
+
+    pub(crate) fn record_write(&mut self, request_write: Duration) {
+        self.request_write = request_write;
+    }
+
+    /// Records the response phases for a request written at `sent`, whose
+    /// response started arriving at `first_byte` and is complete now.
+    pub(crate) fn record_response(&mut self, sent: Instant, first_byte: Option<Instant>) {
+        let first_byte = first_byte.unwrap_or(sent);
+        self.first_byte = first_byte.saturating_duration_since(sent);
+        self.body = first_byte.elapsed();
+    }
+
+    /// Get the time it took to resolve the host name.
+    ///
+    /// This is `None` for TLS connections, as the runtime resolves the host
+    /// name as part of the handshake.
+    pub fn resolve(&self) -> Option<Duration> {
+        self.resolve
+    }
+
+    /// Get the time it took to open the TCP connection.
+    ///
+    /// This is `None` for TLS connections, as the runtime opens the TCP
+    /// connection as part of the handshake.
+    pub fn connect(&self) -> Option<Duration> {
+        self.connect
+    }
+
+    /// Get the time it took to open the TLS connection, including resolving
+    /// the host name and the TCP connection it runs over.
+    pub fn tls_handshake(&self) -> Option<Duration> {
+        self.tls_handshake
+    }
+
+    /// Get the time it took to write the request.
+    pub fn request_write(&self) -> Duration {
+        self.request_write
+    }
+
+    /// Get the time between writing the request and receiving the first
+    /// byte of the response.
+    pub fn first_byte(&self) -> Duration {
+        self.first_byte
+    }
+
+    /// Get the time between the first byte of the response and the end of
+    /// its body.
+    pub fn body(&self) -> Duration {
+        self.body
+    }
+
+    /// Get the time all phases took together.
+    pub fn total(&self) -> Duration {
+        self.resolve.unwrap_or_default()
+            + self.connect.unwrap_or_default()
+            + self.tls_handshake.unwrap_or_default()
+            + self.request_write
+            + self.first_byte
+            + self.body
+    }
+}
 
 /// A Response to a submitted `Request`.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SerializableResponse {
     /// body of response
     pub body: Vec<u8>,
+    /// body of response before it was decompressed, see
+    /// `HttpResponse::coded_body`
+    pub coded_body: Option<Vec<u8>>,
     /// The response's status as u16
     pub status: u16,
 
@@ -42,7 +188,15 @@ pub struct SerializableResponse {
     pub url: Url,
     /// list of urls hopped during redirects
     pub redirect_chain: Vec<Url>,
-    // pub info: HttpInfo,
+    /// the redirects followed, and how each changed the request
+    pub redirect_history: Vec<RedirectHop>,
+    /// header fields received in `103 Early Hints` responses
+    pub early_hints: HashMap<String, Vec<String>>,
+    pub cache_status: Option<CacheStatus>,
+    /// the connection the response was received on
+    pub info: Option<HttpInfo>,
+    /// how long each phase of the exchange took
+    pub timings: Timings,
 }
 
 impl TryFrom<SerializableResponse> for HttpResponse {
@@ -51,19 +205,51 @@ impl TryFrom<SerializableResponse> for HttpResponse {
     fn try_from(res: SerializableResponse) -> Result<Self, Self::Error> {
         Ok(HttpResponse {
             body: res.body,
+            coded_body: res.coded_body,
             status: StatusCode::from_u16(res.status).unwrap(),
             version: res.version,
             headers: header_map_from_hashmap(res.headers),
             url: res.url,
             redirect_chain: res.redirect_chain,
+            redirect_history: res.redirect_history,
+            early_hints: header_map_from_hashmap(res.early_hints),
+            cache_status: res.cache_status,
+            info: res.info,
+            attempts: 1,
+            timings: res.timings,
+            attempt_timings: vec![],
+            queue_wait: Duration::from_secs(0),
         })
     }
 }
 
+impl From<HttpResponse> for SerializableResponse {
+    fn from(res: HttpResponse) -> Self {
+        SerializableResponse {
+            body: res.body,
+            coded_body: res.coded_body,
+            status: res.status.as_u16(),
+            version: res.version,
+            headers: hashmap_from_header_map(res.headers),
+            url: res.url,
+            redirect_chain: res.redirect_chain,
+            redirect_history: res.redirect_history,
+            early_hints: hashmap_from_header_map(res.early_hints),
+            cache_status: res.cache_status,
+            info: res.info,
+            timings: res.timings,
+        }
+    }
+}
+
 /// Response of an http request
 pub struct HttpResponse {
     /// body of response
     pub body: Vec<u8>,
+
+    /// body of response as received, before it was decompressed, kept if
+    /// the response has a `Content-Digest` header, which covers it
+    pub coded_body: Option<Vec<u8>>,
     /// The response's status
     pub status: StatusCode,
 
@@ -78,10 +264,58 @@ pub struct HttpResponse {
 
     /// chain of urls if any redirection happened
     pub redirect_chain: Vec<Url>,
-    // pub info: HttpInfo,
+
+    /// the redirects followed, and how each changed the request
+    pub redirect_history: Vec<RedirectHop>,
+
+    /// header fields received in `103 Early Hints` responses
+    /// that preceded this response
+    pub early_hints: HeaderMap<HeaderValue>,
+
+    /// number of attempts made for this response, including retries
+    pub attempts: u32,
+
+    /// how long each phase of the exchange took
+    pub timings: Timings,
+
+    /// timings of the earlier attempts that got a response before being
+    /// retried, in order
+    pub attempt_timings: Vec<Timings>,
+
+    /// time spent waiting for a free connection slot
+    pub queue_wait: Duration,
+
+    /// whether the response came from the cache
+    pub cache_status: Option<CacheStatus>,
+
+    /// the connection the response was received on, `None` if it didn't
+    /// come from the network
+    pub info: Option<HttpInfo>,
 }
 
 impl HttpResponse {
+    /// Create a `Response` for `url` with the given status and body, for
+    /// example to answer a request from a [`Middleware`](crate::middleware::Middleware).
+    pub fn new(status: StatusCode, url: Url, body: Vec<u8>) -> HttpResponse {
+        HttpResponse {
+            body,
+            coded_body: None,
+            status,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            url,
+            redirect_chain: vec![],
+            redirect_history: vec![],
+            early_hints: HeaderMap::new(),
+            attempts: 1,
+            timings: Timings::default(),
+            attempt_timings: vec![],
+            queue_wait: Duration::from_secs(0),
+            cache_status: None,
+            info: None,
+        }
+    }
+
     /// Get the `StatusCode` of this `Response`.
     #[inline]
     pub fn status(&self) -> StatusCode {
@@ -106,6 +340,54 @@ impl HttpResponse {
         &mut self.headers
     }
 
+    /// Get the header fields sent in `103 Early Hints` interim responses
+    /// before this `Response`, most notably `Link` headers.
+    ///
+    /// The map is empty if the server sent no early hints.
+    #[inline]
+    pub fn early_hints(&self) -> &HeaderMap {
+        &self.early_hints
+    }
+
+    /// Get the number of attempts it took to get this `Response`.
+    ///
+    /// This is `1` unless the request was retried according to a
+    /// [`retry::Policy`](crate::retry::Policy).
+    #[inline]
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Get how long each phase of the exchange that produced this
+    /// `Response` took.
+    pub fn timings(&self) -> &Timings {
+        &self.timings
+    }
+
+    /// Get the timings of the earlier attempts that got a response before
+    /// being retried, in order. Attempts that failed without a response
+    /// have no timings.
+    pub fn attempt_timings(&self) -> &[Timings] {
+        &self.attempt_timings
+    }
+
+    /// Get how long the request waited in the queue of its host before it
+    /// was sent, when the connections of the `Client` are limited.
+    #[inline]
+    pub fn queue_wait(&self) -> Duration {
+        self.queue_wait
+    }
+
+    /// Get whether this `Response` was answered from the cache, revalidated
+    /// or fetched from the server.
+    ///
+    /// This is `None` if the `Client` has no cache, or the request wasn't
+    /// cacheable.
+    #[inline]
+    pub fn cache_status(&self) -> Option<CacheStatus> {
+        self.cache_status
+    }
+
     /// Get the content-length of this response, if known.
     ///
     /// Reasons it may not be known:
@@ -137,13 +419,21 @@ impl HttpResponse {
         &self.url
     }
 
+    /// Get the redirects that were followed to get this `Response`, in
+    /// order.
+    pub fn redirect_history(&self) -> &[RedirectHop] {
+        &self.redirect_history
+    }
+
     /// Get the remote address used to get this `Response`.
     pub fn remote_addr(&self) -> Option<SocketAddr> {
-        None
-        // self.res
-        //     .extensions()
-        //     .get::<HttpInfo>()
-        //     .map(|info| info.remote_addr())
+        self.info.as_ref().and_then(HttpInfo::remote_addr)
+    }
+
+    /// Get information about the connection this `Response` was received
+    /// on, or `None` if it was served without one, e.g. from the cache.
+    pub fn info(&self) -> Option<&HttpInfo> {
+        self.info.as_ref()
     }
 
     // /// Returns a reference to the associated extensions.
@@ -387,6 +677,39 @@ impl HttpResponse {
         }
     }
 
+    /// Verify the HTTP Message Signature of this response, see RFC 9421.
+    ///
+    /// Returns an error for which `is_signature()` is true if the response
+    /// has no signature matching the verifier, or the signature is invalid.
+    /// A `Content-Digest` is checked against the body as it was received,
+    /// before it was decompressed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn run() -> Result<(), nightfly::Error> {
+    /// use nightfly::auth::{SignatureKey, SignatureVerifier};
+    ///
+    /// let verifier = SignatureVerifier::new(SignatureKey::hmac_sha256("partner", "secret"))
+    ///     .require_component("@status")
+    ///     .require_component("content-digest");
+    /// let res = nightfly::get("http://localhost:8080/report")?;
+    /// res.verify_signature(&verifier)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn verify_signature(&self, verifier: &SignatureVerifier) -> crate::Result<()> {
+        verifier
+            .verify(
+                self.status,
+                &self.url,
+                &self.headers,
+                self.coded_body.as_deref().unwrap_or(&self.body),
+                SystemTime::now(),
+            )
+            .map_err(|e| crate::error::signature(e, self.url.clone()))
+    }
+
     // private
 
     // The Response's body is an implementation detail.
@@ -0,0 +1,213 @@
+//! Client Metrics
+//!
+//! A `Client` keeps counters about the requests it sends in its process, so
+//! that they cover every process sharing the same `Client`.
+//! `Client::metrics()` returns a snapshot of them as a `Metrics` value,
+//! which can be serialized and forwarded to any exporter, and
+//! `Client::reset_metrics()` starts counting from zero again.
+//!
+//! # Example
+//!
+//! ```rust
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let client = nightfly::Client::new();
+//! client.get("http://httpbin.org/get").send()?;
+//!
+//! let metrics = client.metrics();
+//! println!("2xx responses: {}", metrics.responses_with_class("2xx"));
+//! println!("median latency: {:?}", metrics.latency.quantile(0.5));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use http::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+
+/// Upper bounds of the latency histogram buckets, in milliseconds.
+const BUCKETS_MS: [u64; 14] = [
+    1, 2, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10_000, 30_000,
+];
+
+/// A snapshot of the counters of a `Client`.
+///
+/// Returned by `Client::metrics()`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metrics {
+    /// Number of responses received by method and status class, e.g.
+    /// `requests["GET"]["2xx"]`. Every redirect hop and retry attempt counts
+    /// on its own.
+    pub requests: BTreeMap<String, BTreeMap<String, u64>>,
+    /// Number of bytes written for requests, including their heads.
+    pub bytes_sent: u64,
+    /// Number of bytes read for responses, including their heads and any
+    /// chunked framing, before decompression.
+    pub bytes_received: u64,
+    /// Number of bytes of response bodies after decompression.
+    pub bytes_received_decoded: u64,
+    /// Number of failed requests by kind of error, e.g. `"connect"` or
+    /// `"timeout"`.
+    pub errors: BTreeMap<String, u64>,
+    /// Number of requests sent over a pooled connection.
+    pub pool_hits: u64,
+    /// Number of requests that had to open a new connection.
+    pub pool_misses: u64,
+    /// Number of pooled connections kept open per host.
+    pub open_connections: BTreeMap<String, usize>,
+    /// Time between a request reaching the client and its final response,
+    /// including redirects.
+    pub latency: Histogram,
+    /// Time between writing a request and the first byte of its response.
+    pub time_to_first_byte: Histogram,
+}
+
+/// A histogram of durations with fixed buckets.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    /// Upper bounds of the buckets.
+    pub bounds: Vec<Duration>,
+    /// Number of durations in each bucket, which holds the durations up to
+    /// its bound and above the previous one. The last count is for the
+    /// durations above the last bound.
+    pub counts: Vec<u64>,
+    /// Number of recorded durations.
+    pub count: u64,
+    /// Sum of the recorded durations.
+    pub sum: Duration,
+}
+
+impl Metrics {
+    /// Get the number of responses with the status class `class`, e.g.
+    /// `"5xx"`, for all methods.
+    pub fn responses_with_class(&self, class: &str) -> u64 {
+        self.requests
+            .values()
+            .filter_map(|classes| classes.get(class))
+            .sum()
+    }
+
+    /// Get the number of errors of all kinds.
+    pub fn total_errors(&self) -> u64 {
+        self.errors.values().sum()
+    }
+
+    pub(crate) fn record_response(&mut self, method: &Method, status: StatusCode) {
+        let class = format!("{}xx", status.as_u16() / 100);
+        *self
+            .requests
+            .entry(method.to_string())
+            .or_default()
+            .entry(class)
+            .or_default() += 1;
+    }
+
+    pub(crate) fn record_error(&mut self, kind: &str) {
+        *self.errors.entry(kind.to_string()).or_default() += 1;
+    }
+
+    pub(crate) fn record_sent(&mut self, bytes: usize) {
+        self.bytes_sent += bytes as u64;
+    }
+
+    pub(crate) fn record_received(&mut self, raw: usize, decoded: usize) {
+        self.bytes_received += raw as u64;
+        self.bytes_received_decoded += decoded as u64;
+    }
+
+    pub(crate) fn record_connection(&mut self, reused: bool) {
+        if reused {
+            self.pool_hits += 1;
+        } else {
+            self.pool_misses += 1;
+        }
+    }
+}
+
+impl Histogram {
+    pub(crate) fn record(&mut self, duration: Duration) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| duration <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum += duration;
+    }
+
+    /// Get the mean of the recorded durations, or `None` if there are none.
+    pub fn mean(&self) -> Option<Duration> {
+        match self.count {
+            0 => None,
+            count => Some(self.sum / count as u32),
+        }
+    }
+
+    /// Get the upper bound of the bucket that holds the quantile `q` of the
+    /// recorded durations, e.g. `0.99` for the 99th percentile.
+    ///
+    /// Returns `None` if there are no durations, or if the quantile is
+    /// above the last bound.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            seen += count;
+            if seen >= rank {
+                return Some(*bound);
+            }
+        }
+        None
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            bounds: BUCKETS_MS
+                .iter()
+                .map(|ms| Duration::from_millis(*ms))
+                .collect(),
+            counts: vec![0; BUCKETS_MS.len() + 1],
+            count: 0,
+            sum: Duration::from_secs(0),
+        }
+    }
+}
+
+#[lunatic::test]
+fn test_metrics_counters() {
+    let mut metrics = Metrics::default();
+    metrics.record_response(&Method::GET, StatusCode::OK);
+    metrics.record_response(&Method::GET, StatusCode::NO_CONTENT);
+    metrics.record_response(&Method::POST, StatusCode::SERVICE_UNAVAILABLE);
+    metrics.record_error("connect");
+
+    assert_eq!(metrics.requests["GET"]["2xx"], 2);
+    assert_eq!(metrics.requests["POST"]["5xx"], 1);
+    assert_eq!(metrics.responses_with_class("2xx"), 2);
+    assert_eq!(metrics.total_errors(), 1);
+}
+
+#[lunatic::test]
+fn test_metrics_histogram() {
+    let mut histogram = Histogram::default();
+    assert_eq!(histogram.quantile(0.5), None);
+
+    for ms in [3, 4, 20, 40_000] {
+        histogram.record(Duration::from_millis(ms));
+    }
+    assert_eq!(histogram.counts[2], 2);
+    assert_eq!(histogram.counts[BUCKETS_MS.len()], 1);
+    assert_eq!(histogram.count, 4);
+    assert_eq!(histogram.quantile(0.5), Some(Duration::from_millis(5)));
+    assert_eq!(histogram.quantile(0.75), Some(Duration::from_millis(25)));
+    // the slowest request is above the last bound
+    assert_eq!(histogram.quantile(1.0), None);
+    assert_eq!(histogram.mean(), Some(Duration::from_micros(10_006_750)));
+}
@@ -0,0 +1,330 @@
+//! Middleware
+//!
+//! Middleware runs in the client process around every request a `Client`
+//! sends. It can inspect or change the `Request` before it is sent, answer
+//! it right away with a response of its own, or change the response.
+//!
+//! Middleware is registered as a value of a serializable type. It is
+//! serialized when it's added to the builder, and `ClientBuilder::build()`
+//! restores it in a process of its own, which the client process calls for
+//! every request. The middleware keeps its state there for the lifetime of
+//! the `Client`. A deserialized `ClientBuilder` fails to build until its
+//! middleware is added again, as only the builder it was added to knows how
+//! to restore it.
+//!
+//! # Example
+//!
+//! ```rust
+//! use nightfly::middleware::Middleware;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct CountRequests {
+//!     sent: usize,
+//! }
+//!
+//! impl Middleware for CountRequests {
+//!     fn handle_request(
+//!         &mut self,
+//!         req: &mut nightfly::Request,
+//!     ) -> Option<nightfly::Result<nightfly::HttpResponse>> {
+//!         self.sent += 1;
+//!         req.headers_mut().insert("x-request-number", self.sent.into());
+//!         None
+//!     }
+//! }
+//!
+//! # fn run() -> Result<(), nightfly::Error> {
+//! let client = nightfly::Client::builder()
+//!     .middleware(CountRequests { sent: 0 })
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use lunatic::{Mailbox, Process, Tag};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::cache;
+use crate::lunatic_impl::request::{header_map_from_pairs, pairs_from_header_map, InnerRequest};
+use crate::{HttpResponse, Request, SerializableResponse};
+
+/// A hook around the requests of a `Client`.
+///
+/// Middleware runs in the order it was added to the `ClientBuilder` before a
+/// request is sent, and in the reverse order once the response arrived.
+pub trait Middleware: 'static {
+    /// Called before the request is sent.
+    ///
+    /// The request can be changed in place. Returning `Some` skips sending
+    /// the request, as well as the middleware added after this one, and
+    /// uses the returned result as the response.
+    fn handle_request(&mut self, req: &mut Request) -> Option<crate::Result<HttpResponse>> {
+        let _ = req;
+        None
+    }
+
+    /// Called with the result of the request, after redirects were followed.
+    fn handle_response(
+        &mut self,
+        req: &Request,
+        res: crate::Result<HttpResponse>,
+    ) -> crate::Result<HttpResponse> {
+        let _ = req;
+        res
+    }
+}
+
+type Start = fn(&[u8]) -> crate::Result<Handle>;
+
+/// A middleware serialized to be added to a client.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Registered {
+    // not serialized, so only the builder the middleware was added to can
+    // start it
+    #[serde(skip)]
+    start: Option<Start>,
+    state: Vec<u8>,
+    type_name: String,
+}
+
+impl Registered {
+    pub(crate) fn new<M>(middleware: &M) -> crate::Result<Self>
+    where
+        M: Middleware + Serialize + DeserializeOwned,
+    {
+        Ok(Registered {
+            start: Some(start::<M>),
+            state: serde_json::to_vec(middleware).map_err(crate::error::builder)?,
+            type_name: std::any::type_name::<M>().to_string(),
+        })
+    }
+
+    /// Restores the middleware in a process of its own, or returns an error
+    /// if the middleware was deserialized.
+    fn start(&self) -> crate::Result<Handle> {
+        match self.start {
+            Some(start) => start(&self.state),
+            None => Err(crate::error::builder(format!(
+                "middleware `{}` was deserialized and has to be added again",
+                self.type_name
+            ))),
+        }
+    }
+}
+
+impl fmt::Debug for Registered {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.type_name)
+    }
+}
+
+fn start<M>(state: &[u8]) -> crate::Result<Handle>
+where
+    M: Middleware + DeserializeOwned,
+{
+    // restored here once already, so that a state that doesn't deserialize
+    // fails the build instead of the process
+    serde_json::from_slice::<M>(state).map_err(crate::error::builder)?;
+    Ok(Handle {
+        process: Process::spawn_link(state.to_vec(), serve::<M>),
+        type_name: std::any::type_name::<M>().to_string(),
+    })
+}
+
+/// Runs the middleware restored from `state` until it's told to stop.
+fn serve<M>(state: Vec<u8>, mailbox: Mailbox<Call>)
+where
+    M: Middleware + DeserializeOwned,
+{
+    let mut middleware: M = serde_json::from_slice(&state).expect("checked by `start`");
+    loop {
+        match mailbox.receive() {
+            Call::Request(req, caller, tag) => {
+                let mut req = req.into_request();
+                let res = middleware.handle_request(&mut req).map(sent_result);
+                caller.tag_send(tag, (SentRequest::new(&req), res));
+            }
+            Call::Response(req, res, caller, tag) => {
+                let res = middleware.handle_response(&req.into_request(), received(res));
+                caller.tag_send(tag, sent_result(res));
+            }
+            Call::Stop => break,
+        }
+    }
+}
+
+type SentResult = Result<SerializableResponse, crate::Error>;
+
+fn sent_result(res: crate::Result<HttpResponse>) -> SentResult {
+    res.map(SerializableResponse::from)
+}
+
+fn received(res: SentResult) -> crate::Result<HttpResponse> {
+    res.and_then(HttpResponse::try_from)
+}
+
+/// A `Request` as it's sent between the client process and the process of
+/// a middleware.
+#[derive(Serialize, Deserialize)]
+struct SentRequest {
+    inner: InnerRequest,
+    client_headers: Vec<(String, Vec<u8>)>,
+    cache_pending: Option<cache::Pending>,
+}
+
+impl SentRequest {
+    fn new(req: &Request) -> crate::Result<Self> {
+        Ok(SentRequest {
+            inner: InnerRequest::try_from(req.clone())?,
+            client_headers: pairs_from_header_map(&req.client_headers),
+            cache_pending: req.cache_pending.clone(),
+        })
+    }
+
+    fn into_request(self) -> Request {
+        let mut req = Request::from(self.inner);
+        req.client_headers = header_map_from_pairs(self.client_headers);
+        req.cache_pending = self.cache_pending;
+        req
+    }
+}
+
+/// A message to the process of a middleware. The answer is sent back to the
+/// given process with the given tag.
+#[derive(Serialize, Deserialize)]
+enum Call {
+    Request(
+        SentRequest,
+        Process<(crate::Result<SentRequest>, Option<SentResult>)>,
+        Tag,
+    ),
+    Response(SentRequest, SentResult, Process<SentResult>, Tag),
+    Stop,
+}
+
+/// A middleware running in a process of its own.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Handle {
+    process: Process<Call>,
+    type_name: String,
+}
+
+impl Handle {
+    fn handle_request(&self, req: &mut Request) -> Option<crate::Result<HttpResponse>> {
+        let sent = match SentRequest::new(req) {
+            Ok(sent) => sent,
+            Err(e) => return Some(Err(e)),
+        };
+        let (changed, res) = self.call(|caller, tag| Call::Request(sent, caller, tag));
+        match changed {
+            Ok(changed) => *req = changed.into_request(),
+            Err(e) => return Some(Err(e)),
+        }
+        res.map(received)
+    }
+
+    fn handle_response(
+        &self,
+        req: &Request,
+        res: crate::Result<HttpResponse>,
+    ) -> crate::Result<HttpResponse> {
+        let sent = SentRequest::new(req)?;
+        received(self.call(|caller, tag| Call::Response(sent, sent_result(res), caller, tag)))
+    }
+
+    fn call<T, F>(&self, call: F) -> T
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(Process<T>, Tag) -> Call,
+    {
+        // SAFETY: only the answer is received, with a tag unique to this call
+        let mailbox: Mailbox<T> = unsafe { Mailbox::new() };
+        let tag = Tag::new();
+        self.process.send(call(mailbox.this(), tag));
+        mailbox.tag_receive(&[tag])
+    }
+}
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.type_name)
+    }
+}
+
+/// The middleware of a client, each in the process `ClientBuilder::build()`
+/// started for it.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Stack {
+    middleware: Vec<Handle>,
+}
+
+impl Stack {
+    /// Starts a process for each of the `registered` middleware.
+    pub(crate) fn start(registered: &[Registered]) -> crate::Result<Self> {
+        let mut stack = Stack::default();
+        for registered in registered {
+            match registered.start() {
+                Ok(handle) => stack.middleware.push(handle),
+                Err(e) => {
+                    stack.stop();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(stack)
+    }
+
+    /// Links the calling process to the processes of the middleware, so
+    /// that a panicking middleware takes down the client that called it.
+    pub(crate) fn link(&self) {
+        for middleware in &self.middleware {
+            middleware.process.link();
+        }
+    }
+
+    /// Stops the processes of the middleware.
+    pub(crate) fn stop(&self) {
+        for middleware in &self.middleware {
+            middleware.process.send(Call::Stop);
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.middleware.is_empty()
+    }
+
+    /// Runs `req` through the middleware, calling `send` if none of them
+    /// answered it.
+    pub(crate) fn run<F>(&self, mut req: Request, send: F) -> crate::Result<HttpResponse>
+    where
+        F: FnOnce(Request) -> crate::Result<HttpResponse>,
+    {
+        let mut entered = 0;
+        let mut res = None;
+        for middleware in &self.middleware {
+            entered += 1;
+            if let Some(short_circuit) = middleware.handle_request(&mut req) {
+                res = Some(short_circuit);
+                break;
+            }
+        }
+        let mut res = match res {
+            Some(res) => res,
+            None => send(req.clone()),
+        };
+        for middleware in self.middleware[..entered].iter().rev() {
+            res = middleware.handle_response(&req, res);
+        }
+        res
+    }
+}
+
+impl fmt::Debug for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(&self.middleware).finish()
+    }
+}
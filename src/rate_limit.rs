@@ -0,0 +1,260 @@
+//! Rate Limiting
+//!
+//! A `Client` can limit how many requests it sends to each host, and for
+//! each key chosen by the caller with `RequestBuilder::rate_limit_key`, using
+//! token buckets. The buckets live in the client process, so the limits hold
+//! for every process that shares the same `Client`.
+//!
+//! When no token is available, a request either waits in line for one, up
+//! to a maximum wait, or fails right away with an error for which
+//! [`Error::is_rate_limited`](crate::Error::is_rate_limited) returns true.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::lunatic_impl::client::HostRef;
+
+/// A number of requests allowed per period of time.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Rate {
+    requests: u32,
+    per: Duration,
+    burst: u32,
+}
+
+impl Rate {
+    /// Allow `requests` requests every `per`, all of which may be sent at
+    /// once.
+    pub fn new(requests: u32, per: Duration) -> Self {
+        let requests = requests.max(1);
+        Rate {
+            requests,
+            per,
+            burst: requests,
+        }
+    }
+
+    /// Set how many requests may be sent at once after a quiet period.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    fn tokens_per_sec(&self) -> f64 {
+        f64::from(self.requests) / self.per.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// What to do with a request when no token is available.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mode {
+    /// Fail the request right away.
+    Reject,
+    /// Wait in line for a token, but fail if that would take longer than the
+    /// given duration.
+    Queue(Duration),
+}
+
+/// A type that controls how fast a `Client` sends requests.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # fn run() -> Result<(), nightfly::Error> {
+/// use nightfly::rate_limit::{Policy, Rate};
+///
+/// let policy = Policy::default()
+///     .per_host(Rate::new(10, Duration::from_secs(1)))
+///     .per_key(Rate::new(100, Duration::from_secs(60)));
+/// let client = nightfly::Client::builder().rate_limit(policy).build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Policy {
+    per_host: Option<Rate>,
+    per_key: Option<Rate>,
+    mode: Mode,
+}
+
+impl Policy {
+    /// Limit the requests sent to each host.
+    pub fn per_host(mut self, rate: Rate) -> Self {
+        self.per_host = Some(rate);
+        self
+    }
+
+    /// Limit the requests sent with each key set by
+    /// `RequestBuilder::rate_limit_key`.
+    pub fn per_key(mut self, rate: Rate) -> Self {
+        self.per_key = Some(rate);
+        self
+    }
+
+    /// Set what to do with a request when no token is available. It can be
+    /// overridden for a single request with `RequestBuilder::rate_limit_mode`.
+    ///
+    /// Default is to wait up to 30 seconds.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            per_host: None,
+            per_key: None,
+            mode: Mode::Queue(Duration::from_secs(30)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+enum BucketKey {
+    Host(HostRef),
+    Key(String),
+}
+
+/// The token buckets of a client.
+#[derive(Clone, Debug)]
+pub(crate) struct Limiter {
+    policy: Policy,
+    buckets: HashMap<BucketKey, Bucket>,
+}
+
+#[derive(Clone, Debug)]
+struct Bucket {
+    rate: Rate,
+    // goes below zero while requests are waiting in line
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Limiter {
+    pub(crate) fn new(policy: Policy) -> Self {
+        Limiter {
+            policy,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Takes a token from every bucket the request falls into and returns
+    /// how long the request has to wait before it may be sent.
+    ///
+    /// Nothing is taken if the request is rejected.
+    pub(crate) fn acquire(
+        &mut self,
+        host: HostRef,
+        key: Option<String>,
+        mode: Option<Mode>,
+    ) -> Result<Duration, Duration> {
+        let now = Instant::now();
+        let mut keys = Vec::new();
+        if let Some(rate) = self.policy.per_host {
+            keys.push((BucketKey::Host(host), rate));
+        }
+        if let (Some(rate), Some(key)) = (self.policy.per_key, key) {
+            keys.push((BucketKey::Key(key), rate));
+        }
+
+        let mut wait = Duration::from_secs(0);
+        for (key, rate) in &keys {
+            let bucket = self
+                .buckets
+                .entry(key.clone())
+                .or_insert_with(|| Bucket::new(*rate, now));
+            wait = wait.max(bucket.wait(now));
+        }
+        let allowed = match mode.unwrap_or(self.policy.mode) {
+            Mode::Reject => wait == Duration::from_secs(0),
+            Mode::Queue(max_wait) => wait <= max_wait,
+        };
+        if !allowed {
+            return Err(wait);
+        }
+        for (key, _) in &keys {
+            if let Some(bucket) = self.buckets.get_mut(key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+        Ok(wait)
+    }
+}
+
+impl Bucket {
+    fn new(rate: Rate, now: Instant) -> Self {
+        Bucket {
+            rate,
+            tokens: f64::from(rate.burst),
+            updated: now,
+        }
+    }
+
+    /// Refills the bucket and returns how long it takes until a token is
+    /// available for the next request in line.
+    fn wait(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.rate.tokens_per_sec()).min(f64::from(self.rate.burst));
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.rate.tokens_per_sec())
+        }
+    }
+}
+
+#[lunatic::test]
+fn test_rate_limit_reject() {
+    let host = HostRef::Http("localhost:80".to_string());
+    let mut limiter = Limiter::new(
+        Policy::default()
+            .per_host(Rate::new(2, Duration::from_secs(60)))
+            .mode(Mode::Reject),
+    );
+
+    assert_eq!(
+        limiter.acquire(host.clone(), None, None),
+        Ok(Duration::from_secs(0))
+    );
+    assert_eq!(
+        limiter.acquire(host.clone(), None, None),
+        Ok(Duration::from_secs(0))
+    );
+    assert!(limiter.acquire(host.clone(), None, None).is_err());
+
+    // other hosts have their own bucket
+    let other = HostRef::Http("example.com:80".to_string());
+    assert!(limiter.acquire(other, None, None).is_ok());
+}
+
+#[lunatic::test]
+fn test_rate_limit_queue() {
+    let host = HostRef::Http("localhost:80".to_string());
+    let mut limiter = Limiter::new(
+        Policy::default()
+            .per_key(Rate::new(1, Duration::from_secs(1)))
+            .mode(Mode::Queue(Duration::from_millis(1500))),
+    );
+    let key = || Some("user".to_string());
+
+    assert_eq!(
+        limiter.acquire(host.clone(), key(), None),
+        Ok(Duration::from_secs(0))
+    );
+    let wait = limiter.acquire(host.clone(), key(), None).unwrap();
+    assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+    // the third request would have to wait about two seconds
+    assert!(limiter.acquire(host.clone(), key(), None).is_err());
+    // requests without a key are not limited
+    assert_eq!(
+        limiter.acquire(host, None, None),
+        Ok(Duration::from_secs(0))
+    );
+}
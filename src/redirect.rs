@@ -3,15 +3,55 @@
 //! By default, a `Client` will automatically handle HTTP redirects, having a
 //! maximum redirect chain of 10 hops. To customize this behavior, a
 //! `redirect::Policy` can be used with a `ClientBuilder`.
-
+//!
+//! Policies are serialized to be sent to the client process, so they can't
+//! hold closures. Instead, the built-in rules can be combined, and custom
+//! rules are values of a serializable type implementing `Rule`, registered
+//! by name with `ClientBuilder::register_redirect_policy()`. Like
+//! middleware, each of them is restored in a process of its own when the
+//! client is built:
+//!
+//! ```rust
+//! # use nightfly::{Error, redirect};
+//! # use serde::{Deserialize, Serialize};
+//! #
+//! # fn run() -> Result<(), Error> {
+//! #[derive(Serialize, Deserialize)]
+//! struct NoTracking;
+//!
+//! impl redirect::Rule for NoTracking {
+//!     fn check(&mut self, attempt: redirect::Attempt) -> redirect::Action {
+//!         if attempt.url().query_pairs().any(|(name, _)| name == "utm_source") {
+//!             attempt.stop()
+//!         } else {
+//!             attempt.follow()
+//!         }
+//!     }
+//! }
+//!
+//! let policy = redirect::Policy::limited(5)
+//!     .same_registrable_domain()
+//!     .no_downgrade()
+//!     .named("no-tracking");
+//! let client = nightfly::Client::builder()
+//!     .register_redirect_policy("no-tracking", NoTracking)
+//!     .redirect(policy)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
 use std::error::Error as StdError;
 use std::fmt;
+use std::time::Duration;
 
 use crate::header::{HeaderMap, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, WWW_AUTHENTICATE};
 use http::StatusCode;
-use serde::{Deserialize, Serialize};
+use lunatic::{Mailbox, Process, Tag};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::Url;
+use crate::{Timings, Url};
 
 /// A type that controls the policy on how to handle the following of redirects.
 ///
@@ -21,12 +61,29 @@ use crate::Url;
 /// - `limited` can be used have the same as the default behavior, but adjust
 ///   the allowed maximum redirect hops in a chain.
 /// - `none` can be used to disable all redirect behavior.
-/// - `custom` can be used to create a customized policy.
+///
+/// Further rules can be added to a policy, and a redirect is only followed
+/// if all of them allow it:
+///
+/// - `same_origin` and `same_registrable_domain` keep the chain on the
+///   origin or the registrable domain of the first request.
+/// - `allow_hosts` and `deny_hosts` restrict the hosts redirected to.
+/// - `no_downgrade` rejects redirects from `https` to `http`.
+/// - `named` asks a `Rule` registered with
+///   `ClientBuilder::register_redirect_policy()`.
+/// - `and` adds the rules of another policy.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Policy {
     inner: PolicyKind,
 }
 
+/// A custom rule deciding about redirects, see
+/// `ClientBuilder::register_redirect_policy()`.
+pub trait Rule: 'static {
+    /// Decides whether the redirect `attempt` is followed.
+    fn check(&mut self, attempt: Attempt) -> Action;
+}
+
 /// A type that holds information on the next request and previous requests
 /// in redirect chain.
 #[derive(Debug)]
@@ -36,6 +93,38 @@ pub struct Attempt<'a> {
     previous: &'a [Url],
 }
 
+/// A redirect that was followed to get a response, see
+/// `HttpResponse::redirect_history()`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RedirectHop {
+    url: Url,
+    status: u16,
+    // kept as raw pairs, like the headers of an `InnerRequest`
+    headers: Vec<(String, Vec<u8>)>,
+    elapsed: Duration,
+    timings: Timings,
+    changes: Vec<RedirectChange>,
+}
+
+/// A change made to the request when following a redirect, as described in
+/// RFC 9110, section 15.4.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RedirectChange {
+    /// The method was changed, such as a `POST` to a `GET` after a
+    /// `303 See Other`.
+    MethodRewritten {
+        /// the method of the redirected request
+        from: String,
+        /// the method of the next request
+        to: String,
+    },
+    /// The body was dropped along with the method.
+    BodyDropped,
+    /// A header was removed, because it described the dropped body or
+    /// because it's sensitive and the redirect leaves the host.
+    HeaderRemoved(String),
+}
+
 /// An action to perform when a redirect status code is found.
 #[derive(Debug)]
 pub struct Action {
@@ -59,94 +148,128 @@ impl Policy {
         }
     }
 
-    // /// Create a custom `Policy` using the passed function.
-    // ///
-    // /// # Note
-    // ///
-    // /// The default `Policy` handles a maximum loop
-    // /// chain, but the custom variant does not do that for you automatically.
-    // /// The custom policy should have some way of handling those.
-    // ///
-    // /// Information on the next request and previous requests can be found
-    // /// on the [`Attempt`] argument passed to the closure.
-    // ///
-    // /// Actions can be conveniently created from methods on the
-    // /// [`Attempt`].
-    // ///
-    // /// # Example
-    // ///
-    // /// ```rust
-    // /// # use nightfly::{Error, redirect};
-    // /// #
-    // /// # fn run() -> Result<(), Error> {
-    // /// let custom = redirect::Policy::custom(|attempt| {
-    // ///     if attempt.previous().len() > 5 {
-    // ///         attempt.error("too many redirects")
-    // ///     } else if attempt.url().host_str() == Some("example.domain") {
-    // ///         // prevent redirects to 'example.domain'
-    // ///         attempt.stop()
-    // ///     } else {
-    // ///         attempt.follow()
-    // ///     }
-    // /// });
-    // /// let client = nightfly::Client::builder()
-    // ///     .redirect(custom)
-    // ///     .build()?;
-    // /// # Ok(())
-    // /// # }
-    // /// ```
-    // ///
-    // /// [`Attempt`]: struct.Attempt.html
-    // pub fn custom<T>(policy: T) -> Self
-    // where
-    //     T: Fn(Attempt) -> Action + Send + Sync + 'static,
-    // {
-    //     Self {
-    //         inner: PolicyKind::Custom(Box::new(policy)),
-    //     }
-    // }
+    /// Only follow redirects to the origin of the first request, that is
+    /// the same scheme, host and port.
+    pub fn same_origin(self) -> Self {
+        self.with(PolicyKind::SameOrigin)
+    }
+
+    /// Only follow redirects to the registrable domain of the first request,
+    /// such as `example.co.uk` for `www.example.co.uk`, according to the
+    /// public suffix list.
+    ///
+    /// Hosts without a registrable domain, such as IP addresses, must match
+    /// exactly.
+    pub fn same_registrable_domain(self) -> Self {
+        self.with(PolicyKind::SameRegistrableDomain)
+    }
+
+    /// Only follow redirects to these hosts.
+    ///
+    /// A host starting with `*.`, such as `*.example.com`, matches all of
+    /// its subdomains.
+    pub fn allow_hosts<I, H>(self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = H>,
+        H: Into<String>,
+    {
+        self.with(PolicyKind::AllowHosts(lowercase(hosts)))
+    }
+
+    /// Don't follow redirects to these hosts.
+    ///
+    /// A host starting with `*.`, such as `*.example.com`, matches all of
+    /// its subdomains.
+    pub fn deny_hosts<I, H>(self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = H>,
+        H: Into<String>,
+    {
+        self.with(PolicyKind::DenyHosts(lowercase(hosts)))
+    }
+
+    /// Don't follow redirects from `https` to `http`.
+    pub fn no_downgrade(self) -> Self {
+        self.with(PolicyKind::NoDowngrade)
+    }
+
+    /// Ask the `Rule` registered as `name` with
+    /// `ClientBuilder::register_redirect_policy()`.
+    ///
+    /// Building the client fails if no rule is registered as `name`.
+    pub fn named<N: Into<String>>(self, name: N) -> Self {
+        self.with(PolicyKind::Named(name.into()))
+    }
+
+    /// Add the rules of `other` to this policy.
+    pub fn and(self, other: Policy) -> Self {
+        match other.inner {
+            PolicyKind::All(rules) => rules.into_iter().fold(self, Policy::with),
+            rule => self.with(rule),
+        }
+    }
+
+    fn with(self, rule: PolicyKind) -> Self {
+        let rules = match self.inner {
+            PolicyKind::All(mut rules) => {
+                rules.push(rule);
+                rules
+            }
+            first => vec![first, rule],
+        };
+        Self {
+            inner: PolicyKind::All(rules),
+        }
+    }
 
     /// Apply this policy to a given [`Attempt`] to produce a [`Action`].
     ///
     /// # Note
     ///
-    /// This method can be used together with `Policy::custom()`
-    /// to construct one `Policy` that wraps another.
+    /// This method can be used in a `Rule` registered with
+    /// `ClientBuilder::register_redirect_policy()` to wrap another policy.
+    /// The registered rules are only known to the client, so `named` rules
+    /// return an error here.
     ///
     /// # Example
     ///
     /// ```rust
-    /// # use nightfly::{Error, redirect};
+    /// # use nightfly::redirect;
+    /// # use serde::{Deserialize, Serialize};
     /// #
-    /// # fn run() -> Result<(), Error> {
-    /// let custom = redirect::Policy::custom(|attempt| {
-    ///     eprintln!("{}, Location: {:?}", attempt.status(), attempt.url());
-    ///     redirect::Policy::default().redirect(attempt)
-    /// });
-    /// # Ok(())
-    /// # }
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Logged(redirect::Policy);
+    ///
+    /// impl redirect::Rule for Logged {
+    ///     fn check(&mut self, attempt: redirect::Attempt) -> redirect::Action {
+    ///         eprintln!("{}, Location: {:?}", attempt.status(), attempt.url());
+    ///         self.0.redirect(attempt)
+    ///     }
+    /// }
     /// ```
     pub fn redirect(&self, attempt: Attempt) -> Action {
-        match self.inner {
-            // PolicyKind::Custom(ref custom) => custom(attempt),
-            PolicyKind::Limit(max) => {
-                if attempt.previous.len() >= max {
-                    attempt.error(TooManyRedirects)
-                } else {
-                    attempt.follow()
-                }
-            }
-            PolicyKind::None => attempt.stop(),
-        }
+        let inner = self.inner.check(&attempt, &Rules::default());
+        Action { inner }
+    }
+
+    /// Checks that the rules of all `named` rules are in `registered`.
+    pub(crate) fn check_registered<T>(&self, registered: &HashMap<String, T>) -> crate::Result<()> {
+        self.inner.check_registered(registered)
     }
 
-    pub(crate) fn check(&self, status: StatusCode, next: &Url, previous: &[Url]) -> ActionKind {
-        self.redirect(Attempt {
+    pub(crate) fn check(
+        &self,
+        status: StatusCode,
+        next: &Url,
+        previous: &[Url],
+        rules: &Rules,
+    ) -> ActionKind {
+        let attempt = Attempt {
             status,
             next,
             previous,
-        })
-        .inner
+        };
+        self.inner.check(&attempt, rules)
     }
 
     pub(crate) fn is_default(&self) -> bool {
@@ -161,6 +284,59 @@ impl Default for Policy {
     }
 }
 
+impl RedirectHop {
+    pub(crate) fn new(
+        url: Url,
+        status: StatusCode,
+        headers: Vec<(String, Vec<u8>)>,
+        elapsed: Duration,
+        timings: Timings,
+        changes: Vec<RedirectChange>,
+    ) -> Self {
+        RedirectHop {
+            url,
+            status: status.as_u16(),
+            headers,
+            elapsed,
+            timings,
+            changes,
+        }
+    }
+
+    /// Get the URL that answered with the redirect.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Get the status of the redirect.
+    pub fn status(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::FOUND)
+    }
+
+    /// Get the headers of the redirect response selected with
+    /// `ClientBuilder::redirect_history_headers()`, by default `Location`
+    /// and `Set-Cookie`.
+    pub fn headers(&self) -> HeaderMap {
+        crate::lunatic_impl::request::header_map_from_pairs(self.headers.clone())
+    }
+
+    /// Get the time between sending the request and receiving the redirect
+    /// response.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Get how long each phase of the exchange that got the redirect took.
+    pub fn timings(&self) -> &Timings {
+        &self.timings
+    }
+
+    /// Get the changes made to the request to follow the redirect.
+    pub fn changes(&self) -> &[RedirectChange] {
+        &self.changes
+    }
+}
+
 impl<'a> Attempt<'a> {
     /// Get the type of redirect.
     pub fn status(&self) -> StatusCode {
@@ -204,9 +380,117 @@ impl<'a> Attempt<'a> {
 
 #[derive(Clone, Serialize, Deserialize)]
 enum PolicyKind {
-    // Custom(Box<dyn Fn(Attempt) -> Action + Send + Sync + 'static>),
     Limit(usize),
     None,
+    SameOrigin,
+    SameRegistrableDomain,
+    AllowHosts(Vec<String>),
+    DenyHosts(Vec<String>),
+    NoDowngrade,
+    // looked up in the rules registered with the client
+    Named(String),
+    All(Vec<PolicyKind>),
+}
+
+impl PolicyKind {
+    fn check(&self, attempt: &Attempt, rules: &Rules) -> ActionKind {
+        let first = attempt.previous.first();
+        let last = attempt.previous.last();
+        let rejected = match *self {
+            PolicyKind::Limit(max) => {
+                if attempt.previous.len() >= max {
+                    return ActionKind::Error(Box::new(TooManyRedirects));
+                }
+                return ActionKind::Follow;
+            }
+            PolicyKind::None => return ActionKind::Stop,
+            PolicyKind::SameOrigin => {
+                first.map_or(false, |first| first.origin() != attempt.next.origin())
+            }
+            PolicyKind::SameRegistrableDomain => first.map_or(false, |first| {
+                registrable_domain(first) != registrable_domain(attempt.next)
+            }),
+            PolicyKind::AllowHosts(ref hosts) => !matches_host(hosts, attempt.next),
+            PolicyKind::DenyHosts(ref hosts) => matches_host(hosts, attempt.next),
+            PolicyKind::NoDowngrade => last.map_or(false, |last| {
+                last.scheme() == "https" && attempt.next.scheme() == "http"
+            }),
+            PolicyKind::Named(ref name) => {
+                return match rules.0.get(name) {
+                    Some(rule) => rule.check(attempt),
+                    None => ActionKind::Error(Box::new(Unregistered(name.clone()))),
+                };
+            }
+            PolicyKind::All(ref all) => {
+                for rule in all {
+                    match rule.check(attempt, rules) {
+                        ActionKind::Follow => continue,
+                        action => return action,
+                    }
+                }
+                return ActionKind::Follow;
+            }
+        };
+        if rejected {
+            ActionKind::Error(Box::new(Rejected {
+                rule: format!("{:?}", self),
+                url: attempt.next.clone(),
+            }))
+        } else {
+            ActionKind::Follow
+        }
+    }
+
+    fn check_registered<T>(&self, registered: &HashMap<String, T>) -> crate::Result<()> {
+        match *self {
+            PolicyKind::Named(ref name) => {
+                if !registered.contains_key(name) {
+                    return Err(crate::error::builder(Unregistered(name.clone())));
+                }
+            }
+            PolicyKind::All(ref rules) => {
+                for rule in rules {
+                    rule.check_registered(registered)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn lowercase<I, H>(hosts: I) -> Vec<String>
+where
+    I: IntoIterator<Item = H>,
+    H: Into<String>,
+{
+    hosts
+        .into_iter()
+        .map(|host| host.into().to_ascii_lowercase())
+        .collect()
+}
+
+fn matches_host(hosts: &[String], url: &Url) -> bool {
+    let host = match url.host_str() {
+        Some(host) => host,
+        None => return false,
+    };
+    hosts
+        .iter()
+        .any(|pattern| match pattern.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .map_or(false, |sub| sub.ends_with('.')),
+            None => host == pattern,
+        })
+}
+
+fn registrable_domain(url: &Url) -> Option<&str> {
+    let host = url.host_str()?;
+    match url.host() {
+        Some(url::Host::Domain(_)) => psl::domain_str(host).or(Some(host)),
+        _ => Some(host),
+    }
 }
 
 impl fmt::Debug for Policy {
@@ -218,9 +502,15 @@ impl fmt::Debug for Policy {
 impl fmt::Debug for PolicyKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            // PolicyKind::Custom(..) => f.pad("Custom"),
             PolicyKind::Limit(max) => f.debug_tuple("Limit").field(&max).finish(),
             PolicyKind::None => f.pad("None"),
+            PolicyKind::SameOrigin => f.pad("SameOrigin"),
+            PolicyKind::SameRegistrableDomain => f.pad("SameRegistrableDomain"),
+            PolicyKind::AllowHosts(ref hosts) => f.debug_tuple("AllowHosts").field(hosts).finish(),
+            PolicyKind::DenyHosts(ref hosts) => f.debug_tuple("DenyHosts").field(hosts).finish(),
+            PolicyKind::NoDowngrade => f.pad("NoDowngrade"),
+            PolicyKind::Named(ref name) => f.debug_tuple("Named").field(name).finish(),
+            PolicyKind::All(ref rules) => f.debug_list().entries(rules).finish(),
         }
     }
 }
@@ -234,18 +524,178 @@ pub(crate) enum ActionKind {
     Error(Box<dyn StdError + Send + Sync>),
 }
 
-pub(crate) fn remove_sensitive_headers(headers: &mut HeaderMap, next: &Url, previous: &[Url]) {
-    if let Some(previous) = previous.last() {
-        let cross_host = next.host_str() != previous.host_str()
-            || next.port_or_known_default() != previous.port_or_known_default();
-        if cross_host {
-            headers.remove(AUTHORIZATION);
-            headers.remove(COOKIE);
-            headers.remove("cookie2");
-            headers.remove(PROXY_AUTHORIZATION);
-            headers.remove(WWW_AUTHENTICATE);
+type Start = fn(&[u8]) -> crate::Result<RuleHandle>;
+
+/// A `Rule` serialized to be started when the client is built.
+#[derive(Clone)]
+pub(crate) struct Registered {
+    start: Start,
+    state: Vec<u8>,
+}
+
+impl Registered {
+    pub(crate) fn new<R>(rule: &R) -> crate::Result<Self>
+    where
+        R: Rule + Serialize + DeserializeOwned,
+    {
+        Ok(Registered {
+            start: start::<R>,
+            state: serde_json::to_vec(rule).map_err(crate::error::builder)?,
+        })
+    }
+}
+
+fn start<R>(state: &[u8]) -> crate::Result<RuleHandle>
+where
+    R: Rule + DeserializeOwned,
+{
+    // restored here once already, so that a state that doesn't deserialize
+    // fails the build instead of the process
+    serde_json::from_slice::<R>(state).map_err(crate::error::builder)?;
+    Ok(RuleHandle(Process::spawn_link(state.to_vec(), serve::<R>)))
+}
+
+/// Runs the rule restored from `state` until it's told to stop.
+fn serve<R>(state: Vec<u8>, mailbox: Mailbox<RuleCall>)
+where
+    R: Rule + DeserializeOwned,
+{
+    let mut rule: R = serde_json::from_slice(&state).expect("checked by `start`");
+    loop {
+        match mailbox.receive() {
+            RuleCall::Check(status, next, previous, caller, tag) => {
+                let verdict = match StatusCode::from_u16(status) {
+                    Ok(status) => {
+                        let attempt = Attempt {
+                            status,
+                            next: &next,
+                            previous: &previous,
+                        };
+                        match rule.check(attempt).inner {
+                            ActionKind::Follow => Verdict::Follow,
+                            ActionKind::Stop => Verdict::Stop,
+                            ActionKind::Error(err) => Verdict::Error(err.to_string()),
+                        }
+                    }
+                    Err(err) => Verdict::Error(err.to_string()),
+                };
+                caller.tag_send(tag, verdict);
+            }
+            RuleCall::Stop => break,
+        }
+    }
+}
+
+/// A message to the process of a rule. The verdict is sent back to the
+/// given process with the given tag.
+#[derive(Serialize, Deserialize)]
+enum RuleCall {
+    Check(u16, Url, Vec<Url>, Process<Verdict>, Tag),
+    Stop,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Verdict {
+    Follow,
+    Stop,
+    // the error of the rule, which only its message survives
+    Error(String),
+}
+
+/// A `Rule` running in a process of its own.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RuleHandle(Process<RuleCall>);
+
+impl RuleHandle {
+    fn check(&self, attempt: &Attempt) -> ActionKind {
+        // SAFETY: only the verdict is received, with a tag unique to this call
+        let mailbox: Mailbox<Verdict> = unsafe { Mailbox::new() };
+        let tag = Tag::new();
+        self.0.send(RuleCall::Check(
+            attempt.status.as_u16(),
+            attempt.next.clone(),
+            attempt.previous.to_vec(),
+            mailbox.this(),
+            tag,
+        ));
+        match mailbox.tag_receive(&[tag]) {
+            Verdict::Follow => ActionKind::Follow,
+            Verdict::Stop => ActionKind::Stop,
+            Verdict::Error(message) => ActionKind::Error(Box::new(RuleFailed(message))),
+        }
+    }
+}
+
+/// The rules registered with a client, each in the process
+/// `ClientBuilder::build()` started for it.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Rules(HashMap<String, RuleHandle>);
+
+impl Rules {
+    /// Starts a process for each of the `registered` rules.
+    pub(crate) fn start(registered: &HashMap<String, Registered>) -> crate::Result<Self> {
+        let mut rules = Rules::default();
+        for (name, registered) in registered {
+            match (registered.start)(&registered.state) {
+                Ok(rule) => {
+                    rules.0.insert(name.clone(), rule);
+                }
+                Err(e) => {
+                    rules.stop();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Links the calling process to the processes of the rules, so that a
+    /// panicking rule takes down the client that asked it.
+    pub(crate) fn link(&self) {
+        for rule in self.0.values() {
+            rule.0.link();
+        }
+    }
+
+    /// Stops the processes of the rules.
+    pub(crate) fn stop(&self) {
+        for rule in self.0.values() {
+            rule.0.send(RuleCall::Stop);
+        }
+    }
+}
+
+/// Removes the headers which must not leak to another host, and returns
+/// their names.
+pub(crate) fn remove_sensitive_headers(
+    headers: &mut HeaderMap,
+    next: &Url,
+    previous: &[Url],
+) -> Vec<String> {
+    let mut removed = Vec::new();
+    if is_cross_host(next, previous) {
+        for name in &[
+            AUTHORIZATION.as_str(),
+            COOKIE.as_str(),
+            "cookie2",
+            PROXY_AUTHORIZATION.as_str(),
+            WWW_AUTHENTICATE.as_str(),
+        ] {
+            if headers.remove(*name).is_some() {
+                removed.push(name.to_string());
+            }
         }
     }
+    removed
+}
+
+/// Returns true if a redirect to `next` leaves the host of the last of the
+/// `previous` URLs.
+pub(crate) fn is_cross_host(next: &Url, previous: &[Url]) -> bool {
+    previous.last().map_or(false, |previous| {
+        next.host_str() != previous.host_str()
+            || next.port_or_known_default() != previous.port_or_known_default()
+    })
 }
 
 #[derive(Debug)]
@@ -259,6 +709,42 @@ impl fmt::Display for TooManyRedirects {
 
 impl StdError for TooManyRedirects {}
 
+#[derive(Debug)]
+struct Rejected {
+    rule: String,
+    url: Url,
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "redirect to {} rejected by {}", self.url, self.rule)
+    }
+}
+
+impl StdError for Rejected {}
+
+#[derive(Debug)]
+struct Unregistered(String);
+
+impl fmt::Display for Unregistered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no redirect policy registered as {:?}", self.0)
+    }
+}
+
+impl StdError for Unregistered {}
+
+#[derive(Debug)]
+struct RuleFailed(String);
+
+impl fmt::Display for RuleFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl StdError for RuleFailed {}
+
 #[lunatic::test]
 fn test_redirect_policy_limit() {
     let policy = Policy::default();
@@ -267,14 +753,14 @@ fn test_redirect_policy_limit() {
         .map(|i| Url::parse(&format!("http://a.b/c/{}", i)).unwrap())
         .collect::<Vec<_>>();
 
-    match policy.check(StatusCode::FOUND, &next, &previous) {
+    match policy.check(StatusCode::FOUND, &next, &previous, &Rules::default()) {
         ActionKind::Follow => (),
         other => panic!("unexpected {:?}", other),
     }
 
     previous.push(Url::parse("http://a.b.d/e/33").unwrap());
 
-    match policy.check(StatusCode::FOUND, &next, &previous) {
+    match policy.check(StatusCode::FOUND, &next, &previous, &Rules::default()) {
         ActionKind::Error(err) if err.is::<TooManyRedirects>() => (),
         other => panic!("unexpected {:?}", other),
     }
@@ -286,34 +772,101 @@ fn test_redirect_policy_limit_to_0() {
     let next = Url::parse("http://x.y/z").unwrap();
     let previous = vec![Url::parse("http://a.b/c").unwrap()];
 
-    match policy.check(StatusCode::FOUND, &next, &previous) {
+    match policy.check(StatusCode::FOUND, &next, &previous, &Rules::default()) {
         ActionKind::Error(err) if err.is::<TooManyRedirects>() => (),
         other => panic!("unexpected {:?}", other),
     }
 }
 
-// #[test]
-// fn test_redirect_policy_custom() {
-//     let policy = Policy::custom(|attempt| {
-//         if attempt.url().host_str() == Some("foo") {
-//             attempt.stop()
-//         } else {
-//             attempt.follow()
-//         }
-//     });
+#[derive(Serialize, Deserialize)]
+struct StopAtFoo;
 
-//     let next = Url::parse("http://bar/baz").unwrap();
-//     match policy.check(StatusCode::FOUND, &next, &[]) {
-//         ActionKind::Follow => (),
-//         other => panic!("unexpected {:?}", other),
-//     }
+impl Rule for StopAtFoo {
+    fn check(&mut self, attempt: Attempt) -> Action {
+        if attempt.url().host_str() == Some("foo") {
+            attempt.stop()
+        } else {
+            attempt.follow()
+        }
+    }
+}
 
-//     let next = Url::parse("http://foo/baz").unwrap();
-//     match policy.check(StatusCode::FOUND, &next, &[]) {
-//         ActionKind::Stop => (),
-//         other => panic!("unexpected {:?}", other),
-//     }
-// }
+#[lunatic::test]
+fn test_redirect_policy_named() {
+    let policy = Policy::default().named("stop-at-foo");
+    let next = Url::parse("http://bar/baz").unwrap();
+    let mut registered = HashMap::new();
+    match policy.check(StatusCode::FOUND, &next, &[], &Rules::default()) {
+        ActionKind::Error(err) if err.is::<Unregistered>() => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(policy.check_registered(&registered).is_err());
+
+    registered.insert(
+        "stop-at-foo".to_string(),
+        Registered::new(&StopAtFoo).unwrap(),
+    );
+    policy.check_registered(&registered).unwrap();
+    let rules = Rules::start(&registered).unwrap();
+    match policy.check(StatusCode::FOUND, &next, &[], &rules) {
+        ActionKind::Follow => (),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let next = Url::parse("http://foo/baz").unwrap();
+    match policy.check(StatusCode::FOUND, &next, &[], &rules) {
+        ActionKind::Stop => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    rules.stop();
+
+    // a policy deserialized from anywhere only holds the name
+    let json = serde_json::to_string(&policy).unwrap();
+    let policy: Policy = serde_json::from_str(&json).unwrap();
+    match policy.check(StatusCode::FOUND, &next, &[], &Rules::default()) {
+        ActionKind::Error(err) if err.is::<Unregistered>() => (),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    assert!(Policy::none()
+        .named("missing")
+        .check_registered(&registered)
+        .is_err());
+}
+
+#[lunatic::test]
+fn test_redirect_policy_rules() {
+    let previous = vec![Url::parse("https://www.example.co.uk/a").unwrap()];
+    let follows = |policy: &Policy, next: &str| {
+        let next = Url::parse(next).unwrap();
+        match policy.check(StatusCode::FOUND, &next, &previous, &Rules::default()) {
+            ActionKind::Follow => true,
+            ActionKind::Error(err) if err.is::<Rejected>() => false,
+            other => panic!("unexpected {:?}", other),
+        }
+    };
+
+    let same_origin = Policy::default().same_origin();
+    assert!(follows(&same_origin, "https://www.example.co.uk/b"));
+    assert!(!follows(&same_origin, "https://www.example.co.uk:8443/b"));
+    assert!(!follows(&same_origin, "https://api.example.co.uk/b"));
+
+    let same_domain = Policy::default().same_registrable_domain();
+    assert!(follows(&same_domain, "https://api.example.co.uk/b"));
+    assert!(!follows(&same_domain, "https://other.co.uk/b"));
+
+    let hosts = Policy::default()
+        .allow_hosts(vec!["*.example.co.uk", "cdn.net"])
+        .and(Policy::default().deny_hosts(vec!["Admin.Example.co.uk"]));
+    assert!(follows(&hosts, "https://www.example.co.uk/b"));
+    assert!(follows(&hosts, "https://cdn.net/b"));
+    assert!(!follows(&hosts, "https://example.co.uk/b"));
+    assert!(!follows(&hosts, "https://admin.example.co.uk/b"));
+
+    let no_downgrade = Policy::default().no_downgrade();
+    assert!(follows(&no_downgrade, "https://other.com/b"));
+    assert!(!follows(&no_downgrade, "http://www.example.co.uk/b"));
+}
 
 #[lunatic::test]
 fn test_remove_sensitive_headers() {
@@ -328,13 +881,16 @@ fn test_remove_sensitive_headers() {
     let mut prev = vec![Url::parse("http://initial-domain.com/new_path").unwrap()];
     let mut filtered_headers = headers.clone();
 
-    remove_sensitive_headers(&mut headers, &next, &prev);
+    assert!(remove_sensitive_headers(&mut headers, &next, &prev).is_empty());
     assert_eq!(headers, filtered_headers);
 
     prev.push(Url::parse("http://new-domain.com/path").unwrap());
     filtered_headers.remove(AUTHORIZATION);
     filtered_headers.remove(COOKIE);
 
-    remove_sensitive_headers(&mut headers, &next, &prev);
+    assert_eq!(
+        remove_sensitive_headers(&mut headers, &next, &prev),
+        vec!["authorization", "cookie"]
+    );
     assert_eq!(headers, filtered_headers);
 }
@@ -0,0 +1,302 @@
+//! Retry Handling
+//!
+//! By default, a `Client` doesn't retry failed requests. A `retry::Policy`
+//! can be set on a `ClientBuilder`, or on a single request with
+//! `RequestBuilder::retry`, to retry requests that failed with a connection
+//! error or a retryable status code.
+//!
+//! Retries happen in the process that sends the request, so waiting between
+//! attempts never blocks other users of the same `Client`.
+
+use std::time::{Duration, SystemTime};
+
+use http::header::RETRY_AFTER;
+use http::{HeaderValue, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::HttpResponse;
+
+/// A type that controls which failed requests are retried, and how long to
+/// wait between attempts.
+///
+/// The default policy makes up to 3 attempts for idempotent requests that
+/// failed to connect, timed out or returned `429`, `502`, `503` or `504`,
+/// waiting an exponentially growing, jittered delay between attempts.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # fn run() -> Result<(), nightfly::Error> {
+/// let policy = nightfly::retry::Policy::default()
+///     .max_attempts(5)
+///     .backoff(Duration::from_millis(50), Duration::from_secs(2))
+///     .deadline(Duration::from_secs(10));
+/// let client = nightfly::Client::builder().retry(policy).build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Policy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    statuses: Vec<u16>,
+    errors: Vec<ErrorKind>,
+    idempotent_only: bool,
+    deadline: Option<Duration>,
+}
+
+/// The kinds of errors a [`Policy`] can retry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorKind {
+    /// The connection couldn't be established.
+    Connect,
+    /// The request timed out.
+    Timeout,
+    /// The response couldn't be decoded.
+    Decode,
+}
+
+impl Policy {
+    /// Create a `Policy` that never retries.
+    pub fn never() -> Self {
+        Policy::default().max_attempts(1)
+    }
+
+    /// Set the maximum number of attempts, including the first one.
+    pub fn max_attempts(mut self, max: u32) -> Self {
+        self.max_attempts = max.max(1);
+        self
+    }
+
+    /// Set the delay before the first retry and the upper bound for the
+    /// delay, which doubles after every attempt.
+    ///
+    /// The upper bound also applies to delays asked for with `Retry-After`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Enable or disable randomizing the delay between attempts.
+    ///
+    /// With jitter enabled, the delay is picked between half and all of the
+    /// exponential backoff, so that many clients don't retry at once.
+    ///
+    /// Default is `true`.
+    pub fn jitter(mut self, enabled: bool) -> Self {
+        self.jitter = enabled;
+        self
+    }
+
+    /// Set the response status codes that are retried.
+    pub fn retry_statuses<I>(mut self, statuses: I) -> Self
+    where
+        I: IntoIterator<Item = StatusCode>,
+    {
+        self.statuses = statuses.into_iter().map(|s| s.as_u16()).collect();
+        self
+    }
+
+    /// Set the kinds of errors that are retried.
+    pub fn retry_errors<I>(mut self, errors: I) -> Self
+    where
+        I: IntoIterator<Item = ErrorKind>,
+    {
+        self.errors = errors.into_iter().collect();
+        self
+    }
+
+    /// Allow retrying requests with non-idempotent methods, like `POST`.
+    ///
+    /// Default is `false`.
+    pub fn retry_non_idempotent(mut self, enabled: bool) -> Self {
+        self.idempotent_only = !enabled;
+        self
+    }
+
+    /// Set the total time after which no more attempts are started,
+    /// counted from the start of the first attempt.
+    ///
+    /// Default is no deadline.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub(crate) fn total_deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+
+    pub(crate) fn allows_method(&self, method: &Method) -> bool {
+        self.max_attempts > 1 && (!self.idempotent_only || is_idempotent(method))
+    }
+
+    /// Returns the delay before the next attempt if the response of attempt
+    /// number `attempt` should be retried.
+    pub(crate) fn retry_response(&self, res: &HttpResponse, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_attempts || !self.statuses.contains(&res.status().as_u16()) {
+            return None;
+        }
+        let retry_after = match res.status() {
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+                res.headers().get(RETRY_AFTER).and_then(parse_retry_after)
+            }
+            _ => None,
+        };
+        Some(match retry_after {
+            Some(retry_after) => retry_after.min(self.max_backoff),
+            None => self.backoff_for(attempt),
+        })
+    }
+
+    /// Returns the delay before the next attempt if the error of attempt
+    /// number `attempt` should be retried.
+    pub(crate) fn retry_error(&self, err: &crate::Error, attempt: u32) -> Option<Duration> {
+        // waiting for a free slot again would only queue up behind the same
+        // requests that made this one time out
+        if attempt >= self.max_attempts || err.is_queue_timeout() {
+            return None;
+        }
+        let kind = if err.is_timeout() {
+            ErrorKind::Timeout
+        } else if err.is_connect() {
+            ErrorKind::Connect
+        } else if err.is_decode() {
+            ErrorKind::Decode
+        } else {
+            return None;
+        };
+        if self.errors.contains(&kind) {
+            Some(self.backoff_for(attempt))
+        } else {
+            None
+        }
+    }
+
+    fn backoff_for(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let backoff = self
+            .initial_backoff
+            .checked_mul(factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+        if !self.jitter {
+            return backoff;
+        }
+        let half = backoff / 2;
+        let spread = half.as_nanos() as u64 + 1;
+        half + Duration::from_nanos(crate::util::fast_random() % spread)
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            jitter: true,
+            statuses: vec![429, 502, 503, 504],
+            errors: vec![ErrorKind::Connect, ErrorKind::Timeout],
+            idempotent_only: true,
+            deadline: None,
+        }
+    }
+}
+
+pub(crate) fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PUT | Method::DELETE
+    )
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+#[lunatic::test]
+fn test_retry_policy_backoff() {
+    let policy = Policy::default()
+        .jitter(false)
+        .backoff(Duration::from_millis(100), Duration::from_millis(300));
+
+    assert_eq!(policy.backoff_for(1), Duration::from_millis(100));
+    assert_eq!(policy.backoff_for(2), Duration::from_millis(200));
+    assert_eq!(policy.backoff_for(3), Duration::from_millis(300));
+    assert_eq!(policy.backoff_for(40), Duration::from_millis(300));
+
+    let policy = policy.jitter(true);
+    for attempt in 1..5 {
+        let backoff = policy.backoff_for(attempt);
+        assert!(backoff >= Duration::from_millis(50));
+        assert!(backoff <= Duration::from_millis(300));
+    }
+}
+
+#[lunatic::test]
+fn test_retry_policy_methods() {
+    let policy = Policy::default();
+    assert!(policy.allows_method(&Method::GET));
+    assert!(policy.allows_method(&Method::PUT));
+    assert!(!policy.allows_method(&Method::POST));
+    assert!(policy
+        .clone()
+        .retry_non_idempotent(true)
+        .allows_method(&Method::POST));
+    assert!(!Policy::never().allows_method(&Method::GET));
+}
+
+#[lunatic::test]
+fn test_parse_retry_after() {
+    assert_eq!(
+        parse_retry_after(&HeaderValue::from_static("120")),
+        Some(Duration::from_secs(120))
+    );
+    assert_eq!(
+        parse_retry_after(&HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT")),
+        Some(Duration::from_secs(0))
+    );
+    assert_eq!(parse_retry_after(&HeaderValue::from_static("soon")), None);
+}
+
+#[lunatic::test]
+fn test_retry_after_is_capped() {
+    let policy = Policy::default().backoff(Duration::from_millis(100), Duration::from_secs(5));
+    let url = url::Url::parse("http://example.com/").unwrap();
+    let mut res = HttpResponse::new(StatusCode::SERVICE_UNAVAILABLE, url.clone(), vec![]);
+    res.headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from_static("86400"));
+    assert_eq!(policy.retry_response(&res, 1), Some(Duration::from_secs(5)));
+
+    res.headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from_static("2"));
+    assert_eq!(policy.retry_response(&res, 1), Some(Duration::from_secs(2)));
+
+    assert_eq!(
+        policy.retry_error(&crate::error::queue_timeout(url), 1),
+        None
+    );
+}
+
+#[lunatic::test]
+fn test_only_connect_errors_are_retried_as_connect() {
+    let policy = Policy::default();
+    assert!(policy
+        .retry_error(&crate::error::connect("connection refused"), 1)
+        .is_some());
+    assert_eq!(
+        policy.retry_error(&crate::error::request("connection closed"), 1),
+        None
+    );
+}
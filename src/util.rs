@@ -1,3 +1,6 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
 use crate::header::{Entry, HeaderMap, OccupiedEntry};
 
 pub(crate) fn replace_headers(dst: &mut HeaderMap, src: HeaderMap) {
@@ -28,3 +31,9 @@ pub(crate) fn replace_headers(dst: &mut HeaderMap, src: HeaderMap) {
         }
     }
 }
+
+/// A random number that is good enough for jitter, taken from the randomly
+/// seeded hasher of the standard library.
+pub(crate) fn fast_random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
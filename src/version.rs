@@ -19,6 +19,7 @@
 //! println!("{:?}", http2);
 //! ```
 
+use std::convert::TryFrom;
 use std::fmt;
 
 use serde::{Deserialize, Serialize};
@@ -44,28 +45,29 @@ impl Version {
     pub const HTTP_3: Version = Version(Http::H3);
 }
 
-impl From<http::Version> for Version {
-    fn from(version: http::Version) -> Self {
+impl TryFrom<http::Version> for Version {
+    type Error = crate::Error;
+
+    fn try_from(version: http::Version) -> Result<Self, Self::Error> {
         match version {
-            http::Version::HTTP_09 => Version::HTTP_09,
-            http::Version::HTTP_10 => Version::HTTP_10,
-            http::Version::HTTP_11 => Version::HTTP_11,
-            http::Version::HTTP_2 => Version::HTTP_2,
-            http::Version::HTTP_3 => Version::HTTP_3,
-            _ => unimplemented!(),
+            http::Version::HTTP_09 => Ok(Version::HTTP_09),
+            http::Version::HTTP_10 => Ok(Version::HTTP_10),
+            http::Version::HTTP_11 => Ok(Version::HTTP_11),
+            http::Version::HTTP_2 => Ok(Version::HTTP_2),
+            http::Version::HTTP_3 => Ok(Version::HTTP_3),
+            other => Err(crate::error::version(format!("{:?}", other))),
         }
     }
 }
 
 impl From<Version> for http::Version {
     fn from(version: Version) -> Self {
-        match version {
-            Version::HTTP_09 => http::Version::HTTP_09,
-            Version::HTTP_10 => http::Version::HTTP_10,
-            Version::HTTP_11 => http::Version::HTTP_11,
-            Version::HTTP_2 => http::Version::HTTP_2,
-            Version::HTTP_3 => http::Version::HTTP_3,
-            _ => unimplemented!(),
+        match version.0 {
+            Http::Http09 => http::Version::HTTP_09,
+            Http::Http10 => http::Version::HTTP_10,
+            Http::Http11 => http::Version::HTTP_11,
+            Http::H2 => http::Version::HTTP_2,
+            Http::H3 => http::Version::HTTP_3,
         }
     }
 }
@@ -77,7 +79,6 @@ enum Http {
     Http11,
     H2,
     H3,
-    __NonExhaustive,
 }
 
 impl Default for Version {
@@ -97,7 +98,6 @@ impl fmt::Debug for Version {
             Http11 => "HTTP/1.1",
             H2 => "HTTP/2.0",
             H3 => "HTTP/3.0",
-            __NonExhaustive => unreachable!(),
         })
     }
 }
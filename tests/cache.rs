@@ -0,0 +1,158 @@
+#[macro_use]
+pub mod support;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use nightfly::cache::{CacheStatus, MemoryStore};
+use submillisecond::{response::Response as SubmsResponse, router, RequestContext};
+use support::RouterFn;
+
+fn now() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    now.as_nanos().to_string()
+}
+
+fn fresh() -> SubmsResponse {
+    SubmsResponse::builder()
+        .header("Cache-Control", "max-age=60")
+        .body(now().into_bytes())
+        .unwrap()
+}
+
+fn etag(req: RequestContext) -> SubmsResponse {
+    if req.headers().get("if-none-match").map(|v| v.as_bytes()) == Some(b"\"v1\"") {
+        return SubmsResponse::builder()
+            .status(304)
+            .header("ETag", "\"v1\"")
+            .header("X-Revalidated", "yes")
+            .body(Default::default())
+            .unwrap();
+    }
+    SubmsResponse::builder()
+        .header("Cache-Control", "no-cache")
+        .header("ETag", "\"v1\"")
+        .body(now().into_bytes())
+        .unwrap()
+}
+
+fn no_store() -> SubmsResponse {
+    SubmsResponse::builder()
+        .header("Cache-Control", "no-store")
+        .body(now().into_bytes())
+        .unwrap()
+}
+
+fn vary_cookie() -> SubmsResponse {
+    SubmsResponse::builder()
+        .header("Cache-Control", "max-age=60")
+        .header("Vary", "Cookie")
+        .body(now().into_bytes())
+        .unwrap()
+}
+
+fn login() -> SubmsResponse {
+    SubmsResponse::builder()
+        .header("Set-Cookie", "session=1")
+        .body(Default::default())
+        .unwrap()
+}
+
+static ROUTER: RouterFn = router! {
+    GET "/fresh" => fresh
+    GET "/etag" => etag
+    GET "/no-store" => no_store
+    GET "/vary-cookie" => vary_cookie
+    GET "/login" => login
+};
+
+static ADDR: &'static str = "0.0.0.0:3022";
+
+wrap_server!(server, ROUTER, ADDR);
+
+#[lunatic::test]
+fn test_fresh_response_is_a_hit() {
+    let _ = server::ensure_server();
+    let client = nightfly::Client::builder()
+        .cache(MemoryStore::new(10))
+        .build()
+        .unwrap();
+    let url = format!("http://{}/fresh", ADDR);
+
+    let first = client.get(&url).send().unwrap();
+    assert_eq!(first.cache_status(), Some(CacheStatus::Miss));
+    let second = client.get(&url).send().unwrap();
+    assert_eq!(second.cache_status(), Some(CacheStatus::Hit));
+    assert!(second.headers().contains_key("age"));
+    assert_eq!(first.text().unwrap(), second.text().unwrap());
+
+    // the request can ask for a fresh response
+    let third = client
+        .get(&url)
+        .header("Cache-Control", "no-cache")
+        .send()
+        .unwrap();
+    assert_eq!(third.cache_status(), Some(CacheStatus::Miss));
+}
+
+#[lunatic::test]
+fn test_stale_response_is_revalidated() {
+    let _ = server::ensure_server();
+    let client = nightfly::Client::builder()
+        .cache(MemoryStore::new(10))
+        .build()
+        .unwrap();
+    let url = format!("http://{}/etag", ADDR);
+
+    let first = client.get(&url).send().unwrap();
+    assert_eq!(first.cache_status(), Some(CacheStatus::Miss));
+    let second = client.get(&url).send().unwrap();
+    assert_eq!(second.cache_status(), Some(CacheStatus::Revalidated));
+    assert_eq!(second.status(), nightfly::StatusCode::OK);
+    assert_eq!(second.headers()["x-revalidated"], "yes");
+    assert_eq!(first.text().unwrap(), second.text().unwrap());
+}
+
+#[lunatic::test]
+fn test_no_store_is_not_cached() {
+    let _ = server::ensure_server();
+    let client = nightfly::Client::builder()
+        .cache(MemoryStore::new(10))
+        .build()
+        .unwrap();
+    let url = format!("http://{}/no-store", ADDR);
+
+    client.get(&url).send().unwrap();
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.cache_status(), Some(CacheStatus::Miss));
+
+    let err_res = client
+        .get(&url)
+        .header("Cache-Control", "only-if-cached")
+        .send()
+        .unwrap();
+    assert_eq!(err_res.status(), nightfly::StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[cfg(feature = "cookies")]
+#[lunatic::test]
+fn test_vary_matches_the_cookies_that_are_sent() {
+    let _ = server::ensure_server();
+    let client = nightfly::Client::builder()
+        .cookie_store(true)
+        .cache(MemoryStore::new(10))
+        .build()
+        .unwrap();
+    let url = format!("http://{}/vary-cookie", ADDR);
+
+    let anonymous = client.get(&url).send().unwrap();
+    assert_eq!(anonymous.cache_status(), Some(CacheStatus::Miss));
+
+    // the cookie jar now sends a cookie, so the stored variant doesn't match
+    client.get(format!("http://{}/login", ADDR)).send().unwrap();
+    let logged_in = client.get(&url).send().unwrap();
+    assert_eq!(logged_in.cache_status(), Some(CacheStatus::Miss));
+    assert_ne!(anonymous.text().unwrap(), logged_in.text().unwrap());
+
+    let again = client.get(&url).send().unwrap();
+    assert_eq!(again.cache_status(), Some(CacheStatus::Hit));
+}
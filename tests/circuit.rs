@@ -0,0 +1,81 @@
+#[macro_use]
+pub mod support;
+
+use std::time::Duration;
+
+use nightfly::circuit::{Policy, State};
+use submillisecond::{response::Response as SubmsResponse, router};
+use support::RouterFn;
+
+static FAILING_ADDR: &'static str = "0.0.0.0:3017";
+
+fn slow() -> SubmsResponse {
+    lunatic::sleep(Duration::from_millis(200));
+    SubmsResponse::default()
+}
+
+static ROUTER: RouterFn = router! {
+    GET "/slow" => slow
+};
+
+static SLOW_ADDR: &'static str = "0.0.0.0:3028";
+
+wrap_server!(server, ROUTER, SLOW_ADDR);
+
+#[lunatic::test]
+fn test_circuit_opens_for_failing_host() {
+    support::serve_raw(
+        FAILING_ADDR,
+        b"HTTP/1.1 500 Internal Server Error\r\n\
+          Connection: close\r\n\
+          Content-Length: 0\r\n\
+          \r\n"
+            .to_vec(),
+    );
+
+    let client = nightfly::Client::builder()
+        .circuit_breaker(
+            Policy::default()
+                .consecutive_failures(2)
+                .open_duration(Duration::from_secs(60)),
+        )
+        .build()
+        .unwrap();
+    let url = format!("http://{}/", FAILING_ADDR);
+
+    for _ in 0..2 {
+        let res = client.get(&url).send().unwrap();
+        assert_eq!(res.status(), nightfly::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    assert_eq!(client.circuit_state(&url).unwrap(), Some(State::Open));
+
+    let err = client.get(&url).send().unwrap_err();
+    assert!(err.is_circuit_open());
+}
+
+#[lunatic::test]
+fn test_circuit_counts_late_responses_as_timeouts() {
+    let _ = server::ensure_server();
+
+    let client = nightfly::Client::builder()
+        .circuit_breaker(
+            Policy::default()
+                .consecutive_failures(2)
+                .open_duration(Duration::from_secs(60)),
+        )
+        .timeout(Duration::from_millis(50))
+        .build()
+        .unwrap();
+    let url = format!("http://{}/slow", SLOW_ADDR);
+
+    let err = client.get(&url).send().unwrap_err();
+    assert!(err.is_timeout());
+    // answered once the client process is done with the late request
+    assert_eq!(client.circuit_state(&url).unwrap(), Some(State::Closed));
+
+    // the late success of the second request doesn't reset the count of
+    // failures either
+    let err = client.get(&url).send().unwrap_err();
+    assert!(err.is_timeout());
+    assert_eq!(client.circuit_state(&url).unwrap(), Some(State::Open));
+}
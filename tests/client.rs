@@ -119,6 +119,37 @@ fn test_response_text() {
     assert_eq!("Hello", text);
 }
 
+#[lunatic::test]
+fn test_client_metrics() {
+    let _ = server::ensure_server();
+
+    let client = Client::new();
+    for _ in 0..2 {
+        let res = client.get(&format!("http://{}/text", ADDR)).send().unwrap();
+        assert_eq!(res.text().unwrap(), "Hello");
+    }
+    let err = client.get("http://127.0.0.1:1/").send().unwrap_err();
+    assert!(err.is_connect());
+
+    let metrics = client.metrics();
+    assert_eq!(metrics.requests["GET"]["2xx"], 2);
+    assert_eq!(metrics.responses_with_class("2xx"), 2);
+    assert_eq!(metrics.errors["connect"], 1);
+    assert_eq!(metrics.pool_hits + metrics.pool_misses, 3);
+    assert!(metrics.bytes_sent > 0);
+    assert_eq!(metrics.bytes_received_decoded, 10);
+    // the raw bytes include the response heads
+    assert!(metrics.bytes_received > 10);
+    assert_eq!(metrics.latency.count, 2);
+    assert_eq!(metrics.time_to_first_byte.count, 2);
+
+    client.reset_metrics();
+    let metrics = client.metrics();
+    assert!(metrics.requests.is_empty());
+    assert_eq!(metrics.total_errors(), 0);
+    assert_eq!(metrics.latency.count, 0);
+}
+
 #[lunatic::test]
 fn test_response_bytes() {
     let _ = server::ensure_server();
@@ -83,6 +83,21 @@ fn path(req: RequestContext) -> SubmsResponse {
     }
 }
 
+fn disabled(req: RequestContext) -> SubmsResponse {
+    assert_eq!(req.headers().get("cookie"), None);
+    http::Response::builder()
+        .header("Set-Cookie", "key=val")
+        .body(Default::default())
+        .unwrap()
+}
+
+fn persist() -> SubmsResponse {
+    http::Response::builder()
+        .header("Set-Cookie", "persist=1; Max-Age=3600")
+        .body(Default::default())
+        .unwrap()
+}
+
 static ROUTER: RouterFn = router! {
     GET "/" => cookie_response
     GET "/1" => cookie_simple
@@ -94,6 +109,8 @@ static ROUTER: RouterFn = router! {
     GET "/expires" => expires
     GET "/path" => path
     GET "/subpath" => path
+    GET "/disabled" => disabled
+    GET "/persist" => persist
 };
 
 static ADDR: &'static str = "0.0.0.0:3000";
@@ -217,3 +234,126 @@ fn cookie_store_path() {
     let url = format!("http://{}/subpath", ADDR);
     client.get(&url).send().unwrap();
 }
+
+#[lunatic::test]
+fn cookie_store_disabled() {
+    let _ = server::ensure_server();
+
+    let client = nightfly::Client::builder()
+        .cookie_store(false)
+        .build()
+        .unwrap();
+
+    let url = format!("http://{}/disabled", ADDR);
+    client.get(&url).send().unwrap();
+    client.get(&url).send().unwrap();
+}
+
+#[lunatic::test]
+fn cookie_provider_jar() {
+    let _ = server::ensure_server();
+
+    let url = format!("http://{}/2", ADDR).parse().unwrap();
+    let jar = nightfly::cookie::Jar::default();
+    jar.add_cookie_str("key=val", &url);
+    let client = nightfly::Client::builder()
+        .cookie_provider(jar)
+        .build()
+        .unwrap();
+
+    client.get(url).send().unwrap();
+}
+
+#[lunatic::test]
+fn cookie_store_shared() {
+    let _ = server::ensure_server();
+
+    let jar = nightfly::cookie::SharedJar::new(Default::default());
+    let first = nightfly::Client::builder()
+        .shared_cookie_provider(jar.clone())
+        .build()
+        .unwrap();
+    let second = nightfly::Client::builder()
+        .shared_cookie_provider(jar)
+        .build()
+        .unwrap();
+
+    let url = format!("http://{}/1", ADDR);
+    first.get(&url).send().unwrap();
+
+    // the cookie set for the first client is sent by the second one
+    let url = format!("http://{}/2", ADDR);
+    second.get(&url).send().unwrap();
+}
+
+#[lunatic::test]
+fn cookie_store_management() {
+    use nightfly::cookie::{CookieFilter, StoredCookie};
+
+    let _ = server::ensure_server();
+
+    let client = nightfly::Client::builder().build().unwrap();
+
+    let url = format!("http://{}/1", ADDR);
+    client.get(&url).send().unwrap();
+    let cookies = client.cookies(CookieFilter::new().name("key"));
+    assert_eq!(cookies.len(), 1);
+    assert_eq!(cookies[0].value, "val");
+    assert!(cookies[0].http_only);
+
+    client
+        .insert_cookie(StoredCookie::new("other", "1", "example.com"))
+        .unwrap();
+    assert_eq!(client.cookies(CookieFilter::new()).len(), 2);
+    assert_eq!(
+        client
+            .cookies(CookieFilter::new().domain("example.com"))
+            .len(),
+        1
+    );
+
+    assert_eq!(client.delete_cookies(CookieFilter::new().name("key")), 1);
+    let url = format!("http://{}/disabled", ADDR);
+    client.get(&url).send().unwrap();
+
+    client.clear_cookies();
+    assert!(client.cookies(CookieFilter::new()).is_empty());
+}
+
+#[lunatic::test]
+fn cookie_store_autosave() {
+    use nightfly::cookie::CookieFilter;
+
+    let _ = server::ensure_server();
+
+    let path = format!("cookies-{}.json", lunatic::host::process_id());
+    let client = nightfly::Client::builder()
+        .cookie_autosave(&path)
+        .build()
+        .unwrap();
+    client
+        .get(format!("http://{}/persist", ADDR))
+        .send()
+        .unwrap();
+    client.shutdown();
+
+    // a restarted client starts with the saved cookies
+    let client = nightfly::Client::builder()
+        .cookie_autosave(&path)
+        .build()
+        .unwrap();
+    let cookies = client.cookies(CookieFilter::new().name("persist"));
+    client.shutdown();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(cookies.len(), 1);
+    assert_eq!(cookies[0].value, "1");
+}
+
+#[lunatic::test]
+fn cookie_store_autosave_corrupt_file() {
+    let path = format!("cookies-corrupt-{}.json", lunatic::host::process_id());
+    std::fs::write(&path, "not json").unwrap();
+    let result = nightfly::Client::builder().cookie_autosave(&path).build();
+    std::fs::remove_file(&path).unwrap();
+    assert!(result.is_err());
+}
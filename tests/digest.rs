@@ -0,0 +1,84 @@
+#[macro_use]
+pub mod support;
+
+use submillisecond::{response::Response as SubmsResponse, router, RequestContext};
+use support::RouterFn;
+
+fn protected(req: RequestContext) -> SubmsResponse {
+    let authorization = req
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    if !authorization.starts_with("Digest ") {
+        return SubmsResponse::builder()
+            .status(401)
+            .header(
+                "WWW-Authenticate",
+                "Digest realm=\"test\", qop=\"auth\", algorithm=MD5, nonce=\"abc\", opaque=\"xyz\"",
+            )
+            .header(
+                "WWW-Authenticate",
+                "Digest realm=\"test\", qop=\"auth\", algorithm=SHA-256, nonce=\"abc\", opaque=\"xyz\"",
+            )
+            .body(Default::default())
+            .unwrap();
+    }
+    assert!(authorization.contains("username=\"user\""));
+    assert!(authorization.contains("algorithm=SHA-256"));
+    assert!(authorization.contains("opaque=\"xyz\""));
+    assert!(authorization.contains("qop=auth"));
+    // answer with the nonce count, so the test can tell whether the
+    // challenge was answered or the stored one was used
+    let nc = authorization
+        .split(", ")
+        .find_map(|param| param.strip_prefix("nc="))
+        .unwrap()
+        .to_string();
+    SubmsResponse::builder().body(nc.into_bytes()).unwrap()
+}
+
+fn redirect() -> SubmsResponse {
+    SubmsResponse::builder()
+        .status(302)
+        .header("Location", "/protected")
+        .body(Default::default())
+        .unwrap()
+}
+
+static ROUTER: RouterFn = router! {
+    GET "/protected" => protected
+    GET "/redirect" => redirect
+};
+
+static ADDR: &'static str = "0.0.0.0:3023";
+
+wrap_server!(server, ROUTER, ADDR);
+
+#[lunatic::test]
+fn test_digest_auth_answers_challenge() {
+    let _ = server::ensure_server();
+    let client = nightfly::Client::new();
+    let url = format!("http://{}/protected", ADDR);
+
+    let res = client.get(&url).digest_auth("user", "pass").send().unwrap();
+    assert_eq!(res.status(), nightfly::StatusCode::OK);
+    assert_eq!(res.text().unwrap(), "00000001");
+
+    // the stored challenge is used right away with the next nonce count
+    let res = client.get(&url).digest_auth("user", "pass").send().unwrap();
+    assert_eq!(res.text().unwrap(), "00000002");
+
+    // and on redirects within the same origin
+    let res = client
+        .get(format!("http://{}/redirect", ADDR))
+        .digest_auth("user", "pass")
+        .send()
+        .unwrap();
+    assert_eq!(res.text().unwrap(), "00000004");
+
+    // requests without credentials don't answer challenges
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status(), nightfly::StatusCode::UNAUTHORIZED);
+}
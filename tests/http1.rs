@@ -0,0 +1,174 @@
+#[macro_use]
+pub mod support;
+
+static EARLY_HINTS_ADDR: &'static str = "0.0.0.0:3010";
+
+#[lunatic::test]
+fn test_interim_responses_are_skipped() {
+    support::serve_raw(
+        EARLY_HINTS_ADDR,
+        b"HTTP/1.1 100 Continue\r\n\r\n\
+          HTTP/1.1 103 Early Hints\r\n\
+          Link: </style.css>; rel=preload; as=style\r\n\
+          \r\n\
+          HTTP/1.1 200 OK\r\n\
+          Content-Length: 5\r\n\
+          \r\n\
+          Hello"
+            .to_vec(),
+    );
+
+    let res = nightfly::get(format!("http://{}/", EARLY_HINTS_ADDR)).unwrap();
+
+    assert_eq!(res.status(), nightfly::StatusCode::OK);
+    assert_eq!(
+        res.early_hints()["link"],
+        "</style.css>; rel=preload; as=style"
+    );
+    assert_eq!(res.text().unwrap(), "Hello");
+}
+
+static HTTP10_ADDR: &'static str = "0.0.0.0:3011";
+static HTTP09_ADDR: &'static str = "0.0.0.0:3012";
+
+#[lunatic::test]
+fn test_http10_close_delimited_body() {
+    support::serve_raw(
+        HTTP10_ADDR,
+        b"HTTP/1.0 200 OK\r\n\r\nuntil the connection closes".to_vec(),
+    );
+
+    let res = nightfly::get(format!("http://{}/", HTTP10_ADDR)).unwrap();
+
+    assert_eq!(res.version(), nightfly::Version::HTTP_10);
+    assert_eq!(res.text().unwrap(), "until the connection closes");
+}
+
+#[lunatic::test]
+fn test_http09_responses() {
+    support::serve_raw(HTTP09_ADDR, b"<html>old device</html>".to_vec());
+
+    let err = nightfly::get(format!("http://{}/", HTTP09_ADDR)).unwrap_err();
+    assert!(err.is_version());
+
+    let res = nightfly::Client::builder()
+        .http09_responses()
+        .build()
+        .unwrap()
+        .get(format!("http://{}/", HTTP09_ADDR))
+        .send()
+        .unwrap();
+
+    assert_eq!(res.version(), nightfly::Version::HTTP_09);
+    assert_eq!(res.text().unwrap(), "<html>old device</html>");
+}
+
+static OBS_FOLD_ADDR: &'static str = "0.0.0.0:3013";
+
+#[lunatic::test]
+fn test_obsolete_multiline_headers() {
+    support::serve_raw(
+        OBS_FOLD_ADDR,
+        b"HTTP/1.1 200 OK\r\n\
+          X-Folded: first\r\n second\r\n\t third\r\n\
+          Content-Length: 2\r\n\
+          \r\n\
+          ok"
+        .to_vec(),
+    );
+
+    let err = nightfly::get(format!("http://{}/", OBS_FOLD_ADDR)).unwrap_err();
+    assert!(err.is_decode());
+
+    let res = nightfly::Client::builder()
+        .http1_allow_obsolete_multiline_headers_in_responses(true)
+        .build()
+        .unwrap()
+        .get(format!("http://{}/", OBS_FOLD_ADDR))
+        .send()
+        .unwrap();
+
+    assert_eq!(res.headers()["x-folded"], "first second third");
+    assert_eq!(res.text().unwrap(), "ok");
+}
+
+static STALE_ADDR: &'static str = "0.0.0.0:3016";
+
+#[lunatic::test]
+fn test_stale_pooled_connection_is_replaced() {
+    // the server closes every connection after answering, although the
+    // response allows keeping it alive
+    support::serve_raw(
+        STALE_ADDR,
+        b"HTTP/1.1 200 OK\r\n\
+          Content-Length: 2\r\n\
+          \r\n\
+          ok"
+        .to_vec(),
+    );
+
+    let client = nightfly::Client::new();
+    for _ in 0..3 {
+        let res = client
+            .get(format!("http://{}/", STALE_ADDR))
+            .send()
+            .unwrap();
+        // the stale connection was swapped for a fresh one
+        assert!(!res.info().unwrap().is_reused());
+        assert_eq!(res.text().unwrap(), "ok");
+    }
+
+    // a POST might have reached the server, so it isn't replayed
+    let err = client
+        .post(format!("http://{}/", STALE_ADDR))
+        .send()
+        .unwrap_err();
+    assert!(err.is_request());
+}
+
+static INFO_ADDR: &'static str = "0.0.0.0:3027";
+
+#[lunatic::test]
+fn test_connection_info() {
+    support::serve_raw(
+        INFO_ADDR,
+        b"HTTP/1.1 200 OK\r\n\
+          Content-Length: 2\r\n\
+          \r\n\
+          ok"
+        .to_vec(),
+    );
+
+    let res = nightfly::get(format!("http://{}/", INFO_ADDR)).unwrap();
+    let info = res.info().unwrap();
+    assert_eq!(info.remote_addr().unwrap().port(), 3027);
+    assert_eq!(res.remote_addr(), info.remote_addr());
+    assert!(!info.is_tls());
+    assert!(!info.is_reused());
+    assert_eq!(info.version(), nightfly::Version::HTTP_11);
+
+    // a fresh connection records every phase except the TLS handshake
+    let timings = res.timings();
+    assert!(timings.resolve().is_some());
+    assert!(timings.connect().is_some());
+    assert!(timings.tls_handshake().is_none());
+    assert!(timings.total() >= timings.first_byte() + timings.body());
+}
+
+static BAD_GZIP_ADDR: &'static str = "0.0.0.0:3029";
+
+#[lunatic::test]
+fn test_undecodable_body_is_an_error() {
+    support::serve_raw(
+        BAD_GZIP_ADDR,
+        b"HTTP/1.1 200 OK\r\n\
+          Content-Encoding: gzip\r\n\
+          Content-Length: 8\r\n\
+          \r\n\
+          not gzip"
+            .to_vec(),
+    );
+
+    let err = nightfly::get(format!("http://{}/", BAD_GZIP_ADDR)).unwrap_err();
+    assert!(err.is_decode());
+}
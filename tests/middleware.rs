@@ -0,0 +1,98 @@
+#[macro_use]
+pub mod support;
+
+use nightfly::middleware::Middleware;
+use nightfly::{HttpResponse, Request, StatusCode};
+use serde::{Deserialize, Serialize};
+
+static MIDDLEWARE_ADDR: &'static str = "0.0.0.0:3020";
+
+#[derive(Serialize, Deserialize)]
+struct Offline {
+    path: String,
+    answered: usize,
+}
+
+impl Middleware for Offline {
+    fn handle_request(&mut self, req: &mut Request) -> Option<nightfly::Result<HttpResponse>> {
+        if req.url().path() != self.path {
+            return None;
+        }
+        self.answered += 1;
+        let body = format!("answered {}", self.answered).into_bytes();
+        Some(Ok(HttpResponse::new(
+            StatusCode::OK,
+            req.url().clone(),
+            body,
+        )))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Tag(String);
+
+impl Middleware for Tag {
+    fn handle_request(&mut self, req: &mut Request) -> Option<nightfly::Result<HttpResponse>> {
+        req.headers_mut().insert("x-tag", self.0.parse().unwrap());
+        None
+    }
+
+    fn handle_response(
+        &mut self,
+        req: &Request,
+        res: nightfly::Result<HttpResponse>,
+    ) -> nightfly::Result<HttpResponse> {
+        let mut res = res?;
+        let tag = req.headers()["x-tag"].clone();
+        res.headers_mut().insert("x-tag", tag);
+        Ok(res)
+    }
+}
+
+#[lunatic::test]
+fn test_middleware() {
+    support::serve_raw(
+        MIDDLEWARE_ADDR,
+        b"HTTP/1.1 200 OK\r\n\
+          Connection: close\r\n\
+          Content-Length: 6\r\n\
+          \r\n\
+          server"
+            .to_vec(),
+    );
+
+    let client = nightfly::Client::builder()
+        .middleware(Tag("tagged".to_string()))
+        .middleware(Offline {
+            path: "/offline".to_string(),
+            answered: 0,
+        })
+        .build()
+        .unwrap();
+
+    let res = client
+        .get(format!("http://{}/", MIDDLEWARE_ADDR))
+        .send()
+        .unwrap();
+    assert_eq!(res.headers()["x-tag"], "tagged");
+    assert_eq!(res.text().unwrap(), "server");
+
+    // the state of the middleware is kept between requests
+    for answered in 1..3 {
+        let res = client
+            .get(format!("http://{}/offline", MIDDLEWARE_ADDR))
+            .send()
+            .unwrap();
+        assert_eq!(res.headers()["x-tag"], "tagged");
+        assert_eq!(res.text().unwrap(), format!("answered {}", answered));
+    }
+}
+
+#[lunatic::test]
+fn test_deserialized_builder_needs_middleware_again() {
+    let builder = nightfly::Client::builder().middleware(Tag("a".to_string()));
+    let json = serde_json::to_string(&builder).unwrap();
+    let builder: nightfly::ClientBuilder = serde_json::from_str(&json).unwrap();
+    let err = builder.build().unwrap_err();
+    assert!(err.is_builder());
+}
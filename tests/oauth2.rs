@@ -0,0 +1,176 @@
+#[macro_use]
+pub mod support;
+
+use http::HeaderMap;
+use nightfly::auth::OAuth2;
+use submillisecond::{response::Response as SubmsResponse, router, RequestContext};
+use support::RouterFn;
+
+fn json(body: &str) -> SubmsResponse {
+    SubmsResponse::builder()
+        .header("Content-Type", "application/json")
+        .body(body.as_bytes().to_vec())
+        .unwrap()
+}
+
+fn token(body: Vec<u8>, headers: HeaderMap) -> SubmsResponse {
+    // base64 of "id:secret"
+    assert_eq!(headers["authorization"], "Basic aWQ6c2VjcmV0");
+    match String::from_utf8(body).unwrap().as_str() {
+        "grant_type=refresh_token&refresh_token=r1" => json(
+            r#"{"access_token":"old","token_type":"Bearer","expires_in":3600,"refresh_token":"r2"}"#,
+        ),
+        "grant_type=refresh_token&refresh_token=r2" => {
+            json(r#"{"access_token":"new","token_type":"Bearer","expires_in":3600}"#)
+        }
+        "grant_type=client_credentials&scope=read+write" => {
+            json(r#"{"access_token":"cc-token","token_type":"bearer"}"#)
+        }
+        _ => SubmsResponse::builder()
+            .status(400)
+            .body(br#"{"error":"invalid_grant"}"#.to_vec())
+            .unwrap(),
+    }
+}
+
+fn resource(req: RequestContext) -> SubmsResponse {
+    let authorization = req
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    match authorization.strip_prefix("Bearer ") {
+        Some(token) if token != "old" => SubmsResponse::builder()
+            .body(token.as_bytes().to_vec())
+            .unwrap(),
+        _ => SubmsResponse::builder()
+            .status(401)
+            .body(Default::default())
+            .unwrap(),
+    }
+}
+
+fn authorization(req: RequestContext) -> SubmsResponse {
+    let authorization = req
+        .headers()
+        .get("authorization")
+        .map(|value| value.as_bytes().to_vec())
+        .unwrap_or_default();
+    SubmsResponse::builder().body(authorization).unwrap()
+}
+
+static ROUTER: RouterFn = router! {
+    POST "/token" => token
+    GET "/resource" => resource
+    GET "/authorization" => authorization
+};
+
+static ADDR: &'static str = "0.0.0.0:3024";
+
+wrap_server!(server, ROUTER, ADDR);
+
+#[lunatic::test]
+fn test_oauth2_renews_rejected_token() {
+    let _ = server::ensure_server();
+    let oauth2 = OAuth2::refresh_token(format!("http://{}/token", ADDR), "id", "secret", "r1")
+        .allow_http(true);
+    let client = nightfly::Client::builder().oauth2(oauth2).build().unwrap();
+    let url = format!("http://{}/resource", ADDR);
+
+    // "old" is rejected, so the client renews it with the rotated refresh
+    // token and sends the request again
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status(), nightfly::StatusCode::OK);
+    assert_eq!(res.text().unwrap(), "new");
+
+    // the renewed token is cached
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.text().unwrap(), "new");
+}
+
+#[lunatic::test]
+fn test_oauth2_client_credentials() {
+    let _ = server::ensure_server();
+    let oauth2 = OAuth2::client_credentials(format!("http://{}/token", ADDR), "id", "secret")
+        .scope("read")
+        .scope("write")
+        .allow_http(true);
+    let client = nightfly::Client::builder().oauth2(oauth2).build().unwrap();
+    let url = format!("http://{}/resource", ADDR);
+
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.text().unwrap(), "cc-token");
+
+    // a request with its own `Authorization` header keeps it
+    let res = client
+        .get(&url)
+        .header("Authorization", "Bearer own")
+        .send()
+        .unwrap();
+    assert_eq!(res.text().unwrap(), "own");
+}
+
+#[lunatic::test]
+fn test_oauth2_keeps_default_authorization() {
+    let _ = server::ensure_server();
+    let oauth2 = OAuth2::refresh_token(format!("http://{}/token", ADDR), "id", "secret", "r1")
+        .allow_http(true);
+    let mut headers = HeaderMap::new();
+    headers.insert("authorization", "Bearer old".parse().unwrap());
+    let client = nightfly::Client::builder()
+        .default_headers(headers)
+        .oauth2(oauth2)
+        .build()
+        .unwrap();
+
+    // the rejected header isn't the token of the client, so nothing is
+    // renewed and the request isn't sent again
+    let res = client
+        .get(format!("http://{}/resource", ADDR))
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), nightfly::StatusCode::UNAUTHORIZED);
+    assert_eq!(client.metrics().responses_with_class("4xx"), 1);
+}
+
+#[lunatic::test]
+fn test_oauth2_bad_token_url() {
+    let oauth2 = OAuth2::client_credentials("not a url", "id", "secret");
+    let err = nightfly::Client::builder()
+        .oauth2(oauth2)
+        .build()
+        .unwrap_err();
+    assert!(err.is_builder());
+}
+
+#[lunatic::test]
+fn test_oauth2_token_scoped_to_origin() {
+    let _ = server::ensure_server();
+    let oauth2 = OAuth2::client_credentials(format!("http://{}/token", ADDR), "id", "secret")
+        .allow_http(true);
+    let client = nightfly::Client::builder().oauth2(oauth2).build().unwrap();
+
+    let res = client
+        .get(format!("http://{}/authorization", ADDR))
+        .send()
+        .unwrap();
+    assert_eq!(res.text().unwrap(), "Bearer cc-token");
+
+    // the same server under another host is another origin
+    let res = client
+        .get("http://127.0.0.1:3024/authorization")
+        .send()
+        .unwrap();
+    assert_eq!(res.text().unwrap(), "");
+}
+
+#[lunatic::test]
+fn test_oauth2_refuses_http() {
+    let oauth2 = OAuth2::client_credentials(format!("http://{}/token", ADDR), "id", "secret");
+    let err = nightfly::Client::builder()
+        .oauth2(oauth2)
+        .build()
+        .unwrap_err();
+    assert!(err.is_builder());
+}
@@ -0,0 +1,100 @@
+#[macro_use]
+pub mod support;
+
+use std::time::Duration;
+
+use lunatic::{Mailbox, Process};
+use submillisecond::{response::Response as SubmsResponse, router};
+use support::RouterFn;
+
+fn slow_for(test: &str) -> SubmsResponse {
+    // tell the test that the request holding the slot reached the server
+    if let Some(test) = Process::<()>::lookup(&test) {
+        test.send(());
+    }
+    lunatic::sleep(Duration::from_millis(500));
+    SubmsResponse::default()
+}
+
+fn slow() -> SubmsResponse {
+    slow_for("__queue_test__")
+}
+
+fn slow_wait() -> SubmsResponse {
+    slow_for("__queue_wait_test__")
+}
+
+static ROUTER: RouterFn = router! {
+    GET "/slow" => slow
+    GET "/slow-wait" => slow_wait
+};
+
+static ADDR: &'static str = "0.0.0.0:3019";
+
+wrap_server!(server, ROUTER, ADDR);
+
+#[lunatic::test]
+fn test_requests_wait_for_a_free_slot() {
+    let _ = server::ensure_server();
+    // SAFETY: the test process only receives `()` from the `/slow` handler
+    let mailbox = unsafe { Mailbox::<()>::new() };
+    mailbox.this().register(&"__queue_test__");
+
+    let client = nightfly::Client::builder()
+        .max_connections_per_host(1)
+        .queue_timeout(Duration::from_millis(100))
+        .build()
+        .unwrap();
+    let url = format!("http://{}/slow", ADDR);
+
+    // occupy the only slot from another process
+    Process::spawn_link(
+        (client.clone(), url.clone()),
+        |(client, url), _: Mailbox<()>| {
+            client.get(&url).send().unwrap();
+        },
+    );
+    mailbox
+        .receive_timeout(Duration::from_secs(5))
+        .expect("request should reach the server");
+
+    let status = client.queue_status(&url).unwrap();
+    assert_eq!(status.in_flight, 1);
+    assert_eq!(status.queued, 0);
+
+    let err = client.get(&url).send().unwrap_err();
+    assert!(err.is_queue_timeout());
+    assert!(err.is_timeout());
+    assert_eq!(client.queue_status(&url).unwrap().queued, 0);
+    assert_eq!(client.metrics().errors["queue_timeout"], 1);
+}
+
+#[lunatic::test]
+fn test_queued_request_is_sent_once_the_slot_is_free() {
+    let _ = server::ensure_server();
+    // SAFETY: the test process only receives `()` from the `/slow-wait` handler
+    let mailbox = unsafe { Mailbox::<()>::new() };
+    mailbox.this().register(&"__queue_wait_test__");
+
+    let client = nightfly::Client::builder()
+        .max_in_flight(1)
+        .build()
+        .unwrap();
+    let url = format!("http://{}/slow-wait", ADDR);
+
+    Process::spawn_link(
+        (client.clone(), url.clone()),
+        |(client, url), _: Mailbox<()>| {
+            client.get(&url).send().unwrap();
+        },
+    );
+    mailbox
+        .receive_timeout(Duration::from_secs(5))
+        .expect("request should reach the server");
+
+    // the admission process tells the request when the first one is done
+    let res = client.get(&url).send().unwrap();
+    assert!(res.status().is_success());
+    assert!(res.queue_wait() >= Duration::from_millis(100));
+    assert_eq!(client.queue_status(&url).unwrap().in_flight, 0);
+}
@@ -0,0 +1,52 @@
+#[macro_use]
+pub mod support;
+
+use std::time::{Duration, Instant};
+
+use nightfly::rate_limit::{Mode, Policy, Rate};
+
+static LIMITED_ADDR: &'static str = "0.0.0.0:3018";
+
+#[lunatic::test]
+fn test_rate_limit_per_host_and_key() {
+    support::serve_raw(
+        LIMITED_ADDR,
+        b"HTTP/1.1 200 OK\r\n\
+          Connection: close\r\n\
+          Content-Length: 2\r\n\
+          \r\n\
+          ok"
+        .to_vec(),
+    );
+
+    let client = nightfly::Client::builder()
+        .rate_limit(
+            Policy::default()
+                .per_key(Rate::new(1, Duration::from_millis(200)))
+                .mode(Mode::Reject),
+        )
+        .build()
+        .unwrap();
+    let url = format!("http://{}/", LIMITED_ADDR);
+
+    // requests without a key are not limited
+    client.get(&url).send().unwrap();
+    client.get(&url).send().unwrap();
+
+    client.get(&url).rate_limit_key("alice").send().unwrap();
+    let err = client.get(&url).rate_limit_key("alice").send().unwrap_err();
+    assert!(err.is_rate_limited());
+    assert_eq!(client.metrics().errors["rate_limited"], 1);
+    client.get(&url).rate_limit_key("bob").send().unwrap();
+
+    // waiting in line instead of failing
+    let start = Instant::now();
+    let res = client
+        .get(&url)
+        .rate_limit_key("alice")
+        .rate_limit_mode(Mode::Queue(Duration::from_secs(1)))
+        .send()
+        .unwrap();
+    assert_eq!(res.text().unwrap(), "ok");
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
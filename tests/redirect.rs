@@ -1,6 +1,7 @@
 #[macro_use]
 pub mod support;
 
+use serde::{Deserialize, Serialize};
 use submillisecond::{response::Response as SubmsResponse, router, RequestContext};
 use support::RouterFn;
 
@@ -34,6 +35,14 @@ fn handle_308() -> SubmsResponse {
     redirect(308)
 }
 
+fn handle_307_echo() -> SubmsResponse {
+    http::Response::builder()
+        .status(307)
+        .header("location", "/echo")
+        .body(Default::default())
+        .unwrap()
+}
+
 fn dst(body: Vec<u8>) -> SubmsResponse {
     SubmsResponse::builder()
         .header("server", "test-dst")
@@ -131,6 +140,8 @@ static ROUTER: RouterFn = router! {
     POST "/308" => handle_308
     GET "/307" => handle_307
     GET "/308" => handle_308
+    POST "/307-echo" => handle_307_echo
+    POST "/echo" => dst
     GET "/dst" => dst_get
     POST "/dst" => dst_post
     GET "/sensitive" => mid_server
@@ -214,6 +225,57 @@ fn test_redirect_307_and_308_tries_to_post_again() {
     }
 }
 
+#[lunatic::test]
+fn test_redirect_307_keeps_the_body() {
+    let _ = server::ensure_server();
+
+    let res = nightfly::Client::new()
+        .post(&format!("http://{}/307-echo", ADDR))
+        .header("content-type", "text/plain")
+        .body("Hello")
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), nightfly::StatusCode::OK);
+    assert_eq!(res.body, b"Hello".to_vec());
+    let history = res.redirect_history();
+    assert_eq!(history.len(), 1);
+    assert_eq!(
+        history[0].status(),
+        nightfly::StatusCode::TEMPORARY_REDIRECT
+    );
+    assert!(history[0].changes().is_empty());
+}
+
+#[lunatic::test]
+fn test_redirect_303_records_dropped_body() {
+    use nightfly::redirect::RedirectChange;
+
+    let _ = server::ensure_server();
+
+    let url = format!("http://{}/303", ADDR);
+    let res = nightfly::Client::new()
+        .post(&url)
+        .header("content-type", "text/plain")
+        .body("Hello")
+        .send()
+        .unwrap();
+    assert_eq!(res.body, b"GET".to_vec());
+    let history = res.redirect_history();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].url().as_str(), url);
+    assert_eq!(
+        history[0].changes(),
+        &[
+            RedirectChange::MethodRewritten {
+                from: "POST".to_string(),
+                to: "GET".to_string(),
+            },
+            RedirectChange::BodyDropped,
+            RedirectChange::HeaderRemoved("content-type".to_string()),
+        ]
+    );
+}
+
 #[lunatic::test]
 fn test_redirect_removes_sensitive_headers() {
     let _ = server::ensure_server();
@@ -259,6 +321,53 @@ fn test_redirect_policy_can_stop_redirects_without_an_error() {
     assert_eq!(res.status(), nightfly::StatusCode::FOUND);
 }
 
+#[derive(Serialize, Deserialize)]
+struct StopAtDont;
+
+impl nightfly::redirect::Rule for StopAtDont {
+    fn check(&mut self, attempt: nightfly::redirect::Attempt) -> nightfly::redirect::Action {
+        if attempt.url().path() == "/dont" {
+            attempt.stop()
+        } else {
+            attempt.follow()
+        }
+    }
+}
+
+#[lunatic::test]
+fn test_redirect_policy_named_and_same_origin() {
+    let _ = server::ensure_server();
+    let _ = end_server::ensure_server();
+
+    let url = format!("http://{}/no-redirect", ADDR);
+    let res = nightfly::Client::builder()
+        .register_redirect_policy("stop-at-dont", StopAtDont)
+        .redirect(nightfly::redirect::Policy::default().named("stop-at-dont"))
+        .build()
+        .unwrap()
+        .get(&url)
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), nightfly::StatusCode::FOUND);
+
+    // `/sensitive` redirects to another port
+    let err = nightfly::Client::builder()
+        .redirect(nightfly::redirect::Policy::default().same_origin())
+        .build()
+        .unwrap()
+        .get(&format!("http://{}/sensitive", ADDR))
+        .header(nightfly::header::COOKIE, "foo=bar")
+        .send()
+        .unwrap_err();
+    assert!(err.is_redirect());
+
+    let err = nightfly::Client::builder()
+        .redirect(nightfly::redirect::Policy::default().named("unknown"))
+        .build()
+        .unwrap_err();
+    assert!(err.is_builder());
+}
+
 #[lunatic::test]
 fn test_referer_is_not_set_if_disabled() {
     let _ = server::ensure_server();
@@ -299,6 +408,32 @@ fn test_redirect_302_with_set_cookies() {
     assert_eq!(res.status(), nightfly::StatusCode::OK);
 }
 
+#[lunatic::test]
+fn test_redirect_history_records_hop_headers() {
+    let _ = server::ensure_server();
+    let url = format!("http://{}/302-cookie", ADDR);
+
+    let client = nightfly::ClientBuilder::new().build().unwrap();
+    let res = client.get(&url).send().unwrap();
+
+    let history = res.redirect_history();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].url().as_str(), url);
+    assert_eq!(history[0].status(), nightfly::StatusCode::FOUND);
+    let headers = history[0].headers();
+    assert_eq!(headers["location"], "/dst");
+    assert_eq!(headers["set-cookie"], "key=value");
+
+    let client = nightfly::ClientBuilder::new()
+        .redirect_history_headers(["Location"])
+        .build()
+        .unwrap();
+    let res = client.get(&url).send().unwrap();
+    let headers = res.redirect_history()[0].headers();
+    assert!(headers.contains_key("location"));
+    assert!(!headers.contains_key("set-cookie"));
+}
+
 // #[cfg(feature = "__rustls")]
 // #[lunatic::test]
 // #[ignore = "Needs TLS support in the test server"]
@@ -0,0 +1,82 @@
+#[macro_use]
+pub mod support;
+
+use std::time::Duration;
+
+use nightfly::retry::Policy;
+
+static UNAVAILABLE_ADDR: &'static str = "0.0.0.0:3014";
+static CLOSING_ADDR: &'static str = "0.0.0.0:3031";
+
+fn unavailable() -> Vec<u8> {
+    b"HTTP/1.1 503 Service Unavailable\r\n\
+      Retry-After: 0\r\n\
+      Connection: close\r\n\
+      Content-Length: 0\r\n\
+      \r\n"
+        .to_vec()
+}
+
+#[lunatic::test]
+fn test_retry_until_max_attempts() {
+    support::serve_raw(UNAVAILABLE_ADDR, unavailable());
+
+    let client = nightfly::Client::builder()
+        .retry(Policy::default().max_attempts(3))
+        .build()
+        .unwrap();
+    let res = client
+        .get(format!("http://{}/", UNAVAILABLE_ADDR))
+        .send()
+        .unwrap();
+
+    assert_eq!(res.status(), nightfly::StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.attempts(), 3);
+
+    // non-idempotent requests are not retried unless allowed
+    let res = client
+        .post(format!("http://{}/", UNAVAILABLE_ADDR))
+        .send()
+        .unwrap();
+    assert_eq!(res.attempts(), 1);
+
+    // the request policy overrides the client policy
+    let res = client
+        .get(format!("http://{}/", UNAVAILABLE_ADDR))
+        .retry(Policy::never())
+        .send()
+        .unwrap();
+    assert_eq!(res.attempts(), 1);
+}
+
+#[lunatic::test]
+fn test_retry_connect_errors() {
+    // nothing listens on this port
+    let client = nightfly::Client::builder()
+        .retry(Policy::default().backoff(Duration::from_millis(1), Duration::from_millis(1)))
+        .build()
+        .unwrap();
+    let err = client.get("http://127.0.0.1:3015/").send().unwrap_err();
+
+    assert!(err.is_connect());
+    // every attempt fails on its own
+    assert_eq!(client.metrics().errors["connect"], 3);
+}
+
+#[lunatic::test]
+fn test_no_retry_after_the_request_was_sent() {
+    // the server closes the connection without answering
+    support::serve_raw(CLOSING_ADDR, vec![]);
+
+    let client = nightfly::Client::builder()
+        .retry(Policy::default().backoff(Duration::from_millis(1), Duration::from_millis(1)))
+        .build()
+        .unwrap();
+    let err = client
+        .get(format!("http://{}/", CLOSING_ADDR))
+        .send()
+        .unwrap_err();
+
+    assert!(err.is_request());
+    assert_eq!(client.metrics().errors["request"], 1);
+}
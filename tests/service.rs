@@ -0,0 +1,119 @@
+#[macro_use]
+pub mod support;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::ptr;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::time::Duration;
+
+use lunatic::net::TcpListener;
+use lunatic::{Mailbox, Process};
+use tower_service::Service;
+
+static SERVICE_ADDR: &'static str = "0.0.0.0:3021";
+static SLOW_ADDR: &'static str = "0.0.0.0:3030";
+
+fn noop_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+    unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) }
+}
+
+fn ready<F: Future + Unpin>(mut fut: F) -> F::Output {
+    let waker = noop_waker();
+    match Pin::new(&mut fut).poll(&mut Context::from_waker(&waker)) {
+        Poll::Ready(output) => output,
+        Poll::Pending => panic!("future is not ready"),
+    }
+}
+
+#[lunatic::test]
+fn test_client_as_service() {
+    support::serve_raw(
+        SERVICE_ADDR,
+        b"HTTP/1.1 200 OK\r\n\
+          Connection: close\r\n\
+          Content-Length: 7\r\n\
+          \r\n\
+          service"
+            .to_vec(),
+    );
+
+    let mut client = nightfly::Client::builder()
+        .max_in_flight(4)
+        .build()
+        .unwrap();
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let url = format!("http://{}/", SERVICE_ADDR);
+
+    assert!(matches!(
+        Service::<nightfly::Request>::poll_ready(&mut client, &mut cx),
+        Poll::Ready(Ok(()))
+    ));
+    let req = client.get(&url).build().unwrap();
+    let res = ready(client.call(req)).unwrap();
+    assert_eq!(res.text().unwrap(), "service");
+
+    let req = http::Request::get(&url).body(()).unwrap();
+    let res = ready(client.call(req)).unwrap();
+    assert_eq!(res.status(), nightfly::StatusCode::OK);
+}
+
+#[lunatic::test]
+fn test_client_as_service_is_not_ready_at_max_in_flight() {
+    // SAFETY: the test process only receives `()` from the server below
+    let mailbox = unsafe { Mailbox::<()>::new() };
+    let listener = TcpListener::bind(SLOW_ADDR).unwrap();
+    Process::spawn_link(
+        (listener, mailbox.this()),
+        |(listener, test), _: Mailbox<()>| {
+            use std::io::{Read, Write};
+
+            while let Ok((mut stream, _)) = listener.accept() {
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf);
+                // hold the request until the test checked the client
+                test.send(());
+                lunatic::sleep(Duration::from_millis(300));
+                let _ = stream.write_all(
+                    b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+                );
+            }
+        },
+    );
+
+    let mut client = nightfly::Client::builder()
+        .max_in_flight(1)
+        .build()
+        .unwrap();
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let url = format!("http://{}/", SLOW_ADDR);
+
+    Process::spawn_link(
+        (client.clone(), url.clone()),
+        |(client, url), _: Mailbox<()>| {
+            client.get(&url).send().unwrap();
+        },
+    );
+    mailbox
+        .receive_timeout(Duration::from_secs(5))
+        .expect("request should reach the server");
+    assert!(Service::<nightfly::Request>::poll_ready(&mut client, &mut cx).is_pending());
+
+    // ready again once the request in flight is done
+    let mut ready = false;
+    for _ in 0..100 {
+        if Service::<nightfly::Request>::poll_ready(&mut client, &mut cx).is_ready() {
+            ready = true;
+            break;
+        }
+        lunatic::sleep(Duration::from_millis(10));
+    }
+    assert!(ready);
+}
@@ -0,0 +1,154 @@
+#[macro_use]
+pub mod support;
+
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::{write::GzEncoder, Compression};
+
+use hmac::{Hmac, Mac};
+use http::HeaderMap;
+use nightfly::auth::{MessageSigner, SignatureKey, SignatureVerifier};
+use sha2::{Digest, Sha256};
+use submillisecond::{response::Response as SubmsResponse, router};
+use support::RouterFn;
+
+fn content_digest(body: &[u8]) -> String {
+    format!("sha-256=:{}:", base64::encode(Sha256::digest(body)))
+}
+
+fn webhook(body: Vec<u8>, headers: HeaderMap) -> SubmsResponse {
+    let input = headers["signature-input"].to_str().unwrap();
+    assert!(input.starts_with("sig1=(\"@method\" \"@authority\" \"content-digest\");created="));
+    assert!(input.ends_with(";keyid=\"client\""));
+    assert!(headers["signature"].to_str().unwrap().starts_with("sig1=:"));
+    assert_eq!(headers["content-digest"], content_digest(&body).as_str());
+
+    signed(b"ok".to_vec()).body(b"ok".to_vec()).unwrap()
+}
+
+fn webhook_length(body: Vec<u8>, headers: HeaderMap) -> SubmsResponse {
+    let input = headers["signature-input"].to_str().unwrap();
+    let params = input.strip_prefix("sig1=").unwrap();
+    assert!(params.starts_with("(\"content-length\");created="));
+    assert_eq!(headers["content-length"], body.len().to_string().as_str());
+
+    let base = format!(
+        "\"content-length\": {}\n\"@signature-params\": {}",
+        body.len(),
+        params
+    );
+    let mut mac = Hmac::<Sha256>::new_from_slice(b"client-secret").unwrap();
+    mac.update(base.as_bytes());
+    let signature = format!("sig1=:{}:", base64::encode(mac.finalize().into_bytes()));
+    assert_eq!(headers["signature"], signature.as_str());
+
+    SubmsResponse::builder().body(b"ok".to_vec()).unwrap()
+}
+
+/// Starts a response with a `Content-Digest` of `body`, signed the way a
+/// partner would.
+fn signed(body: Vec<u8>) -> http::response::Builder {
+    let digest = content_digest(&body);
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let params = format!(
+        "(\"@status\" \"content-digest\");created={};keyid=\"server\"",
+        created
+    );
+    let base = format!(
+        "\"@status\": 200\n\"content-digest\": {}\n\"@signature-params\": {}",
+        digest, params
+    );
+    let mut mac = Hmac::<Sha256>::new_from_slice(b"server-secret").unwrap();
+    mac.update(base.as_bytes());
+    let signature = base64::encode(mac.finalize().into_bytes());
+    SubmsResponse::builder()
+        .header("Content-Digest", digest)
+        .header("Signature-Input", format!("sig1={}", params))
+        .header("Signature", format!("sig1=:{}:", signature))
+}
+
+fn gzipped() -> SubmsResponse {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"compressed ok").unwrap();
+    let body = encoder.finish().unwrap();
+    // the digest is over the content-coded body
+    signed(body.clone())
+        .header("Content-Encoding", "gzip")
+        .body(body)
+        .unwrap()
+}
+
+fn unsigned() -> SubmsResponse {
+    SubmsResponse::builder().body(b"ok".to_vec()).unwrap()
+}
+
+static ROUTER: RouterFn = router! {
+    POST "/webhook" => webhook
+    POST "/webhook-length" => webhook_length
+    GET "/unsigned" => unsigned
+    GET "/gzip" => gzipped
+};
+
+static ADDR: &'static str = "0.0.0.0:3026";
+
+wrap_server!(server, ROUTER, ADDR);
+
+fn verifier() -> SignatureVerifier {
+    SignatureVerifier::new(SignatureKey::hmac_sha256("server", "server-secret"))
+        .require_component("@status")
+        .require_component("content-digest")
+}
+
+#[lunatic::test]
+fn test_signed_request_and_response() {
+    let _ = server::ensure_server();
+    let signer = MessageSigner::new(SignatureKey::hmac_sha256("client", "client-secret"))
+        .component("@method")
+        .component("@authority")
+        .component("content-digest");
+    let res = nightfly::Client::new()
+        .post(format!("http://{}/webhook", ADDR))
+        .body("{\"event\":\"ping\"}")
+        .message_signature(signer)
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), nightfly::StatusCode::OK);
+    res.verify_signature(&verifier()).unwrap();
+
+    let other = SignatureVerifier::new(SignatureKey::hmac_sha256("server", "wrong"));
+    assert!(res.verify_signature(&other).unwrap_err().is_signature());
+}
+
+#[lunatic::test]
+fn test_unsigned_response_is_rejected() {
+    let _ = server::ensure_server();
+    let res = nightfly::get(format!("http://{}/unsigned", ADDR)).unwrap();
+    let err = res.verify_signature(&verifier()).unwrap_err();
+    assert!(err.is_signature());
+}
+
+#[lunatic::test]
+fn test_content_digest_of_compressed_response() {
+    let _ = server::ensure_server();
+    let res = nightfly::get(format!("http://{}/gzip", ADDR)).unwrap();
+    assert_eq!(res.body, b"compressed ok");
+    res.verify_signature(&verifier()).unwrap();
+}
+
+#[lunatic::test]
+fn test_signed_content_length() {
+    let _ = server::ensure_server();
+    let signer = MessageSigner::new(SignatureKey::hmac_sha256("client", "client-secret"))
+        .component("content-length");
+    let res = nightfly::Client::new()
+        .post(format!("http://{}/webhook-length", ADDR))
+        .body("{\"event\":\"ping\"}")
+        .message_signature(signer)
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), nightfly::StatusCode::OK);
+}
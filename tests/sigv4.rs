@@ -0,0 +1,64 @@
+#[macro_use]
+pub mod support;
+
+use http::{HeaderMap, HeaderValue};
+use nightfly::auth::SigV4;
+use submillisecond::{response::Response as SubmsResponse, router, RequestContext};
+use support::RouterFn;
+
+fn object(req: RequestContext) -> SubmsResponse {
+    let headers = req.headers();
+    assert!(headers.contains_key("x-amz-date"));
+    assert_eq!(headers["x-amz-security-token"], "token");
+    let authorization = headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    SubmsResponse::builder()
+        .body(authorization.into_bytes())
+        .unwrap()
+}
+
+fn redirect() -> SubmsResponse {
+    SubmsResponse::builder()
+        .status(302)
+        .header("Location", "/object")
+        .body(Default::default())
+        .unwrap()
+}
+
+static ROUTER: RouterFn = router! {
+    GET "/object" => object
+    GET "/redirect" => redirect
+};
+
+static ADDR: &'static str = "0.0.0.0:3025";
+
+wrap_server!(server, ROUTER, ADDR);
+
+#[lunatic::test]
+fn test_sigv4_signs_default_headers_and_redirects() {
+    let _ = server::ensure_server();
+    let mut default_headers = HeaderMap::new();
+    default_headers.insert("x-tenant", HeaderValue::from_static("acme"));
+    let client = nightfly::Client::builder()
+        .default_headers(default_headers)
+        .build()
+        .unwrap();
+    let sigv4 = SigV4::new("AKIDEXAMPLE", "secret", "us-east-1", "s3").session_token("token");
+
+    for path in ["object", "redirect"] {
+        let res = client
+            .get(format!("http://{}/{}", ADDR, path))
+            .aws_sigv4(sigv4.clone())
+            .send()
+            .unwrap();
+        assert_eq!(res.status(), nightfly::StatusCode::OK);
+        let authorization = res.text().unwrap();
+        assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+        assert!(authorization.contains("/us-east-1/s3/aws4_request, "));
+        // the default headers are merged in before signing
+        assert!(authorization.contains(";x-amz-date;x-amz-security-token;x-tenant, "));
+    }
+}
@@ -100,3 +100,32 @@ macro_rules! wrap_server {
 
 pub type RouterFn =
     fn() -> fn(req: ::submillisecond::RequestContext) -> ::submillisecond::response::Response;
+
+/// Spawns a bare TCP server on `addr` that answers every connection with the
+/// given raw bytes, used for responses that `submillisecond` can't produce.
+#[allow(unused)]
+pub fn serve_raw(addr: &'static str, response: Vec<u8>) {
+    use lunatic::net::TcpListener;
+    use lunatic::{Mailbox, Process};
+
+    let listener = TcpListener::bind(addr).expect("should bind raw server");
+    Process::spawn_link(
+        (listener, response),
+        |(listener, response), _: Mailbox<()>| {
+            use std::io::{Read, Write};
+
+            while let Ok((mut stream, _)) = listener.accept() {
+                // read until the end of the request head
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let _ = stream.write_all(&response);
+            }
+        },
+    );
+}
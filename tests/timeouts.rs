@@ -57,6 +57,29 @@ fn request_timeout() {
     assert_eq!(err.url().map(|u| u.as_str()), Some(url.as_str()));
 }
 
+#[lunatic::test]
+fn late_response_counts_as_timeout() {
+    let _ = server::ensure_server();
+
+    let client = nightfly::Client::builder().build().unwrap();
+
+    let url = format!("http://{}/slow", ADDR);
+
+    let err = client
+        .get(&url)
+        .timeout(Duration::from_millis(500))
+        .send()
+        .unwrap_err();
+    assert!(err.is_timeout());
+
+    // the client process answers once it's done with the late response
+    let metrics = client.metrics();
+    assert_eq!(metrics.errors["timeout"], 1);
+    assert_eq!(metrics.total_errors(), 1);
+    assert_eq!(metrics.responses_with_class("2xx"), 0);
+    assert_eq!(metrics.latency.count, 0);
+}
+
 // #[lunatic::test]
 // fn connect_timeout() {
 //     let client = nightfly::Client::builder()